@@ -0,0 +1,101 @@
+// Compares lexing an in-memory input into owned tokens (`Lexer::get_next_token`) against borrowed
+// tokens (`Lexer::tokenize_borrowed`): heap allocations, lexing time, and that both produce the
+// same token text.
+//
+// Run with `cargo bench --bench borrowed_tokens`.
+
+use log_surgeon::error_handling::Result;
+use log_surgeon::lexer::{Lexer, StringStream};
+use log_surgeon::parser::SchemaConfig;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const NUM_LINES: usize = 100_000;
+
+// Counts the heap allocations made by the process
+struct CountingAllocator;
+
+static NUM_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        NUM_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const SCHEMA: &str = "timestamp:\n  - '\\d{4}\\-\\d{2}\\-\\d{2} \\d{2}:\\d{2}:\\d{2}'\n\
+                      delimiters: \" \\t:,=\"\n\
+                      variables:\n  int: '\\-{0,1}\\d+'\n  level: 'INFO|WARN|ERROR|DEBUG'\n";
+
+fn build_input() -> String {
+    let levels = ["INFO", "WARN", "ERROR", "DEBUG"];
+    let mut input = String::new();
+    for i in 0..NUM_LINES {
+        input += format!(
+            "2015-01-31 15:50:{:02} {} request /api/items id={} took {} ms\n",
+            i % 60,
+            levels[i % levels.len()],
+            i,
+            i % 1000
+        )
+        .as_str();
+    }
+    input
+}
+
+// Returns the total length of the token text, the number of allocations, and the elapsed time
+fn lex_owned(lexer: &mut Lexer, input: &str) -> Result<(usize, usize, Duration)> {
+    let num_allocations = NUM_ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    lexer.set_input_stream(Box::new(StringStream::new(input)));
+    let mut total_len = 0;
+    while let Some(token) = lexer.get_next_token()? {
+        total_len += token.get_val().len();
+    }
+    Ok((
+        total_len,
+        NUM_ALLOCATIONS.load(Ordering::Relaxed) - num_allocations,
+        start.elapsed(),
+    ))
+}
+
+fn lex_borrowed(lexer: &mut Lexer, input: &str) -> Result<(usize, usize, Duration)> {
+    let num_allocations = NUM_ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut total_len = 0;
+    for token in lexer.tokenize_borrowed(input) {
+        total_len += token?.get_val().len();
+    }
+    Ok((
+        total_len,
+        NUM_ALLOCATIONS.load(Ordering::Relaxed) - num_allocations,
+        start.elapsed(),
+    ))
+}
+
+fn main() -> Result<()> {
+    let mut lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(SCHEMA)?))?;
+    let input = build_input();
+
+    let (owned_len, owned_allocations, owned_duration) = lex_owned(&mut lexer, input.as_str())?;
+    let (borrowed_len, borrowed_allocations, borrowed_duration) =
+        lex_borrowed(&mut lexer, input.as_str())?;
+    assert_eq!(owned_len, borrowed_len);
+
+    println!(
+        "{} lines: owned tokens {} allocations in {:?}, borrowed tokens {} allocations in {:?}",
+        NUM_LINES, owned_allocations, owned_duration, borrowed_allocations, borrowed_duration
+    );
+    Ok(())
+}
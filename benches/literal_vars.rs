@@ -0,0 +1,120 @@
+// Compares literal variables (matched through the literal fast path) against the same variables
+// written as regexes (matched through the variable DFA): lexing throughput, lexer construction
+// time, and that both produce identical tokens.
+//
+// Run with `cargo bench --bench literal_vars`.
+
+use log_surgeon::error_handling::Result;
+use log_surgeon::lexer::{Lexer, StringStream, Token};
+use log_surgeon::parser::SchemaConfig;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const NUM_LINES: usize = 100_000;
+const NUM_ITERATIONS: usize = 5;
+const NUM_GENERATED_KEYWORDS: usize = 300;
+
+fn build_schema_with_generated_keywords(literal_prefix: &str, literal_suffix: &str) -> String {
+    let mut schema = build_schema(literal_prefix, literal_suffix);
+    for i in 0..NUM_GENERATED_KEYWORDS {
+        schema += format!(
+            "  keyword{}: '{}KEYWORD{}{}'\n",
+            i, literal_prefix, i, literal_suffix
+        )
+        .as_str();
+    }
+    schema
+}
+
+fn time_lexer_construction(schema: &str) -> Result<Duration> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(schema)?);
+    let start = Instant::now();
+    Lexer::new(schema_config)?;
+    Ok(start.elapsed())
+}
+
+fn build_schema(literal_prefix: &str, literal_suffix: &str) -> String {
+    let mut schema = String::from(
+        "timestamp:\n  - '\\d{4}\\-\\d{2}\\-\\d{2} \\d{2}:\\d{2}:\\d{2}'\n\
+         delimiters: \" \\t:,=\"\n\
+         variables:\n  int: '\\-{0,1}\\d+'\n",
+    );
+    for keyword in [
+        "TRACE", "DEBUG", "INFO", "WARN", "ERROR", "FATAL", "GET", "POST", "PUT", "DELETE",
+    ] {
+        schema += format!(
+            "  {}: '{}{}{}'\n",
+            keyword.to_lowercase(),
+            literal_prefix,
+            keyword,
+            literal_suffix
+        )
+        .as_str();
+    }
+    schema
+}
+
+fn build_input() -> String {
+    let levels = ["INFO", "WARN", "ERROR", "DEBUG"];
+    let methods = ["GET", "POST", "PUT", "DELETE"];
+    let mut input = String::new();
+    for i in 0..NUM_LINES {
+        input += format!(
+            "2015-01-31 15:50:{:02} {} request {} /api/items id={} took {} ms\n",
+            i % 60,
+            levels[i % levels.len()],
+            methods[i % methods.len()],
+            i,
+            i % 1000
+        )
+        .as_str();
+    }
+    input
+}
+
+fn lex(schema: &str, input: &str) -> Result<(Vec<Token>, Duration)> {
+    let mut lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(schema)?))?;
+    let start = Instant::now();
+    lexer.set_input_stream(Box::new(StringStream::new(input)));
+    let mut tokens = Vec::new();
+    while let Some(token) = lexer.get_next_token()? {
+        tokens.push(token);
+    }
+    Ok((tokens, start.elapsed()))
+}
+
+fn main() -> Result<()> {
+    let literal_schema = build_schema("", "");
+    let regex_schema = build_schema("(", ")");
+    let input = build_input();
+
+    let mut literal_duration = Duration::ZERO;
+    let mut regex_duration = Duration::ZERO;
+    for _ in 0..NUM_ITERATIONS {
+        let (literal_tokens, duration) = lex(literal_schema.as_str(), input.as_str())?;
+        literal_duration += duration;
+        let (regex_tokens, duration) = lex(regex_schema.as_str(), input.as_str())?;
+        regex_duration += duration;
+        assert_eq!(
+            format!("{:?}", literal_tokens),
+            format!("{:?}", regex_tokens)
+        );
+    }
+
+    println!(
+        "{} lines x {} iterations: literal path {:?}/iter, regex path {:?}/iter",
+        NUM_LINES,
+        NUM_ITERATIONS,
+        literal_duration / NUM_ITERATIONS as u32,
+        regex_duration / NUM_ITERATIONS as u32
+    );
+
+    println!(
+        "Lexer construction with {} keywords: literal path {:?}, regex path {:?}",
+        NUM_GENERATED_KEYWORDS,
+        time_lexer_construction(build_schema_with_generated_keywords("", "").as_str())?,
+        time_lexer_construction(build_schema_with_generated_keywords("(", ")").as_str())?
+    );
+    Ok(())
+}
@@ -3,7 +3,7 @@ use log_surgeon::log_parser::LogEvent;
 use log_surgeon::log_parser::LogParser;
 use log_surgeon::parser::SchemaConfig;
 
-use std::rc::Rc;
+use std::sync::Arc;
 
 fn main() -> Result<()> {
     let project_root = env!("CARGO_MANIFEST_DIR");
@@ -12,7 +12,7 @@ fn main() -> Result<()> {
         .join("logs")
         .join("simple.log");
 
-    let parsed_schema = Rc::new(SchemaConfig::parse_from_file(
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
         schema_path.to_str().unwrap(),
     )?);
     let mut log_parser = LogParser::new(parsed_schema.clone())?;
@@ -13,6 +13,7 @@ enum Tag {
     End(usize),
 }
 
+#[derive(Clone)]
 struct Transition {
     from_state: State,
     symbol_onehot_encoding: u128,
@@ -45,12 +46,15 @@ impl Debug for Transition {
     }
 }
 
-pub(crate) struct DFA {
+#[derive(Clone)]
+pub struct DFA {
     start: State,
     accept: Vec<State>,
     states: Vec<State>,
     transitions: Vec<Vec<Option<Transition>>>, // from_state -> symbol[index in the length 128 vector] -> transition
     dfa_to_accepted_nfa_state_mapping: Vec<Option<(usize, crate::nfa::nfa::State)>>, // to determine which NFA gets matched
+    dfa_to_accepted_nfas: Vec<Vec<usize>>, // all the NFAs accepted in a DFA state, sorted by NFA index
+    dfa_to_alive_nfas: Vec<Vec<usize>>, // all the NFAs that can still match in a DFA state, sorted by NFA index
 }
 
 impl Debug for DFA {
@@ -103,6 +107,8 @@ impl DFA {
             states: _states,
             transitions: _transitions,
             dfa_to_accepted_nfa_state_mapping: Vec::new(),
+            dfa_to_accepted_nfas: Vec::new(),
+            dfa_to_alive_nfas: Vec::new(),
         }
     }
 
@@ -220,6 +226,10 @@ impl DFA {
             }
         }
 
+        // Sorted and deduplicated so that equal state sets map to the same DFA state, whatever
+        // order the transitions leading to them were visited in
+        closure.sort_by_key(|(idx, state)| (*idx, state.0));
+        closure.dedup();
         closure
     }
 }
@@ -236,17 +246,117 @@ impl DFA {
         }
     }
 
+    // Returns the index of the NFA accepted in the given state. If multiple NFAs are accepted, the
+    // one with the lowest index (the first declared) is returned.
     pub fn is_accept_state(&self, state: State) -> Option<usize> {
         self.get_accept_nfa_state(state.0)
     }
 
+    // Returns the indices of all the NFAs accepted in the given state, in increasing order
+    pub fn get_accepted_nfas(&self, state: State) -> &[usize] {
+        match self.dfa_to_accepted_nfas.get(state.0) {
+            Some(accepted_nfas) => accepted_nfas,
+            None => &[],
+        }
+    }
+
+    pub fn is_nfa_accepted(&self, state: State, nfa_idx: usize) -> bool {
+        match self.dfa_to_accepted_nfas.get(state.0) {
+            Some(accepted_nfas) => accepted_nfas.binary_search(&nfa_idx).is_ok(),
+            None => false,
+        }
+    }
+
+    // Returns whether the given NFA can still match (or has matched) in the given state
+    pub fn is_nfa_alive(&self, state: State, nfa_idx: usize) -> bool {
+        match self.dfa_to_alive_nfas.get(state.0) {
+            Some(alive_nfas) => alive_nfas.binary_search(&nfa_idx).is_ok(),
+            None => false,
+        }
+    }
+
     pub fn get_root(&self) -> State {
         self.start.clone()
     }
+
+    // Dumps the transition table as CSV with the header `from_state,symbol_range,to_state,accept_tag`.
+    // Each row covers a contiguous range of symbols (e.g. `a-z`, or `a` for a single symbol) going
+    // from one state to another; `accept_tag` is the index of the NFA accepted in the destination
+    // state, or empty if it isn't an accepting state. Rows are sorted by state, then by symbol.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("from_state,symbol_range,to_state,accept_tag\n");
+        for state in &self.states {
+            // Group the symbols by destination state, in order of the first symbol
+            let mut symbols_by_to_state: Vec<(State, u128)> = Vec::new();
+            for (symbol, transition) in self.transitions[state.0].iter().enumerate() {
+                let Some(transition) = transition else {
+                    continue;
+                };
+                match symbols_by_to_state
+                    .iter_mut()
+                    .find(|(to_state, _)| *to_state == transition.to_state)
+                {
+                    Some((_, symbols)) => *symbols |= 1 << symbol,
+                    None => symbols_by_to_state.push((transition.to_state.clone(), 1 << symbol)),
+                }
+            }
+
+            let mut rows: Vec<((u8, u8), &State)> = Vec::new();
+            for (to_state, symbols) in &symbols_by_to_state {
+                let transition = crate::nfa::nfa::Transition::new(
+                    crate::nfa::nfa::State(state.0),
+                    crate::nfa::nfa::State(to_state.0),
+                    *symbols,
+                    -1,
+                );
+                for range in transition.get_symbol_ranges() {
+                    rows.push((range, to_state));
+                }
+            }
+            rows.sort_by_key(|(range, _)| *range);
+
+            for ((begin, end), to_state) in rows {
+                let accept_tag = match self.get_accept_nfa_state(to_state.0) {
+                    Some(nfa_idx) => nfa_idx.to_string(),
+                    None => String::new(),
+                };
+                csv += format!(
+                    "{},{},{},{}\n",
+                    state.0,
+                    Self::format_csv_symbol_range(begin, end),
+                    to_state.0,
+                    accept_tag
+                )
+                .as_str();
+            }
+        }
+        csv
+    }
+
+    // Formats the symbol range for `to_csv`, quoting it if it contains a comma or a quote
+    fn format_csv_symbol_range(begin: u8, end: u8) -> String {
+        let mut range = (begin as char).escape_default().to_string();
+        if begin != end {
+            range += "-";
+            range += (end as char).escape_default().to_string().as_str();
+        }
+        if range.contains(',') || range.contains('"') {
+            return format!("\"{}\"", range.replace('"', "\"\""));
+        }
+        range
+    }
 }
 
 impl DFA {
     pub fn from_multiple_nfas(nfas: Vec<NFA>) -> DFA {
+        Self::from_multiple_nfas_with_state_mapping(nfas).0
+    }
+
+    // Builds the DFA like `from_multiple_nfas`, along with the subset of NFA states each DFA state
+    // stands for, as (NFA index, NFA state) pairs sorted by NFA index and state
+    pub fn from_multiple_nfas_with_state_mapping(
+        nfas: Vec<NFA>,
+    ) -> (DFA, HashMap<State, Vec<(usize, crate::nfa::nfa::State)>>) {
         // All of the nodes now have a pair of identifiers,
         // 1. the NFA index within the list of NFAs
         // 2. the NFA state index within the NFA
@@ -257,6 +367,8 @@ impl DFA {
             Vec::new();
         let mut dfa_to_accepted_nfa_state_mapping: Vec<Option<(usize, crate::nfa::nfa::State)>> =
             Vec::new();
+        let mut dfa_to_accepted_nfas: Vec<Vec<usize>> = Vec::new();
+        let mut dfa_to_alive_nfas: Vec<Vec<usize>> = Vec::new();
         let mut dfa_accept_states = HashSet::new();
         let mut dfa_transitions: Vec<Vec<Option<Transition>>> = Vec::new();
 
@@ -292,6 +404,8 @@ impl DFA {
 
         dfa_to_nfa_state_mapping.push(start_epi_closure.clone());
         dfa_to_accepted_nfa_state_mapping.push(None);
+        dfa_to_accepted_nfas.push(Vec::new());
+        dfa_to_alive_nfas.push(Vec::new());
         l_nfa_states_to_dfa_mapping.insert(start_epi_closure, State(start_state));
         l_worklist.push(State(start_state));
 
@@ -300,17 +414,29 @@ impl DFA {
             // Take the immutable borrow into a local variable
             let nfa_states = { dfa_to_nfa_state_mapping.get(dfa_state.0).unwrap().clone() };
 
-            // Check if this DFA state is an accept state
+            // Check if this DFA state is an accept state. When multiple NFAs are accepted, the
+            // first declared one (the lowest index) takes precedence.
+            let mut accepted_nfas = Vec::new();
+            let mut alive_nfas = Vec::new();
             for (idx, nfa_state) in nfa_states.iter() {
-                if nfas.get(*idx).unwrap().get_accept() == *nfa_state {
-                    dfa_to_accepted_nfa_state_mapping
+                alive_nfas.push(*idx);
+                if nfas.get(*idx).unwrap().is_accept_state(nfa_state) {
+                    accepted_nfas.push(*idx);
+                    let mapping = dfa_to_accepted_nfa_state_mapping
                         .get_mut(dfa_state.0)
-                        .as_mut()
-                        .unwrap()
-                        .replace((*idx, nfa_state.clone()));
+                        .unwrap();
+                    if mapping.is_none() || mapping.as_ref().unwrap().0 > *idx {
+                        mapping.replace((*idx, nfa_state.clone()));
+                    }
                     dfa_accept_states.insert(dfa_state.clone());
                 }
             }
+            accepted_nfas.sort();
+            accepted_nfas.dedup();
+            alive_nfas.sort();
+            alive_nfas.dedup();
+            dfa_to_accepted_nfas[dfa_state.0] = accepted_nfas;
+            dfa_to_alive_nfas[dfa_state.0] = alive_nfas;
 
             // Process the Move operation for all transitions in the NFA states set
             let mut move_transitions_symbol_to_transitions_vec = vec![Vec::new(); 128];
@@ -363,6 +489,8 @@ impl DFA {
                     }
                     dfa_transitions.push(transition_vector);
                     dfa_to_accepted_nfa_state_mapping.push(None);
+                    dfa_to_accepted_nfas.push(Vec::new());
+                    dfa_to_alive_nfas.push(Vec::new());
 
                     // Ensure no mutable and immutable borrow overlap
                     dfa_to_nfa_state_mapping.push(destination_nfa_states.clone());
@@ -380,23 +508,29 @@ impl DFA {
                 // Add the transition to the DFA
                 dfa_transitions.get_mut(dfa_state.0).unwrap()[symbol] = Some(Transition {
                     from_state: dfa_state.clone(),
-                    symbol_onehot_encoding:
-                        crate::nfa::nfa::Transition::convert_char_to_symbol_onehot_encoding(
-                            symbol as u8 as char,
-                        ),
+                    // Symbols range over ASCII
+                    symbol_onehot_encoding: 1u128 << symbol,
                     to_state: destination_dfa_state.clone(),
                     tag: None,
                 });
             }
         }
 
-        DFA {
+        let state_mapping = dfa_to_nfa_state_mapping
+            .iter()
+            .enumerate()
+            .map(|(dfa_state_idx, nfa_states)| (State(dfa_state_idx), nfa_states.to_vec()))
+            .collect();
+        let dfa = DFA {
             start: State(start_state),
             accept: dfa_accept_states.into_iter().collect(),
             states: dfa_states,
             transitions: dfa_transitions,
             dfa_to_accepted_nfa_state_mapping,
-        }
+            dfa_to_accepted_nfas,
+            dfa_to_alive_nfas,
+        };
+        (dfa, state_mapping)
     }
 }
 
@@ -471,13 +605,13 @@ mod tests {
 
         dfa.add_transition(
             start.clone(),
-            nfa::nfa::Transition::convert_char_to_symbol_onehot_encoding('a'),
+            nfa::nfa::Transition::convert_char_to_symbol_onehot_encoding('a').unwrap(),
             accept.clone(),
             None,
         );
         dfa.add_transition(
             accept.clone(),
-            nfa::nfa::Transition::convert_char_to_symbol_onehot_encoding('b'),
+            nfa::nfa::Transition::convert_char_to_symbol_onehot_encoding('b').unwrap(),
             start.clone(),
             None,
         );
@@ -521,6 +655,30 @@ mod tests {
         Ok(nfa)
     }
 
+    #[test]
+    fn test_to_csv() -> Result<()> {
+        let nfa = NFA::from_regex("a")?;
+        let dfa = DFA::from_multiple_nfas(vec![nfa]);
+        assert_eq!(
+            dfa.to_csv(),
+            "from_state,symbol_range,to_state,accept_tag\n0,a,1,0\n"
+        );
+
+        // Symbols are coalesced into ranges, and ranges containing commas are quoted
+        let nfa = NFA::from_regex("[a-z0-9,]b")?;
+        let dfa = DFA::from_multiple_nfas(vec![nfa]);
+        assert_eq!(
+            dfa.to_csv(),
+            "from_state,symbol_range,to_state,accept_tag\n\
+             0,\",\",1,\n\
+             0,0-9,1,\n\
+             0,a-z,1,\n\
+             1,b,2,0\n"
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_nfa1_from_nfa_to_dfa() -> Result<()> {
         let nfa = create_nfa1()?;
@@ -696,6 +854,51 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_first_declared_nfa_wins_ties() -> Result<()> {
+        let mut int_nfa = NFA::new();
+        int_nfa.add_ast_to_nfa(
+            &RegexParser::new().parse_into_ast(r"\d+")?,
+            NFA::START_STATE,
+            NFA::ACCEPT_STATE,
+        )?;
+        let mut hex_nfa = NFA::new();
+        hex_nfa.add_ast_to_nfa(
+            &RegexParser::new().parse_into_ast(r"(\d|[a-f])+")?,
+            NFA::START_STATE,
+            NFA::ACCEPT_STATE,
+        )?;
+
+        let dfa = DFA::from_multiple_nfas(vec![int_nfa, hex_nfa]);
+        assert_eq!(dfa.simulate("12"), (Some(0usize), true));
+        assert_eq!(dfa.simulate("1a"), (Some(1usize), true));
+
+        let mut state = dfa.get_root();
+        state = dfa.get_next_state(state, b'1').unwrap();
+        assert!(dfa.is_nfa_accepted(state.clone(), 0));
+        assert!(dfa.is_nfa_accepted(state.clone(), 1));
+        state = dfa.get_next_state(state, b'a').unwrap();
+        assert_eq!(dfa.is_nfa_alive(state.clone(), 0), false);
+        assert!(dfa.is_nfa_alive(state.clone(), 1));
+        assert!(dfa.is_nfa_accepted(state, 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clone() -> Result<()> {
+        let dfa = DFA::from_multiple_nfas(vec![create_nfa1()?]);
+        let mut cloned_dfa = dfa.clone();
+
+        // Make "a" no longer accepted in the cloned DFA
+        cloned_dfa.transitions[0]['a' as usize] = None;
+        assert_eq!(cloned_dfa.simulate("a"), (None, false));
+        assert_eq!(dfa.simulate("a"), (Some(0usize), true));
+        assert_eq!(dfa.simulate("ab"), (Some(0usize), true));
+
+        Ok(())
+    }
+
     #[test]
     fn test_int() -> Result<()> {
         let mut parser = RegexParser::new();
@@ -972,4 +1175,80 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_alternation_with_empty_branch() -> Result<()> {
+        let dfa = DFA::from_multiple_nfas(vec![NFA::from_regex(r"(a|)")?]);
+        assert_eq!(dfa.simulate("a"), (Some(0usize), true));
+        assert_eq!(dfa.simulate(""), (Some(0usize), true));
+        assert_eq!(dfa.simulate("aa"), (None, false));
+
+        let dfa = DFA::from_multiple_nfas(vec![NFA::from_regex(r"error(:|)")?]);
+        assert_eq!(dfa.simulate("error"), (Some(0usize), true));
+        assert_eq!(dfa.simulate("error:"), (Some(0usize), true));
+        assert_eq!(dfa.simulate("error::"), (None, false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_appended_regex() -> Result<()> {
+        let mut nfa = NFA::from_regex(r"a")?;
+        nfa.append_regex(r"b")?;
+        let dfa = DFA::from_multiple_nfas(vec![nfa]);
+        assert_eq!(dfa.simulate("ab"), (Some(0usize), true));
+        assert_eq!(dfa.simulate("a"), (None, false));
+        assert_eq!(dfa.simulate("b"), (None, false));
+        assert_eq!(dfa.simulate("ba"), (None, false));
+        assert_eq!(dfa.simulate("abb"), (None, false));
+
+        let mut nfa = NFA::from_regex(r"\d{4}\-\d{2}\-\d{2}")?;
+        nfa.append_regex(r" \d{2}:\d{2}")?;
+        nfa.append_regex(r"( UTC|)")?;
+        let dfa = DFA::from_multiple_nfas(vec![nfa]);
+        assert_eq!(dfa.simulate("2015-01-31 15:50"), (Some(0usize), true));
+        assert_eq!(dfa.simulate("2015-01-31 15:50 UTC"), (Some(0usize), true));
+        assert_eq!(dfa.simulate("2015-01-31"), (None, false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reversed_nfa() -> Result<()> {
+        let dfa = DFA::from_multiple_nfas(vec![NFA::from_regex(r"ab")?.reverse()]);
+        assert_eq!(dfa.simulate("ba"), (Some(0usize), true));
+        assert_eq!(dfa.simulate("ab"), (None, false));
+
+        let dfa = DFA::from_multiple_nfas(vec![NFA::from_regex(r"\d+\.(a|)")?.reverse()]);
+        assert_eq!(dfa.simulate(".12"), (Some(0usize), true));
+        assert_eq!(dfa.simulate("a.12"), (Some(0usize), true));
+        assert_eq!(dfa.simulate("12."), (None, false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_epsilon_free_nfa() -> Result<()> {
+        let inputs = [
+            "", "a", "aa", "aaa", "b", "bb", "ab", "ba", "1.5", "12.", ".5",
+        ];
+        for pattern in [r"a*", r"a|b*", r"(a|b)+", r"a?b", r"\d+\.(\d|)", r"a{1,3}"] {
+            let dfa = DFA::from_multiple_nfas(vec![NFA::from_regex(pattern)?]);
+            let epsilon_free_dfa =
+                DFA::from_multiple_nfas(vec![NFA::from_regex(pattern)?.remove_epsilons()]);
+            let reversed_dfa = DFA::from_multiple_nfas(vec![NFA::from_regex(pattern)?
+                .remove_epsilons()
+                .reverse()]);
+            for input in inputs {
+                assert_eq!(epsilon_free_dfa.simulate(input), dfa.simulate(input));
+                let reversed_input = input.chars().rev().collect::<String>();
+                assert_eq!(
+                    reversed_dfa.simulate(reversed_input.as_str()).0,
+                    dfa.simulate(input).0
+                );
+            }
+        }
+
+        Ok(())
+    }
 }
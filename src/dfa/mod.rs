@@ -1,5 +1,5 @@
 mod dfa;
 
 pub(crate) use dfa::DfaSimulator;
-pub(crate) use dfa::State;
-pub(crate) use dfa::DFA;
+pub use dfa::State;
+pub use dfa::DFA;
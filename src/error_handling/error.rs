@@ -1,5 +1,14 @@
 use regex_syntax::ast;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    TokensPerEvent,
+    BytesPerLine,
+    DeterminizedStates,
+    NFAStates,
+    CompileMillis,
+}
+
 #[derive(Debug)]
 pub enum Error {
     RegexParsingError(ast::Error),
@@ -12,12 +21,38 @@ pub enum Error {
     UnsupportedAstBracketedKind,
     UnsupportedClassSetType,
     UnsupportedGroupKindType,
+    InvalidRepetitionRange {
+        min: u32,
+        max: u32,
+    },
     MissingSchemaKey(&'static str),
     LexerInputStreamNotSet,
+    UnseekableInputStream,
     LexerStateUnknown,
     LexerInternalErr(&'static str),
     LogParserInternalErr(&'static str),
-    InvalidSchema,
+    InvalidSchema(String),
+    DuplicateVariableName(String),
+    UnresolvedSchemaReference(String),
+    LimitExceeded {
+        kind: LimitKind,
+        limit: usize,
+    },
+    MissingTimestamp {
+        line: usize,
+    },
+    UnsupportedRegexFeature {
+        feature: &'static str,
+        position: usize,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+// Keeps the message and the span of the `regex_syntax` error, so that the position of the malformed
+// part of a pattern can be reported
+impl From<ast::Error> for Error {
+    fn from(error: ast::Error) -> Self {
+        Error::RegexParsingError(error)
+    }
+}
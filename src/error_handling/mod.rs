@@ -1,3 +1,4 @@
 mod error;
 pub use error::Error;
+pub use error::LimitKind;
 pub use error::Result;
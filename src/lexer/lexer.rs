@@ -1,12 +1,40 @@
 use crate::dfa::{State, DFA};
-use crate::error_handling::Error::{LexerInputStreamNotSet, LexerInternalErr, LexerStateUnknown};
-use crate::error_handling::Result;
-use crate::lexer::LexerStream;
+use crate::error_handling::Error::{
+    LexerInputStreamNotSet, LexerInternalErr, LexerStateUnknown, LimitExceeded,
+    NoneASCIICharacters, UnresolvedSchemaReference,
+};
+use crate::error_handling::{LimitKind, Result};
+use crate::lexer::{LexerStream, StringStream};
 use crate::nfa::nfa::NFA;
 use crate::parser::SchemaConfig;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
-use std::rc::Rc;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::ops::Range;
+use std::sync::Arc;
+
+// Literals are short, so the literal fast path uses FNV-1a rather than the default SipHash
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+type LiteralVarMap = HashMap<Vec<char>, usize, BuildHasherDefault<FnvHasher>>;
 
 enum LexerState {
     SeekingToTheNextDelimiter,
@@ -14,28 +42,127 @@ enum LexerState {
     DFANotAccepted,
     DFAAccepted,
     VarExtract,
+    LiteralMatching,
     ParsingTimestamp,
     EndOfStream,
 }
 
+// The policy used to select a variable match when multiple variable schemas match at the same
+// position.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MatchPolicy {
+    // Maximal munch: the longest match wins. If multiple variables match the same (longest)
+    // input, the variable with the highest priority wins, then the first declared. A match must be
+    // followed by a delimiter (or the end of the stream), otherwise it is treated as static text.
+    LongestMatch,
+
+    // The first variable (by priority, then declaration order) that matches at the current
+    // position wins, and the match is extended for as long as that variable can keep matching.
+    // The match ends as soon as the variable can't consume the next character, even if that
+    // character isn't a delimiter.
+    FirstDeclared,
+}
+
+// How non-ASCII characters read from the input stream are handled. No variable or timestamp can
+// match them, so by default they're kept as static text (see `Lexer::set_emit_unknown_tokens`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BytePolicy {
+    // Lexing fails with `NoneASCIICharacters`
+    Strict,
+    // The characters are dropped
+    Skip,
+    // Each character is replaced with `?`
+    Replace,
+}
+
+// Splits an input stream into timestamp, variable and static-text tokens. The lexer can be used on
+// its own: create it with `Lexer::new`, set the input with `set_input_stream`, then pull tokens
+// with `get_next_token` until it returns `None`. `LogParser` is built on top of it to group tokens
+// into log events.
+//
+// A character can be both a delimiter and part of a variable (e.g. `-` with `\-{0,1}\d+`). A
+// variable match starts after a delimiter, and takes precedence over the delimiter when the
+// variable can consume it from there: with `-` as a delimiter, `-42` is lexed as a single
+// variable. A `-` following other text still delimits that text, and is only consumed if the
+// match it starts is accepted.
 pub struct Lexer {
-    schema_config: Rc<SchemaConfig>,
+    schema_config: Arc<SchemaConfig>,
     ts_dfa: DFA,
+    var_nfas: Vec<NFA>,
     var_dfa: DFA,
+    // Maps the NFA index in `var_dfa` to the variable schema ID
+    var_dfa_schema_ids: Vec<usize>,
+    // Literal variables matched by the literal fast path (see `build_var_dfa`), keyed by literal
+    literal_vars: LiteralVarMap,
+    max_literal_len: usize,
+    literal_fast_path_supported: bool,
 
     state: LexerState,
     dfa_state: State,
+    match_policy: MatchPolicy,
+    committed_var: Option<usize>,
+    keep_delimiters: bool,
+    collapse_delimiters: bool,
+    emit_unknown_tokens: bool,
+    classify_whitespace: bool,
+    // Number of tokens a timestamp may follow at the start of a line (see
+    // `set_timestamp_lookahead_tokens`)
+    timestamp_lookahead_tokens: usize,
+    // Names of the variables, indexed by schema ID, shared by the tokens matching them
+    var_names: Vec<Arc<str>>,
+    // Whether each variable (indexed by schema ID) is matched (see `set_variable_enabled`)
+    var_enabled: Vec<bool>,
+    // Character terminating lines (see `SchemaConfig::get_line_separator`)
+    line_separator: char,
 
     input_stream: Option<Box<dyn LexerStream>>,
+    // Label of the input stream, copied into each token
+    source_id: Option<Arc<str>>,
+    // Character read ahead from the input stream while normalizing CRLF line endings
+    lookahead_char: Option<char>,
+    // Bytes dropped while reading the input stream (the `\r` of CRLF line endings, and non-ASCII
+    // characters dropped or replaced under `byte_policy`) since the last character was buffered
+    stripped_bytes: usize,
+    byte_policy: Option<BytePolicy>,
     buf: Vec<char>,
+    // Buffer positions of the characters read after dropping input bytes, along with the number of
+    // bytes dropped, in increasing order. The bytes are accounted to the token containing the
+    // character, so that the following tokens report their position in the input.
+    buf_stripped_bytes: Vec<(usize, usize)>,
     buf_cursor_pos: usize,
     token_queue: VecDeque<Token>,
+    // Whether a variable references timestamp groups, in which case the fields captured by the last
+    // timestamp are kept to check its matches
+    has_timestamp_refs: bool,
+    timestamp_fields: Option<HashMap<String, String>>,
+    // Set while lexing through `tokenize_borrowed`, where token text is sliced from the input
+    // instead of being copied into each token
+    borrowed_tokens: bool,
 
     last_delimiter: Option<char>,
     last_tokenized_pos: usize,
     match_start_pos: usize,
     match_end_pos: usize,
+    // Variables whose regex matches delimiters can consume them (except the line separator). These
+    // track the first delimiter consumed by the current match, and the longest accepted match (end
+    // position and variable schema ID) followed by a consumed delimiter, to recover when the match
+    // eventually fails.
+    match_delimiter_pos: Option<usize>,
+    delimited_match: Option<(usize, usize)>,
+    // Buffer ranges covered by multibyte delimiters (see `SchemaConfig::get_multibyte_delimiters`),
+    // in increasing order
+    multibyte_delimiter_spans: Vec<Range<usize>>,
     line_num: usize,
+    byte_offset: usize,
+    // Position of the next token within its line (see `Token::get_column` and
+    // `Token::display_column`)
+    line_start_byte_offset: usize,
+    line_chars: usize,
+    line_last_tab: Option<Arc<LineTab>>,
+
+    // Bytes read from the input stream since the last line separator
+    line_bytes: usize,
+    max_line_bytes: Option<usize>,
 }
 
 #[derive(Clone, Debug)]
@@ -44,13 +171,76 @@ pub enum TokenType {
     Variable(usize),
     StaticText,
     StaticTextWithEndLine,
+    // A run of whitespace in static text. Only emitted when enabled with
+    // `Lexer::set_classify_whitespace`; otherwise whitespace is part of `StaticText`.
+    Whitespace,
+    // Characters outside of the ASCII alphabet supported by the automata, which no variable or
+    // timestamp can match. Only emitted when enabled with `Lexer::set_emit_unknown_tokens`.
+    Unknown,
     End,
 }
 
+// The outcome of a lexing step (see `Lexer::next_step`)
+#[derive(Debug)]
+pub enum LexStep {
+    Token(Token),
+    // Input was consumed without producing a token: the rest of a line exceeding the maximum line
+    // size was dropped
+    Skipped,
+    // The end of the input stream is reached; every following step is `Eof` as well
+    Eof,
+}
+
+// Kinds of runs static text is split into (see `Lexer::generate_token`)
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum StaticTextRun {
+    Text,
+    Whitespace,
+    Delimiters,
+    Unknown,
+}
+
+#[derive(Clone)]
 pub struct Token {
     val: String,
+    // The value after applying the variable's transform, if it has one
+    transformed_val: Option<String>,
     token_type: TokenType,
     line_num: usize,
+    byte_offset: usize,
+    // Bytes the token spans in the input, which includes any `\r` dropped by CRLF normalization
+    num_input_bytes: usize,
+    // Bytes and characters between the start of the line and the token
+    column: usize,
+    char_column: usize,
+    // The last tab between the start of the line and the token, to expand the tabs in
+    // `display_column`
+    last_tab: Option<Arc<LineTab>>,
+    // Label of the input stream the token was read from (see `LexerStream::source_id`)
+    source_id: Option<Arc<str>>,
+    // Name of the matched variable, for a `TokenType::Variable` token
+    variable_name: Option<Arc<str>>,
+}
+
+// A tab of a line, linked to the tab before it, so that the tokens of a line share the tabs
+// preceding them rather than each holding a copy
+struct LineTab {
+    char_column: usize,
+    prev: Option<Arc<LineTab>>,
+}
+
+// Unlinks the tabs one at a time, as dropping the chain of a long line recursively could overflow
+// the stack
+impl Drop for LineTab {
+    fn drop(&mut self) {
+        let mut prev = self.prev.take();
+        while let Some(tab) = prev {
+            prev = match Arc::try_unwrap(tab) {
+                Ok(mut tab) => tab.prev.take(),
+                Err(_) => None,
+            };
+        }
+    }
 }
 
 impl Debug for Token {
@@ -66,10 +256,91 @@ impl Debug for Token {
 }
 
 impl Token {
+    pub const DEFAULT_TAB_WIDTH: usize = 8;
+
     pub fn get_val(&self) -> &str {
         self.val.as_str()
     }
 
+    // Returns the matched text exactly as it appears in the input
+    pub fn raw(&self) -> &str {
+        self.val.as_str()
+    }
+
+    // Returns the reported value: the matched text with the variable's transform applied, if any
+    pub fn value(&self) -> &str {
+        self.transformed_val.as_deref().unwrap_or(self.val.as_str())
+    }
+
+    pub fn get_token_type(&self) -> TokenType {
+        self.token_type.clone()
+    }
+
+    pub fn get_line_num(&self) -> usize {
+        self.line_num
+    }
+
+    pub fn get_byte_offset(&self) -> usize {
+        self.byte_offset
+    }
+
+    // Returns the byte offset of the token from the start of its line
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+
+    // Returns the column the token is displayed at, with each tab advancing to the next multiple of
+    // `tab_width` (e.g. `Token::DEFAULT_TAB_WIDTH`). Every other character takes a single column.
+    pub fn display_column(&self, tab_width: usize) -> usize {
+        let tab_width = tab_width.max(1);
+        let mut tab_columns = Vec::new();
+        let mut tab = self.last_tab.as_deref();
+        while let Some(curr_tab) = tab {
+            tab_columns.push(curr_tab.char_column);
+            tab = curr_tab.prev.as_deref();
+        }
+        let mut display_column = 0;
+        let mut next_char_column = 0;
+        for tab_column in tab_columns.iter().rev() {
+            display_column += tab_column - next_char_column;
+            display_column = (display_column / tab_width + 1) * tab_width;
+            next_char_column = tab_column + 1;
+        }
+        display_column + self.char_column - next_char_column
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        matches!(self.token_type, TokenType::Unknown)
+    }
+
+    // Returns the label of the input stream the token was read from, if it has one
+    pub fn source_id(&self) -> Option<&str> {
+        self.source_id.as_deref()
+    }
+
+    // Returns the name of the variable the token matched, or `None` if it isn't a variable. This is
+    // the name of the variable schema `TokenType::Variable` refers to.
+    pub fn variable_name(&self) -> Option<&str> {
+        self.variable_name.as_deref()
+    }
+}
+
+// A token whose text borrows from the input passed to `Lexer::tokenize_borrowed`. The text is the
+// input exactly as it is, so a line ending normalized from CRLF keeps its `\r`. Variable transforms
+// aren't applied, as that would require an owned copy.
+#[derive(Clone, Debug)]
+pub struct TokenRef<'a> {
+    val: &'a str,
+    token_type: TokenType,
+    line_num: usize,
+    byte_offset: usize,
+}
+
+impl<'a> TokenRef<'a> {
+    pub fn get_val(&self) -> &'a str {
+        self.val
+    }
+
     pub fn get_token_type(&self) -> TokenType {
         self.token_type.clone()
     }
@@ -77,12 +348,47 @@ impl Token {
     pub fn get_line_num(&self) -> usize {
         self.line_num
     }
+
+    pub fn get_byte_offset(&self) -> usize {
+        self.byte_offset
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        matches!(self.token_type, TokenType::Unknown)
+    }
+}
+
+// Iterator over the tokens of an in-memory input (see `Lexer::tokenize_borrowed`).
+pub struct BorrowedTokens<'l, 'a> {
+    lexer: &'l mut Lexer,
+    input: &'a str,
+}
+
+impl<'l, 'a> Iterator for BorrowedTokens<'l, 'a> {
+    type Item = Result<TokenRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = match self.lexer.get_next_token() {
+            Ok(Some(token)) => token,
+            Ok(None) => return None,
+            Err(e) => return Some(Err(e)),
+        };
+        let end = token.byte_offset + token.num_input_bytes;
+        Some(Ok(TokenRef {
+            val: &self.input[token.byte_offset..end],
+            token_type: token.token_type,
+            line_num: token.line_num,
+            byte_offset: token.byte_offset,
+        }))
+    }
 }
 
 impl Lexer {
     const MIN_BUF_GARBAGE_COLLECTION_SIZE: usize = 4096;
 
-    pub fn new(schema_mgr: Rc<SchemaConfig>) -> Result<Self> {
+    // Builds the timestamp and variable automata of the schema. Fails if a regex uses a construct
+    // the automata don't support.
+    pub fn new(schema_mgr: Arc<SchemaConfig>) -> Result<Self> {
         let mut ts_nfas: Vec<NFA> = Vec::new();
         for schema in schema_mgr.get_ts_schemas() {
             let mut nfa = NFA::new();
@@ -97,46 +403,346 @@ impl Lexer {
             nfa.add_ast_to_nfa(schema.get_ast(), nfa.get_start(), nfa.get_accept())?;
             var_nfas.push(nfa);
         }
-        let var_dfa = DFA::from_multiple_nfas(var_nfas);
+        let literal_fast_path_supported =
+            Self::is_literal_fast_path_supported(schema_mgr.as_ref(), &var_nfas);
+        // The default match policy is `MatchPolicy::LongestMatch`
+        let (var_dfa, var_dfa_schema_ids, literal_vars) =
+            Self::build_var_dfa(schema_mgr.as_ref(), &var_nfas, literal_fast_path_supported);
         let var_dfa_root = var_dfa.get_root();
+        let max_literal_len = Self::get_max_literal_len(&literal_vars);
+        let line_separator = schema_mgr.get_line_separator();
+        let has_timestamp_refs = schema_mgr
+            .get_var_schemas()
+            .iter()
+            .any(|schema| false == schema.get_timestamp_refs().is_empty());
+        let var_names = schema_mgr
+            .get_var_schemas()
+            .iter()
+            .map(|schema| Arc::from(schema.get_name()))
+            .collect();
+        let var_enabled = vec![true; schema_mgr.get_var_schemas().len()];
 
         Ok(Self {
             schema_config: schema_mgr,
             ts_dfa,
+            var_nfas,
             var_dfa,
+            var_dfa_schema_ids,
+            literal_vars,
+            max_literal_len,
+            literal_fast_path_supported,
             state: LexerState::ParsingTimestamp,
             dfa_state: var_dfa_root,
+            match_policy: MatchPolicy::LongestMatch,
+            committed_var: None,
+            keep_delimiters: false,
+            collapse_delimiters: true,
+            emit_unknown_tokens: false,
+            classify_whitespace: false,
+            timestamp_lookahead_tokens: 0,
+            var_names,
+            var_enabled,
+            line_separator,
             input_stream: None,
+            source_id: None,
+            lookahead_char: None,
+            stripped_bytes: 0,
+            byte_policy: None,
             buf: Vec::new(),
+            buf_stripped_bytes: Vec::new(),
             buf_cursor_pos: 0,
             token_queue: VecDeque::new(),
+            has_timestamp_refs,
+            timestamp_fields: None,
+            borrowed_tokens: false,
             last_delimiter: None,
             last_tokenized_pos: 0,
             match_start_pos: 0,
             match_end_pos: 0,
+            match_delimiter_pos: None,
+            multibyte_delimiter_spans: Vec::new(),
+            delimited_match: None,
             line_num: 0,
+            byte_offset: 0,
+            line_start_byte_offset: 0,
+            line_chars: 0,
+            line_last_tab: None,
+            line_bytes: 0,
+            max_line_bytes: None,
         })
     }
 
+    // The literal fast path requires that no variable can match a delimiter, so that under
+    // `MatchPolicy::LongestMatch` a variable always spans exactly the text between two delimiters.
+    fn is_literal_fast_path_supported(schema_config: &SchemaConfig, var_nfas: &[NFA]) -> bool {
+        let mut delimiter_mask = 0u128;
+        for c in 0..128u8 {
+            if schema_config.has_delimiter(c as char) {
+                delimiter_mask |= 1u128 << c;
+            }
+        }
+        for multibyte_delimiter in schema_config.get_multibyte_delimiters() {
+            for c in multibyte_delimiter.bytes() {
+                delimiter_mask |= 1u128 << c;
+            }
+        }
+        var_nfas.iter().all(|nfa| {
+            nfa.get_transitions()
+                .values()
+                .flatten()
+                .all(|transition| 0 == transition.get_symbol_onehot_encoding() & delimiter_mask)
+        })
+    }
+
+    // Builds the variable DFA, returning it along with the variable schema ID of each of its NFAs
+    // and the literal variables. When the literal fast path is used, variables whose regex is a
+    // pure literal are left out of the DFA and matched with a hash lookup on the text between
+    // delimiters instead, which keeps the DFA small for schemas with many keywords.
+    fn build_var_dfa(
+        schema_config: &SchemaConfig,
+        var_nfas: &[NFA],
+        use_literal_fast_path: bool,
+    ) -> (DFA, Vec<usize>, LiteralVarMap) {
+        let mut nfas: Vec<NFA> = Vec::new();
+        let mut var_dfa_schema_ids: Vec<usize> = Vec::new();
+        let mut literal_vars = LiteralVarMap::default();
+        // NFAs are added in order of precedence, since the DFA resolves ties in favor of the NFA
+        // with the lowest index
+        for schema_id in schema_config.var_schema_ids_by_priority() {
+            let schema = &schema_config.get_var_schemas()[schema_id];
+            if use_literal_fast_path {
+                if let Some(literal) = schema.as_literal() {
+                    literal_vars
+                        .entry(literal.chars().collect())
+                        .or_insert(schema_id);
+                    continue;
+                }
+            }
+            nfas.push(var_nfas[schema_id].clone());
+            var_dfa_schema_ids.push(schema_id);
+        }
+        (
+            DFA::from_multiple_nfas(nfas),
+            var_dfa_schema_ids,
+            literal_vars,
+        )
+    }
+
+    fn get_max_literal_len(literal_vars: &LiteralVarMap) -> usize {
+        literal_vars
+            .keys()
+            .map(|literal| literal.len())
+            .max()
+            .unwrap_or(0)
+    }
+
     fn reset(&mut self) {
         self.input_stream = None;
+        self.source_id = None;
+        self.lookahead_char = None;
+        self.stripped_bytes = 0;
         self.buf.clear();
+        self.buf_stripped_bytes.clear();
         self.buf_cursor_pos = 0;
         self.token_queue.clear();
+        self.timestamp_fields = None;
+        self.borrowed_tokens = false;
         self.last_delimiter = None;
+        self.multibyte_delimiter_spans.clear();
         self.last_tokenized_pos = 0;
         self.match_start_pos = 0;
         self.match_end_pos = 0;
         self.line_num = 0;
+        self.byte_offset = 0;
+        self.line_start_byte_offset = 0;
+        self.line_chars = 0;
+        self.line_last_tab = None;
+        self.line_bytes = 0;
+        self.committed_var = None;
         self.state = LexerState::ParsingTimestamp;
     }
 
+    // The match policy should be set before lexing an input stream.
+    pub fn set_match_policy(&mut self, match_policy: MatchPolicy) {
+        if match_policy == self.match_policy {
+            return;
+        }
+        self.match_policy = match_policy;
+
+        // The literal fast path only applies to `MatchPolicy::LongestMatch`
+        (self.var_dfa, self.var_dfa_schema_ids, self.literal_vars) = Self::build_var_dfa(
+            self.schema_config.as_ref(),
+            &self.var_nfas,
+            self.literal_fast_path_supported && MatchPolicy::LongestMatch == match_policy,
+        );
+        self.max_literal_len = Self::get_max_literal_len(&self.literal_vars);
+        self.dfa_state = self.var_dfa.get_root();
+    }
+
+    pub fn get_match_policy(&self) -> MatchPolicy {
+        self.match_policy
+    }
+
+    // Disables (or re-enables) the named variable: text it would match becomes static text, or is
+    // matched by another variable. The automata are kept as is, so this can be called between
+    // tokens of a stream, e.g. to silence a noisy variable; it applies to matches starting after
+    // the call. An unknown name results in `UnresolvedSchemaReference`.
+    pub fn set_variable_enabled(&mut self, name: &str, enabled: bool) -> Result<()> {
+        let schema_id = self.get_var_schema_id(name)?;
+        self.var_enabled[schema_id] = enabled;
+        Ok(())
+    }
+
+    pub fn is_variable_enabled(&self, name: &str) -> Result<bool> {
+        Ok(self.var_enabled[self.get_var_schema_id(name)?])
+    }
+
+    fn get_var_schema_id(&self, name: &str) -> Result<usize> {
+        self.var_names
+            .iter()
+            .position(|var_name| var_name.as_ref() == name)
+            .ok_or_else(|| UnresolvedSchemaReference(name.to_string()))
+    }
+
+    // When set, runs of delimiters in static text are emitted as their own static-text tokens
+    // instead of being folded into the surrounding static text.
+    pub fn set_keep_delimiters(&mut self, keep_delimiters: bool) {
+        self.keep_delimiters = keep_delimiters;
+    }
+
+    pub fn get_keep_delimiters(&self) -> bool {
+        self.keep_delimiters
+    }
+
+    // When set (the default), a run of consecutive delimiters kept by `set_keep_delimiters` is
+    // emitted as a single token, whatever its length or the delimiters it mixes. Otherwise, every
+    // delimiter is emitted as its own token, so that the blank fields between adjacent delimiters
+    // (e.g. in `a,,b` or `a, ,b`) can be told apart.
+    pub fn set_collapse_delimiters(&mut self, collapse_delimiters: bool) {
+        self.collapse_delimiters = collapse_delimiters;
+    }
+
+    pub fn get_collapse_delimiters(&self) -> bool {
+        self.collapse_delimiters
+    }
+
+    // When set, runs of whitespace in static text are emitted as `TokenType::Whitespace` tokens,
+    // so that the spacing of a line can be told apart from its words. Unlike delimiters (see
+    // `set_collapse_delimiters`), a run of whitespace is always a single token. The text ending a
+    // line keeps its `TokenType::StaticTextWithEndLine` type.
+    pub fn set_classify_whitespace(&mut self, classify_whitespace: bool) {
+        self.classify_whitespace = classify_whitespace;
+    }
+
+    pub fn get_classify_whitespace(&self) -> bool {
+        self.classify_whitespace
+    }
+
+    // When set, runs of non-ASCII characters in static text are emitted as `TokenType::Unknown`
+    // tokens instead of being folded into the surrounding static text, so that they can be flagged.
+    pub fn set_emit_unknown_tokens(&mut self, emit_unknown_tokens: bool) {
+        self.emit_unknown_tokens = emit_unknown_tokens;
+    }
+
+    pub fn get_emit_unknown_tokens(&self) -> bool {
+        self.emit_unknown_tokens
+    }
+
+    // Allows a timestamp to follow up to the given number of tokens at the start of a line (e.g. a
+    // hostname prefixing each line), instead of only the line's indentation. The text before the
+    // timestamp is emitted as a single `TokenType::StaticText` token (no variable is matched in
+    // it), followed by the timestamp. A token here is a run of characters other than whitespace
+    // and delimiters.
+    pub fn set_timestamp_lookahead_tokens(&mut self, timestamp_lookahead_tokens: usize) {
+        self.timestamp_lookahead_tokens = timestamp_lookahead_tokens;
+    }
+
+    pub fn get_timestamp_lookahead_tokens(&self) -> usize {
+        self.timestamp_lookahead_tokens
+    }
+
+    // Sets the maximum number of bytes allowed in a single line. When a line exceeds the limit,
+    // the rest of the line is skipped and `LimitExceeded` is returned; lexing resumes from the next
+    // line on the following call.
+    pub fn set_max_line_bytes(&mut self, max_line_bytes: Option<usize>) {
+        self.max_line_bytes = max_line_bytes;
+    }
+
+    // Sets how non-ASCII characters of the input are handled. By default (`None`), they're kept.
+    pub fn set_byte_policy(&mut self, byte_policy: Option<BytePolicy>) {
+        self.byte_policy = byte_policy;
+    }
+
+    pub fn get_byte_policy(&self) -> Option<BytePolicy> {
+        self.byte_policy
+    }
+
+    pub fn get_max_line_bytes(&self) -> Option<usize> {
+        self.max_line_bytes
+    }
+
+    // Dumps the transition table of the timestamp DFA as CSV, for auditing and diffing compiled
+    // schemas. The accept tags are indices of the timestamp schemas.
+    pub fn timestamp_dfa_to_csv(&self) -> String {
+        self.ts_dfa.to_csv()
+    }
+
+    // Dumps the transition table of the variable DFA as CSV. The accept tags are indices into
+    // `var_dfa_schema_ids`, which maps them to the variable schema IDs.
+    pub fn var_dfa_to_csv(&self) -> String {
+        self.var_dfa.to_csv()
+    }
+
+    // Sets the stream to lex, discarding any state left from the previous stream
     pub fn set_input_stream(&mut self, input_stream: Box<dyn LexerStream>) {
         self.reset();
+        (self.line_num, self.byte_offset) = input_stream.initial_position();
+        self.source_id = input_stream.source_id().map(Arc::from);
         self.input_stream = Some(input_stream);
         self.state = LexerState::ParsingTimestamp;
     }
 
+    // Lexes an in-memory input, yielding tokens whose text is a slice of `input` rather than an
+    // owned copy. This avoids an allocation per token, but variable transforms aren't applied
+    // (see `TokenRef`) and non-ASCII characters are kept whatever the byte policy. Calling
+    // `set_input_stream` afterwards switches back to owned tokens.
+    pub fn tokenize_borrowed<'l, 'a>(&'l mut self, input: &'a str) -> BorrowedTokens<'l, 'a> {
+        self.set_input_stream(Box::new(StringStream::new(input)));
+        self.borrowed_tokens = true;
+        BorrowedTokens { lexer: self, input }
+    }
+
+    // Lexes a single line on its own and returns the byte range and type of each of its tokens,
+    // e.g. to highlight the line. The line is lexed from scratch, so a line without a timestamp is
+    // lexed as the continuation of an event, and this replaces any input stream previously set.
+    pub fn highlight_line(&mut self, line: &str) -> Result<Vec<(Range<usize>, TokenType)>> {
+        self.tokenize_borrowed(line)
+            .map(|token| {
+                let token = token?;
+                let begin = token.get_byte_offset();
+                Ok((begin..begin + token.get_val().len(), token.get_token_type()))
+            })
+            .collect()
+    }
+
+    // Lexes a single line on its own like `highlight_line`, but returns owned tokens reporting the
+    // given line number, e.g. for an editor to replace the tokens of an edited line without lexing
+    // the whole input again. Byte offsets and columns are relative to the start of the line. The
+    // line may end with the line separator; any text following it is reported on the next lines.
+    pub fn relex_line(&mut self, line_num: usize, line: &str) -> Result<Vec<Token>> {
+        self.set_input_stream(Box::new(StringStream::new(line)));
+        self.line_num = line_num;
+        let mut tokens = Vec::new();
+        while let Some(token) = self.get_next_token()? {
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    // Returns the next token, or `None` once the end of the input stream is reached. Returns
+    // `LexerInputStreamNotSet` if no input stream has been set. `None` is only returned at the end
+    // of the stream: every call lexes until a token is produced (input made only of delimiters
+    // still produces static-text tokens), so there's no "no token yet" state.
     pub fn get_next_token(&mut self) -> Result<Option<Token>> {
         if self.input_stream.is_none() {
             return Err(LexerInputStreamNotSet);
@@ -147,12 +753,28 @@ impl Lexer {
         Ok(self.token_queue.pop_front())
     }
 
+    // Lexes the next step like `get_next_token`, telling apart a token, input dropped without
+    // producing a token, and the end of the stream. A line dropped for exceeding the maximum line
+    // size (see `set_max_line_bytes`) is reported as `LexStep::Skipped` rather than as a
+    // `LimitExceeded` error, so lexing can carry on without matching on errors.
+    pub fn next_step(&mut self) -> Result<LexStep> {
+        match self.get_next_token() {
+            Ok(Some(token)) => Ok(LexStep::Token(token)),
+            Ok(None) => Ok(LexStep::Eof),
+            Err(LimitExceeded {
+                kind: LimitKind::BytesPerLine,
+                ..
+            }) => Ok(LexStep::Skipped),
+            Err(e) => Err(e),
+        }
+    }
+
     fn fill_token_queue(&mut self) -> Result<()> {
         loop {
             match self.state {
                 LexerState::SeekingToTheNextDelimiter => match self.get_next_char_from_buffer()? {
                     Some(c) => {
-                        if self.schema_config.has_delimiter(c) {
+                        if self.is_unescaped_delimiter_at(self.buf_cursor_pos - 1) {
                             self.last_delimiter = Some(c);
                             self.state = LexerState::HandleDelimiter;
                         }
@@ -169,16 +791,12 @@ impl Lexer {
 
                     let delimiter = self.last_delimiter.unwrap();
                     self.last_delimiter = None;
-                    match delimiter {
-                        '\n' => {
-                            self.generate_token(
-                                self.buf_cursor_pos,
-                                TokenType::StaticTextWithEndLine,
-                            )?;
-                            self.line_num += 1;
-                            self.state = LexerState::ParsingTimestamp;
-                        }
-                        _ => self.proceed_to_var_dfa_simulation(),
+                    if self.line_separator == delimiter {
+                        self.generate_token(self.buf_cursor_pos, TokenType::StaticTextWithEndLine)?;
+                        self.line_num += 1;
+                        self.state = LexerState::ParsingTimestamp;
+                    } else {
+                        self.proceed_to_var_dfa_simulation();
                     }
                 }
 
@@ -194,7 +812,30 @@ impl Lexer {
                     Some(c) => {
                         self.simulate_var_dfa_and_set_lexer_state(c, LexerState::HandleDelimiter)
                     }
-                    None => self.state = LexerState::EndOfStream,
+                    None => {
+                        let dst_state =
+                            self.match_literal_var(self.buf_cursor_pos, LexerState::EndOfStream);
+                        self.state = self.recover_from_failed_match(dst_state);
+                    }
+                },
+
+                LexerState::LiteralMatching => match self.get_next_char_from_buffer()? {
+                    Some(c) => {
+                        if self.capture_delimiter(c) {
+                            self.state = self.match_literal_var(
+                                self.buf_cursor_pos - 1,
+                                LexerState::HandleDelimiter,
+                            );
+                        } else if self.buf_cursor_pos - self.match_start_pos > self.max_literal_len
+                        {
+                            // Too long to be any literal
+                            self.state = LexerState::SeekingToTheNextDelimiter;
+                        }
+                    }
+                    None => {
+                        self.state =
+                            self.match_literal_var(self.buf_cursor_pos, LexerState::EndOfStream)
+                    }
                 },
 
                 LexerState::DFAAccepted => {
@@ -204,7 +845,10 @@ impl Lexer {
                         Some(c) => {
                             self.simulate_var_dfa_and_set_lexer_state(c, LexerState::VarExtract)
                         }
-                        None => self.state = LexerState::VarExtract,
+                        None => {
+                            self.state =
+                                self.match_literal_var(self.buf_cursor_pos, LexerState::VarExtract)
+                        }
                     }
                 }
 
@@ -222,7 +866,11 @@ impl Lexer {
                     }
 
                     // Extract variable
-                    match self.var_dfa.is_accept_state(self.dfa_state.clone()) {
+                    let accepted_var = match self.committed_var {
+                        Some(schema_id) => Some(schema_id),
+                        None => self.get_accepted_var_schema_id(),
+                    };
+                    match accepted_var {
                         Some(schema_id) => {
                             assert_eq!(self.match_start_pos, self.last_tokenized_pos);
                             self.generate_token(
@@ -239,6 +887,12 @@ impl Lexer {
 
                     match self.last_delimiter {
                         Some(_) => self.state = LexerState::HandleDelimiter,
+                        None if self.buf_cursor_pos > self.match_end_pos => {
+                            // The match ended before a non-delimiter character (only possible with
+                            // `MatchPolicy::FirstDeclared`). Continue lexing right after the match.
+                            self.buf_cursor_pos = self.match_end_pos;
+                            self.proceed_to_var_dfa_simulation();
+                        }
                         None => self.state = LexerState::EndOfStream,
                     }
                 }
@@ -246,7 +900,7 @@ impl Lexer {
                 LexerState::EndOfStream => {
                     if self.buf_cursor_pos > self.last_tokenized_pos {
                         let token_type = if self.last_delimiter.is_some()
-                            && self.last_delimiter.unwrap() == '\n'
+                            && self.last_delimiter.unwrap() == self.line_separator
                         {
                             // TODO: This seems not possible..
                             TokenType::StaticTextWithEndLine
@@ -268,11 +922,115 @@ impl Lexer {
         Ok(())
     }
 
+    // Returns whether the given line starts with a timestamp, i.e. whether lexing it would produce
+    // a timestamp token first (or right after the line's indentation, or the leading tokens
+    // allowed by `set_timestamp_lookahead_tokens`)
+    pub fn starts_with_timestamp(&self, line: &str) -> bool {
+        if self.matches_timestamp_prefix(line) {
+            return true;
+        }
+        let is_separator = |c: char| c.is_whitespace() || self.schema_config.has_delimiter(c);
+        let get_indentation_len = |text: &str| {
+            text.find(|c: char| c == self.line_separator || false == is_separator(c))
+                .unwrap_or(text.len())
+        };
+        let get_token_len = |text: &str| {
+            text.find(|c: char| c == self.line_separator || is_separator(c))
+                .unwrap_or(text.len())
+        };
+        // The timestamp may be indented (see `try_parse_timestamp`)
+        let mut pos = get_indentation_len(line);
+        if 0 != pos && self.matches_timestamp_prefix(&line[pos..]) {
+            return true;
+        }
+        for _ in 0..self.timestamp_lookahead_tokens {
+            let token_end = pos + get_token_len(&line[pos..]);
+            let next_pos = token_end + get_indentation_len(&line[token_end..]);
+            if token_end == pos || next_pos == token_end {
+                return false;
+            }
+            pos = next_pos;
+            if self.matches_timestamp_prefix(&line[pos..]) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn matches_timestamp_prefix(&self, line: &str) -> bool {
+        let mut curr_dfa_state = self.ts_dfa.get_root();
+        for c in line.chars() {
+            if false == c.is_ascii() {
+                return false;
+            }
+            match self.ts_dfa.get_next_state(curr_dfa_state, c as u8) {
+                Some(next_state) => curr_dfa_state = next_state,
+                None => return false,
+            }
+            if self
+                .ts_dfa
+                .is_accept_state(curr_dfa_state.clone())
+                .is_some()
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Matches a timestamp at the start of a line. The timestamp may be indented: if it doesn't
+    // match right away, leading whitespace and delimiters (other than the line separator) are
+    // skipped and emitted as static text before the timestamp. Up to `timestamp_lookahead_tokens`
+    // leading tokens are skipped the same way.
     fn try_parse_timestamp(&mut self) -> Result<bool> {
         let buf_cursor_pos_bookmark = self.buf_cursor_pos;
         if buf_cursor_pos_bookmark != self.last_tokenized_pos {
             return Err(LexerInternalErr("Timestamp parsing corrupted"));
         }
+
+        let mut prefix_end = buf_cursor_pos_bookmark;
+        let mut last_matched = self.match_timestamp()?;
+        if last_matched.is_none() {
+            self.buf_cursor_pos = buf_cursor_pos_bookmark;
+            prefix_end = self.skip_indentation()?;
+            if prefix_end != buf_cursor_pos_bookmark {
+                last_matched = self.match_timestamp()?;
+            }
+        }
+        for _ in 0..self.timestamp_lookahead_tokens {
+            if last_matched.is_some() {
+                break;
+            }
+            self.buf_cursor_pos = prefix_end;
+            let token_end = self.skip_leading_token()?;
+            let next_prefix_end = self.skip_indentation()?;
+            if token_end == prefix_end || next_prefix_end == token_end {
+                // The line ends before another token
+                break;
+            }
+            prefix_end = next_prefix_end;
+            last_matched = self.match_timestamp()?;
+        }
+
+        match last_matched {
+            Some((ts_schema_id, pos)) => {
+                if prefix_end != buf_cursor_pos_bookmark {
+                    self.generate_token(prefix_end, TokenType::StaticText)?;
+                }
+                self.generate_token(pos, TokenType::Timestamp(ts_schema_id))?;
+                self.buf_cursor_pos = pos;
+                Ok(true)
+            }
+            None => {
+                self.buf_cursor_pos = buf_cursor_pos_bookmark;
+                Ok(false)
+            }
+        }
+    }
+
+    // Simulates the timestamp DFA from the buffer cursor, returning the timestamp schema ID and
+    // the end position of the longest match
+    fn match_timestamp(&mut self) -> Result<Option<(usize, usize)>> {
         let mut curr_dfa_state = self.ts_dfa.get_root();
 
         // (Timestamp schema ID, position)
@@ -300,40 +1058,251 @@ impl Lexer {
                 None => {}
             }
         }
+        Ok(last_matched)
+    }
 
-        match last_matched {
-            Some((ts_schema_id, pos)) => {
-                self.generate_token(pos, TokenType::Timestamp(ts_schema_id))?;
-                self.buf_cursor_pos = pos;
-                Ok(true)
+    // Moves the buffer cursor past whitespace and delimiters (other than the line separator),
+    // returning the new cursor position
+    fn skip_indentation(&mut self) -> Result<usize> {
+        while let Some(c) = self.get_next_char_from_buffer()? {
+            let is_indentation = c != self.line_separator
+                && (c.is_whitespace() || self.is_unescaped_delimiter_at(self.buf_cursor_pos - 1));
+            if false == is_indentation {
+                self.buf_cursor_pos -= 1;
+                break;
             }
-            None => {
-                self.buf_cursor_pos = buf_cursor_pos_bookmark;
-                Ok(false)
+        }
+        Ok(self.buf_cursor_pos)
+    }
+
+    // Moves the buffer cursor up to the next whitespace, delimiter or line separator, returning the
+    // new cursor position
+    fn skip_leading_token(&mut self) -> Result<usize> {
+        while let Some(c) = self.get_next_char_from_buffer()? {
+            let ends_token = c == self.line_separator
+                || c.is_whitespace()
+                || self.is_unescaped_delimiter_at(self.buf_cursor_pos - 1);
+            if ends_token {
+                self.buf_cursor_pos -= 1;
+                break;
             }
         }
+        Ok(self.buf_cursor_pos)
     }
 
     fn get_next_char_from_buffer(&mut self) -> Result<Option<char>> {
         let pos = self.buf_cursor_pos;
         if pos == self.buf.len() {
-            match self
-                .input_stream
-                .as_mut()
-                .unwrap()
-                .as_mut()
-                .get_next_char()?
-            {
-                Some(c) => self.buf.push(c),
+            match self.read_char_from_input_stream()? {
+                Some(c) => {
+                    self.check_line_bytes_limit(c)?;
+                    self.push_to_buffer(c)
+                }
                 None => return Ok(None),
             }
         }
         let pos = self.get_and_increment_buf_cursor_pos();
+        if false == self.schema_config.get_multibyte_delimiters().is_empty() {
+            self.find_multibyte_delimiter_at(pos)?;
+        }
         Ok(Some(self.buf[pos]))
     }
 
+    // Records the span of the longest multibyte delimiter starting at the given buffer position,
+    // unless the position is already covered by a delimiter. The characters the delimiter spans
+    // are read ahead into the buffer, up to the end of the line.
+    fn find_multibyte_delimiter_at(&mut self, pos: usize) -> Result<()> {
+        if self
+            .multibyte_delimiter_spans
+            .iter()
+            .any(|span| span.contains(&pos))
+            || self.is_escaped_at(pos)
+        {
+            return Ok(());
+        }
+        let schema_config = self.schema_config.clone();
+        for multibyte_delimiter in schema_config.get_multibyte_delimiters() {
+            let delimiter_len = multibyte_delimiter.len();
+            while self.buf.len() < pos + delimiter_len
+                && self.line_separator != *self.buf.last().unwrap()
+            {
+                match self.read_char_from_input_stream()? {
+                    Some(c) => {
+                        self.check_line_bytes_limit(c)?;
+                        self.push_to_buffer(c);
+                    }
+                    None => break,
+                }
+            }
+            if self.buf.len() >= pos + delimiter_len
+                && multibyte_delimiter
+                    .bytes()
+                    .zip(&self.buf[pos..])
+                    .all(|(byte, c)| byte as char == *c)
+            {
+                let span = pos..pos + delimiter_len;
+                let insert_idx = self
+                    .multibyte_delimiter_spans
+                    .partition_point(|other_span| other_span.start < pos);
+                self.multibyte_delimiter_spans.insert(insert_idx, span);
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    fn push_to_buffer(&mut self, c: char) {
+        if 0 != self.stripped_bytes {
+            let stripped_bytes = std::mem::take(&mut self.stripped_bytes);
+            self.buf_stripped_bytes
+                .push((self.buf.len(), stripped_bytes));
+        }
+        self.buf.push(c);
+    }
+
+    // Reads the next character from the input stream. If the schema normalizes CRLF line endings,
+    // `\r\n` is returned as a single `\n` (file streams always return it as `\n`, see
+    // `BufferedFileStream`). Non-ASCII characters are handled per `byte_policy`.
+    fn read_char_from_input_stream(&mut self) -> Result<Option<char>> {
+        let input_stream = self.input_stream.as_mut().unwrap().as_mut();
+        let mut c = match self.lookahead_char.take() {
+            Some(c) => Some(c),
+            None => input_stream.get_next_char()?,
+        };
+        // Borrowed tokens are sliced from the input, so their text can't differ from it
+        let byte_policy = if self.borrowed_tokens {
+            None
+        } else {
+            self.byte_policy
+        };
+        while let (Some(non_ascii_char), Some(byte_policy)) = (c, byte_policy) {
+            if non_ascii_char.is_ascii() {
+                break;
+            }
+            match byte_policy {
+                BytePolicy::Strict => return Err(NoneASCIICharacters),
+                BytePolicy::Skip => {
+                    self.stripped_bytes += non_ascii_char.len_utf8();
+                    c = input_stream.get_next_char()?;
+                }
+                BytePolicy::Replace => {
+                    self.stripped_bytes += non_ascii_char.len_utf8() - '?'.len_utf8();
+                    c = Some('?');
+                }
+            }
+        }
+        if Some('\r') == c && self.schema_config.is_crlf_normalized() {
+            match input_stream.get_next_char()? {
+                Some('\n') => {
+                    self.stripped_bytes += '\r'.len_utf8() + input_stream.take_stripped_bytes();
+                    return Ok(Some('\n'));
+                }
+                next => self.lookahead_char = next,
+            }
+        }
+        self.stripped_bytes += input_stream.take_stripped_bytes();
+        Ok(c)
+    }
+
+    fn check_line_bytes_limit(&mut self, c: char) -> Result<()> {
+        if self.line_separator == c {
+            self.line_bytes = 0;
+            return Ok(());
+        }
+        self.line_bytes += c.len_utf8();
+        match self.max_line_bytes {
+            Some(limit) if self.line_bytes > limit => {
+                self.skip_to_next_line(c)?;
+                Err(LimitExceeded {
+                    kind: LimitKind::BytesPerLine,
+                    limit,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Drops the untokenized part of the current line (including the given character, which has
+    // been read from the input stream but not buffered) and consumes the input stream up to and
+    // including the next line separator.
+    fn skip_to_next_line(&mut self, c: char) -> Result<()> {
+        let mut skipped_bytes: usize = self.buf[self.last_tokenized_pos..]
+            .iter()
+            .map(|c| c.len_utf8())
+            .sum();
+        skipped_bytes += c.len_utf8();
+        while let Some(c) = self.read_char_from_input_stream()? {
+            skipped_bytes += c.len_utf8();
+            if self.line_separator == c {
+                break;
+            }
+        }
+        skipped_bytes += self.stripped_bytes;
+        self.stripped_bytes = 0;
+        skipped_bytes += self
+            .buf_stripped_bytes
+            .drain(..)
+            .map(|(_, stripped_bytes)| stripped_bytes)
+            .sum::<usize>();
+
+        self.buf.clear();
+        self.buf_cursor_pos = 0;
+        self.last_delimiter = None;
+        self.multibyte_delimiter_spans.clear();
+        self.last_tokenized_pos = 0;
+        self.match_start_pos = 0;
+        self.match_end_pos = 0;
+        self.committed_var = None;
+        self.byte_offset += skipped_bytes;
+        self.line_num += 1;
+        self.line_start_byte_offset = self.byte_offset;
+        self.line_chars = 0;
+        self.line_last_tab = None;
+        self.line_bytes = 0;
+        self.state = LexerState::ParsingTimestamp;
+        Ok(())
+    }
+
+    // Returns whether the character at the given buffer position is a delimiter that isn't escaped
+    // by the schema's escape character. Line separators are never escaped to keep line counting
+    // intact.
+    // A multibyte delimiter is escaped by escaping its first character.
+    fn is_unescaped_delimiter_at(&self, pos: usize) -> bool {
+        if self
+            .multibyte_delimiter_spans
+            .iter()
+            .any(|span| span.contains(&pos))
+        {
+            return true;
+        }
+        let c = self.buf[pos];
+        if false == self.schema_config.has_delimiter(c) {
+            return false;
+        }
+        if self.line_separator == c {
+            return true;
+        }
+        false == self.is_escaped_at(pos)
+    }
+
+    // Returns whether the character at the given buffer position is preceded by an odd number of
+    // escape characters
+    fn is_escaped_at(&self, pos: usize) -> bool {
+        let escape_char = match self.schema_config.get_escape_char() {
+            Some(escape_char) => escape_char,
+            None => return false,
+        };
+        let mut num_escape_chars = 0usize;
+        let mut pos = pos;
+        while pos > 0 && escape_char == self.buf[pos - 1] {
+            num_escape_chars += 1;
+            pos -= 1;
+        }
+        1 == num_escape_chars % 2
+    }
+
     fn capture_delimiter(&mut self, c: char) -> bool {
-        if self.schema_config.has_delimiter(c) {
+        if self.is_unescaped_delimiter_at(self.buf_cursor_pos - 1) {
             self.last_delimiter = Some(c);
             return true;
         }
@@ -345,26 +1314,158 @@ impl Lexer {
             self.state = LexerState::SeekingToTheNextDelimiter;
             return;
         }
-        match self.var_dfa.get_next_state(self.dfa_state.clone(), c as u8) {
+        // A match never consumes the line separator, so that tokens don't span lines
+        let mut optional_next_dfa_state = match self.line_separator == c {
+            true => None,
+            false => self.var_dfa.get_next_state(self.dfa_state.clone(), c as u8),
+        };
+        if let (Some(next_dfa_state), Some(schema_id)) =
+            (&optional_next_dfa_state, self.committed_var)
+        {
+            if false == self.var_dfa.is_nfa_alive(next_dfa_state.clone(), schema_id) {
+                // The committed variable can't consume this character
+                optional_next_dfa_state = None;
+            }
+        }
+
+        match optional_next_dfa_state {
             Some(next_dfa_state) => {
+                if self.is_unescaped_delimiter_at(self.buf_cursor_pos - 1) {
+                    // The variable consumes a delimiter
+                    let delimiter_pos = self.buf_cursor_pos - 1;
+                    self.match_delimiter_pos.get_or_insert(delimiter_pos);
+                    if let LexerState::VarExtract = delimiter_dst_state {
+                        let accepted_var = match self.committed_var {
+                            Some(schema_id) => Some(schema_id),
+                            None => self.get_accepted_var_schema_id(),
+                        };
+                        if let Some(schema_id) = accepted_var {
+                            self.delimited_match = Some((delimiter_pos, schema_id));
+                        }
+                    }
+                }
                 self.dfa_state = next_dfa_state;
-                match self.var_dfa.is_accept_state(self.dfa_state.clone()) {
-                    Some(_) => self.state = LexerState::DFAAccepted,
-                    None => self.state = LexerState::DFANotAccepted,
+                let accepted_var = self.get_accepted_nfa_idx();
+                if MatchPolicy::FirstDeclared == self.match_policy && self.committed_var.is_none() {
+                    self.committed_var = accepted_var;
+                }
+                let accepted = match self.committed_var {
+                    Some(schema_id) => self
+                        .var_dfa
+                        .is_nfa_accepted(self.dfa_state.clone(), schema_id),
+                    None => accepted_var.is_some(),
+                };
+                match accepted {
+                    true => self.state = LexerState::DFAAccepted,
+                    false => self.state = LexerState::DFANotAccepted,
                 }
             }
             None => {
-                self.state = if self.capture_delimiter(c) {
-                    delimiter_dst_state
+                let dst_state = if self.capture_delimiter(c) {
+                    self.match_literal_var(self.buf_cursor_pos - 1, delimiter_dst_state)
+                } else if self.buf_cursor_pos - self.match_start_pos <= self.max_literal_len {
+                    // A literal variable may still match once the next delimiter is reached
+                    LexerState::LiteralMatching
+                } else if MatchPolicy::FirstDeclared == self.match_policy
+                    && matches!(delimiter_dst_state, LexerState::VarExtract)
+                    && false == self.is_committed_var_anchored()
+                {
+                    // The committed variable stops matching, extract it without a delimiter
+                    LexerState::VarExtract
                 } else {
                     LexerState::SeekingToTheNextDelimiter
                 };
+                self.state = self.recover_from_failed_match(dst_state);
             }
         }
     }
 
+    // Called when the variable DFA stops matching, where `dst_state` is the state the lexer would
+    // move to. If the failed match consumed delimiters, the lexer falls back to the longest
+    // accepted match followed by one of them, or otherwise resumes lexing after the first of them,
+    // so that no variable starting after a consumed delimiter is missed.
+    fn recover_from_failed_match(&mut self, dst_state: LexerState) -> LexerState {
+        if let LexerState::VarExtract | LexerState::LiteralMatching = dst_state {
+            return dst_state;
+        }
+        if let Some((end_pos, schema_id)) = self.delimited_match.take() {
+            self.committed_var = Some(schema_id);
+            self.match_end_pos = end_pos;
+            self.buf_cursor_pos = end_pos + 1;
+            self.last_delimiter = Some(self.buf[end_pos]);
+            return LexerState::VarExtract;
+        }
+        if let Some(delimiter_pos) = self.match_delimiter_pos.take() {
+            self.buf_cursor_pos = delimiter_pos + 1;
+            self.last_delimiter = Some(self.buf[delimiter_pos]);
+            return LexerState::HandleDelimiter;
+        }
+        dst_state
+    }
+
+    // Returns the index of the first NFA of an enabled variable accepted in the current DFA state
+    fn get_accepted_nfa_idx(&self) -> Option<usize> {
+        self.var_dfa
+            .get_accepted_nfas(self.dfa_state.clone())
+            .iter()
+            .copied()
+            .find(|nfa_idx| self.var_enabled[self.var_dfa_schema_ids[*nfa_idx]])
+    }
+
+    fn get_accepted_var_schema_id(&self) -> Option<usize> {
+        self.get_accepted_nfa_idx()
+            .map(|nfa_idx| self.var_dfa_schema_ids[nfa_idx])
+    }
+
+    // Called when the text between `match_start_pos` and `end_pos` is followed by a delimiter (or
+    // the end of the stream), where `dst_state` is the state the lexer would move to based on the
+    // variable DFA alone. If the text is a literal variable taking precedence over the variable
+    // accepted by the DFA (if any), the literal variable is extracted instead.
+    fn match_literal_var(&mut self, end_pos: usize, dst_state: LexerState) -> LexerState {
+        let len = end_pos - self.match_start_pos;
+        if 0 == len || len > self.max_literal_len {
+            return dst_state;
+        }
+        let text = &self.buf[self.match_start_pos..end_pos];
+        let literal_schema_id = match self.literal_vars.get(text) {
+            Some(schema_id) if self.var_enabled[*schema_id] => *schema_id,
+            _ => return dst_state,
+        };
+        if let LexerState::VarExtract = dst_state {
+            if let Some(schema_id) = self.get_accepted_var_schema_id() {
+                if self.takes_precedence(schema_id, literal_schema_id) {
+                    return dst_state;
+                }
+            }
+        }
+        self.committed_var = Some(literal_schema_id);
+        self.match_end_pos = end_pos;
+        LexerState::VarExtract
+    }
+
+    // Returns whether variable `schema_id` wins over `other_schema_id` when both match the same
+    // input: the higher priority wins, then the first declared
+    fn takes_precedence(&self, schema_id: usize, other_schema_id: usize) -> bool {
+        let var_schemas = self.schema_config.get_var_schemas();
+        let (priority, other_priority) = (
+            var_schemas[schema_id].get_priority(),
+            var_schemas[other_schema_id].get_priority(),
+        );
+        priority > other_priority || (priority == other_priority && schema_id < other_schema_id)
+    }
+
+    fn is_committed_var_anchored(&self) -> bool {
+        match self.committed_var {
+            Some(schema_id) => self.schema_config.get_var_schemas()[schema_id].is_anchored(),
+            None => false,
+        }
+    }
+
     fn proceed_to_var_dfa_simulation(&mut self) {
         self.match_start_pos = self.buf_cursor_pos;
+        self.match_delimiter_pos = None;
+        self.delimited_match = None;
+        self.committed_var = None;
         self.dfa_state = self.var_dfa.get_root();
         self.state = LexerState::DFANotAccepted;
     }
@@ -373,13 +1474,165 @@ impl Lexer {
         if end_pos <= self.last_tokenized_pos {
             return Err(LexerInternalErr("Tokenization end position corrupted"));
         }
+        let mut token_type = self.resolve_timestamp_refs(end_pos, token_type);
+        if (self.keep_delimiters || self.emit_unknown_tokens || self.classify_whitespace)
+            && matches!(
+                token_type,
+                TokenType::StaticText | TokenType::StaticTextWithEndLine
+            )
+        {
+            // Split the static text into runs of whitespace, delimiters, unknown characters and
+            // other text. The last run keeps the original token type, unless it consists of
+            // unknown characters or whitespace without ending the line (a line always ends with a
+            // line separator, which isn't unknown).
+            let mut run = self.get_static_text_run_at(self.last_tokenized_pos);
+            for pos in self.last_tokenized_pos + 1..end_pos {
+                let next_run = self.get_static_text_run_at(pos);
+                if next_run != run
+                    || (StaticTextRun::Delimiters == run && false == self.collapse_delimiters)
+                {
+                    self.push_token(pos, Self::get_static_text_run_token_type(run));
+                    run = next_run;
+                }
+            }
+            if StaticTextRun::Unknown == run {
+                token_type = TokenType::Unknown;
+            } else if StaticTextRun::Whitespace == run
+                && matches!(token_type, TokenType::StaticText)
+            {
+                token_type = TokenType::Whitespace;
+            }
+        }
+        self.push_token(end_pos, token_type);
+        Ok(())
+    }
+
+    // Records the fields captured by a timestamp, and turns a match of a variable referencing
+    // timestamp groups (see `VarSchema::matches_timestamp_refs`) into static text if it doesn't
+    // agree with the last timestamp, or if there's no timestamp yet.
+    fn resolve_timestamp_refs(&mut self, end_pos: usize, token_type: TokenType) -> TokenType {
+        if false == self.has_timestamp_refs {
+            return token_type;
+        }
+        let text: String = self.buf[self.last_tokenized_pos..end_pos].iter().collect();
+        match token_type {
+            TokenType::Timestamp(ts_schema_id) => {
+                self.timestamp_fields = self.schema_config.get_ts_schemas()[ts_schema_id]
+                    .capture_fields(&text)
+                    .ok()
+                    .flatten();
+                token_type
+            }
+            TokenType::Variable(schema_id) => {
+                let var_schema = &self.schema_config.get_var_schemas()[schema_id];
+                let agrees = match &self.timestamp_fields {
+                    Some(timestamp_fields) => var_schema
+                        .matches_timestamp_refs(&text, timestamp_fields)
+                        .unwrap_or(false),
+                    None => var_schema.get_timestamp_refs().is_empty(),
+                };
+                if agrees {
+                    token_type
+                } else {
+                    TokenType::StaticText
+                }
+            }
+            _ => token_type,
+        }
+    }
+
+    fn get_static_text_run_at(&self, pos: usize) -> StaticTextRun {
+        if self.emit_unknown_tokens && false == self.buf[pos].is_ascii() {
+            StaticTextRun::Unknown
+        } else if self.classify_whitespace && self.buf[pos].is_ascii_whitespace() {
+            StaticTextRun::Whitespace
+        } else if self.keep_delimiters && self.is_unescaped_delimiter_at(pos) {
+            StaticTextRun::Delimiters
+        } else {
+            StaticTextRun::Text
+        }
+    }
+
+    fn get_static_text_run_token_type(run: StaticTextRun) -> TokenType {
+        match run {
+            StaticTextRun::Unknown => TokenType::Unknown,
+            StaticTextRun::Whitespace => TokenType::Whitespace,
+            _ => TokenType::StaticText,
+        }
+    }
+
+    fn push_token(&mut self, end_pos: usize, token_type: TokenType) {
+        let chars = &self.buf[self.last_tokenized_pos..end_pos];
+        let (val, mut num_input_bytes) = if self.borrowed_tokens {
+            // The text is sliced from the input by `BorrowedTokens`, so only its length is needed
+            (String::new(), chars.iter().map(|c| c.len_utf8()).sum())
+        } else {
+            let val: String = chars.iter().collect();
+            let num_bytes = val.len();
+            (val, num_bytes)
+        };
+        // The bytes dropped from the input (e.g. the `\r` before a newline) still take space in it
+        let num_stripped_positions = self
+            .buf_stripped_bytes
+            .partition_point(|(pos, _)| *pos < end_pos);
+        num_input_bytes += self
+            .buf_stripped_bytes
+            .drain(..num_stripped_positions)
+            .map(|(_, stripped_bytes)| stripped_bytes)
+            .sum::<usize>();
+        let byte_offset = self.byte_offset;
+        self.byte_offset += num_input_bytes;
+        let column = byte_offset - self.line_start_byte_offset;
+        let char_column = self.line_chars;
+        let last_tab = self.line_last_tab.clone();
+        let mut bytes_after_line_separator = None;
+        for c in chars {
+            if self.line_separator == *c {
+                self.line_chars = 0;
+                self.line_last_tab = None;
+                bytes_after_line_separator = Some(0);
+                continue;
+            }
+            if '\t' == *c {
+                self.line_last_tab = Some(Arc::new(LineTab {
+                    char_column: self.line_chars,
+                    prev: self.line_last_tab.take(),
+                }));
+            }
+            self.line_chars += 1;
+            if let Some(num_bytes) = bytes_after_line_separator.as_mut() {
+                *num_bytes += c.len_utf8();
+            }
+        }
+        if let Some(num_bytes) = bytes_after_line_separator {
+            self.line_start_byte_offset = self.byte_offset - num_bytes;
+        }
+        let transformed_val = match token_type {
+            TokenType::Variable(schema_id) if false == self.borrowed_tokens => {
+                self.schema_config.get_var_schemas()[schema_id]
+                    .get_transform()
+                    .map(|transform| transform.apply(val.as_str()))
+            }
+            _ => None,
+        };
+        let variable_name = match token_type {
+            TokenType::Variable(schema_id) => Some(self.var_names[schema_id].clone()),
+            _ => None,
+        };
         self.token_queue.push_back(Token {
-            val: self.buf[self.last_tokenized_pos..end_pos].iter().collect(),
+            val,
+            transformed_val,
             line_num: self.line_num,
             token_type,
+            byte_offset,
+            num_input_bytes,
+            column,
+            char_column,
+            last_tab,
+            source_id: self.source_id.clone(),
+            variable_name,
         });
         self.last_tokenized_pos = end_pos;
-        Ok(())
     }
 
     fn get_and_increment_buf_cursor_pos(&mut self) -> usize {
@@ -407,6 +1660,15 @@ impl Lexer {
             src_idx += 1;
         }
         self.buf.resize(dst_idx, 0 as char);
+        let last_tokenized_pos = self.last_tokenized_pos;
+        for (pos, _) in self.buf_stripped_bytes.iter_mut() {
+            *pos -= last_tokenized_pos;
+        }
+        self.multibyte_delimiter_spans
+            .retain(|span| span.end > last_tokenized_pos);
+        for span in self.multibyte_delimiter_spans.iter_mut() {
+            *span = span.start.saturating_sub(last_tokenized_pos)..span.end - last_tokenized_pos;
+        }
         self.buf_cursor_pos -= self.last_tokenized_pos;
         self.last_tokenized_pos = 0;
         // No need to reset match_start/end
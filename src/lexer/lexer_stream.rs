@@ -2,4 +2,24 @@ use crate::error_handling::Result;
 
 pub trait LexerStream {
     fn get_next_char(&mut self) -> Result<Option<char>>;
+
+    // Returns the (line number, byte offset) of the first character produced by the stream. Streams
+    // that are a chunk of a larger source can override it so that tokens report their position in
+    // the original source instead of the position within the chunk.
+    fn initial_position(&self) -> (usize, usize) {
+        (1, 0)
+    }
+
+    // Returns the label of the stream (e.g. the name of the source it's read from), which is
+    // attached to each of its tokens to tell apart the tokens of multiplexed streams
+    fn source_id(&self) -> Option<&str> {
+        None
+    }
+
+    // Returns the number of input bytes the stream dropped since the last call (e.g. the `\r` of
+    // CRLF line endings), so that tokens keep reporting their position in the source. Called after
+    // each character is read; the dropped bytes are accounted to that character.
+    fn take_stripped_bytes(&mut self) -> usize {
+        0
+    }
 }
@@ -2,8 +2,14 @@ mod lexer;
 mod lexer_stream;
 mod streams;
 
+pub use lexer::BorrowedTokens;
+pub use lexer::BytePolicy;
+pub use lexer::LexStep;
 pub use lexer::Lexer;
+pub use lexer::MatchPolicy;
 pub use lexer::Token;
+pub use lexer::TokenRef;
 pub use lexer::TokenType;
 pub use lexer_stream::LexerStream;
 pub use streams::BufferedFileStream;
+pub use streams::StringStream;
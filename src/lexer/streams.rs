@@ -1,22 +1,100 @@
 use super::lexer_stream::LexerStream;
 use crate::error_handling::Error::IOError;
 use crate::error_handling::Result;
-use std::io::BufRead;
+use std::io::{BufRead, Read, Seek, SeekFrom};
 
+// Streams the characters of a file line by line. `\r\n` line endings are returned as `\n`, while
+// other line endings are passed through as they are in the file, so the last line has no newline
+// if the file doesn't end with one.
 pub struct BufferedFileStream {
-    line_it: std::io::Lines<std::io::BufReader<std::fs::File>>,
+    reader: Box<dyn BufRead>,
     line: Option<Vec<char>>,
     pos: usize,
+    initial_position: (usize, usize),
+    lossy_utf8: bool,
+    source_id: Option<String>,
+    // Whether the `\r` before the newline ending the current line was dropped
+    line_crlf: bool,
+    stripped_bytes: usize,
 }
 
 impl BufferedFileStream {
     pub fn new(path: &str) -> Result<Self> {
-        match std::fs::File::open(path) {
-            Ok(file) => Ok(Self {
-                line_it: std::io::BufReader::new(file).lines(),
-                line: None,
-                pos: 0,
-            }),
+        Self::new_at(path, 1, 0)
+    }
+
+    // Creates a stream starting at the given byte offset of the file, which must be the start of
+    // the given line. Tokens report their position in the whole file.
+    pub fn new_at(path: &str, line_num: usize, byte_offset: usize) -> Result<Self> {
+        let mut file = std::fs::File::open(path).map_err(IOError)?;
+        if 0 != byte_offset {
+            file.seek(SeekFrom::Start(byte_offset as u64))
+                .map_err(IOError)?;
+        }
+        Ok(Self {
+            reader: Box::new(std::io::BufReader::new(file)),
+            line: None,
+            pos: 0,
+            initial_position: (line_num, byte_offset),
+            lossy_utf8: false,
+            source_id: None,
+            line_crlf: false,
+            stripped_bytes: 0,
+        })
+    }
+
+    // Creates a stream over the lines of a reader, e.g. standard input. The reader is read a line
+    // at a time, so lines are lexed as soon as they're available.
+    pub fn from_reader<R: BufRead + 'static>(reader: R) -> Self {
+        Self {
+            reader: Box::new(reader),
+            line: None,
+            pos: 0,
+            initial_position: (1, 0),
+            lossy_utf8: false,
+            source_id: None,
+            line_crlf: false,
+            stripped_bytes: 0,
+        }
+    }
+
+    // Creates a stream over the decompressed content of a gzip-compressed file (possibly made of
+    // multiple gzip members). Tokens report their position in the decompressed content.
+    #[cfg(feature = "gzip")]
+    pub fn new_gzip(path: &str) -> Result<Self> {
+        let file = std::fs::File::open(path).map_err(IOError)?;
+        Ok(Self {
+            reader: Box::new(std::io::BufReader::new(flate2::read::MultiGzDecoder::new(
+                file,
+            ))),
+            line: None,
+            pos: 0,
+            initial_position: (1, 0),
+            lossy_utf8: false,
+            source_id: None,
+            line_crlf: false,
+            stripped_bytes: 0,
+        })
+    }
+
+    // When set, byte sequences that aren't valid UTF-8 are decoded as `U+FFFD` replacement
+    // characters. By default, they make reading fail with `IOError`.
+    pub fn set_lossy_utf8(&mut self, lossy_utf8: bool) {
+        self.lossy_utf8 = lossy_utf8;
+    }
+
+    // Sets the label attached to the tokens of the stream (see `LexerStream::source_id`)
+    pub fn set_source_id(&mut self, source_id: &str) {
+        self.source_id = Some(source_id.to_string());
+    }
+
+    // Returns whether the file starts with the gzip magic bytes
+    pub fn is_gzip_file(path: &str) -> Result<bool> {
+        let mut file = std::fs::File::open(path).map_err(IOError)?;
+        let mut magic = [0u8; 2];
+        match file.read_exact(&mut magic) {
+            Ok(()) => Ok([0x1f, 0x8b] == magic),
+            Err(e) if std::io::ErrorKind::UnexpectedEof == e.kind() => Ok(false),
             Err(e) => Err(IOError(e)),
         }
     }
@@ -25,25 +103,93 @@ impl BufferedFileStream {
 impl LexerStream for BufferedFileStream {
     fn get_next_char(&mut self) -> Result<Option<char>> {
         if self.line.is_none() {
-            let next_line = self.line_it.next();
-            if next_line.is_none() {
+            let mut line = Vec::new();
+            let num_bytes = self.reader.read_until(b'\n', &mut line).map_err(IOError)?;
+            if 0 == num_bytes {
                 return Ok(None);
             }
-            match next_line.unwrap() {
-                Ok(line) => {
-                    self.line = Some(line.chars().collect());
-                    self.line.as_mut().unwrap().push('\n');
-                    self.pos = 0;
-                }
-                Err(e) => return Err(IOError(e)),
+            self.line_crlf = line.ends_with(b"\r\n");
+            if self.line_crlf {
+                line.remove(line.len() - 2);
             }
+            let line = if self.lossy_utf8 {
+                String::from_utf8_lossy(&line).into_owned()
+            } else {
+                String::from_utf8(line)
+                    .map_err(|e| IOError(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?
+            };
+            self.line = Some(line.chars().collect());
+            self.pos = 0;
         }
 
         let c = self.line.as_ref().unwrap()[self.pos];
         self.pos += 1;
         if self.pos == self.line.as_ref().unwrap().len() {
             self.line = None;
+            if self.line_crlf {
+                self.stripped_bytes += '\r'.len_utf8();
+            }
         }
         Ok(Some(c))
     }
+
+    fn initial_position(&self) -> (usize, usize) {
+        self.initial_position
+    }
+
+    fn source_id(&self) -> Option<&str> {
+        self.source_id.as_deref()
+    }
+
+    fn take_stripped_bytes(&mut self) -> usize {
+        std::mem::take(&mut self.stripped_bytes)
+    }
+}
+
+// Streams the characters of an in-memory string.
+pub struct StringStream {
+    input: String,
+    pos: usize,
+    initial_position: (usize, usize),
+    source_id: Option<String>,
+}
+
+impl StringStream {
+    pub fn new(input: &str) -> Self {
+        Self {
+            input: input.to_string(),
+            pos: 0,
+            initial_position: (1, 0),
+            source_id: None,
+        }
+    }
+
+    // Sets the label attached to the tokens of the stream (see `LexerStream::source_id`)
+    pub fn set_source_id(&mut self, source_id: &str) {
+        self.source_id = Some(source_id.to_string());
+    }
+
+    // Sets the position of the string in the source it is a chunk of (see
+    // `LexerStream::initial_position`)
+    pub fn set_initial_position(&mut self, line_num: usize, byte_offset: usize) {
+        self.initial_position = (line_num, byte_offset);
+    }
+}
+
+impl LexerStream for StringStream {
+    fn get_next_char(&mut self) -> Result<Option<char>> {
+        let c = self.input[self.pos..].chars().next();
+        if let Some(c) = c {
+            self.pos += c.len_utf8();
+        }
+        Ok(c)
+    }
+
+    fn initial_position(&self) -> (usize, usize) {
+        self.initial_position
+    }
+
+    fn source_id(&self) -> Option<&str> {
+        self.source_id.as_deref()
+    }
 }
@@ -1,8 +1,8 @@
-mod dfa;
+pub mod dfa;
 pub mod error_handling;
 pub mod lexer;
 pub mod log_parser;
-mod nfa;
+pub mod nfa;
 pub mod parser;
 
 const VERSION: &str = "0.0.1";
@@ -1,79 +1,805 @@
-use crate::error_handling::Error::LogParserInternalErr;
-use crate::error_handling::Result;
+use crate::error_handling::Error::{
+    IOError, LimitExceeded, LogParserInternalErr, MissingTimestamp, UnseekableInputStream,
+};
+use crate::error_handling::{LimitKind, Result};
 use crate::lexer::BufferedFileStream;
+use crate::lexer::BytePolicy;
 use crate::lexer::LexerStream;
 use crate::lexer::{Lexer, Token, TokenType};
 use crate::parser::SchemaConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
-use std::rc::Rc;
+use std::io::{BufRead, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::Arc;
 
 pub struct LogParser {
     lexer: Lexer,
-    schema_config: Rc<SchemaConfig>,
+    schema_config: Arc<SchemaConfig>,
+    // Path of the input file, if the input was set with `set_input_file`
+    input_file_path: Option<String>,
     tokens: Option<Vec<Token>>,
+    // Number of tokens and total byte size of the current log event
+    event_num_tokens: usize,
+    event_bytes: usize,
+    // Whether tokens of the current log event have been passed to `for_each_token_of_event`
+    // without the end of the event being signaled yet
+    streaming_event_open: bool,
+    max_tokens_per_event: Option<usize>,
+    max_event_bytes: Option<usize>,
+    max_continuation_lines: Option<usize>,
+    // Number of lines of the current event after its first one
+    event_continuation_lines: usize,
+    // Set when the current event was cut by `max_event_bytes` or `max_continuation_lines` rather
+    // than ended by a timestamp or the end of the input
+    event_truncated: bool,
+
+    // Set when a limit is exceeded or a required timestamp is missing, so that the remaining tokens
+    // are dropped until the next timestamp
+    skipping_event: bool,
+
+    // Whether the next token starts a new line
+    at_line_start: bool,
+    // Set when the indentation (or the leading tokens, see `set_timestamp_lookahead_tokens`) of a
+    // line started an event, so that the timestamp following it continues that event
+    indented_timestamp: bool,
+
+    skip_blank_lines: bool,
+    // Whether events without a timestamp report the timestamp of the last event that had one
+    inherit_timestamp: bool,
+    last_timestamp: Option<Token>,
+    // Whether the next token pulled from the lexer starts a new line. This is ahead of
+    // `at_line_start` while blank lines are held.
+    lexer_at_line_start: bool,
+    // Blank lines held until the next non-blank line tells whether they're inside an event
+    blank_lines: Vec<Token>,
+    // Held blank lines released into the current event, followed by the token that released them
+    released_tokens: VecDeque<Token>,
+
+    progress_callback: Option<Box<dyn FnMut(ParseProgress)>>,
+    progress_interval_lines: usize,
+    lines_since_progress: usize,
+    num_events_emitted: usize,
+    stats: ParseStats,
+}
+
+// Parsing progress reported to the progress callback
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ParseProgress {
+    pub bytes_read: usize,
+    pub line_num: usize,
+    pub num_events_emitted: usize,
+}
+
+// Matching statistics over the tokens of the emitted log events, to help tune a schema (e.g. to
+// spot variables that never match, or catch-all variables matching too much)
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ParseStats {
+    // Number of tokens matched by each variable, indexed by variable schema ID
+    pub var_matches: Vec<usize>,
+    pub timestamp_matches: usize,
+    // Bytes of the tokens not matched by any variable or timestamp, delimiters included
+    pub static_text_bytes: usize,
+}
+
+impl ParseStats {
+    fn new(num_vars: usize) -> Self {
+        Self {
+            var_matches: vec![0; num_vars],
+            ..Default::default()
+        }
+    }
+
+    fn record(&mut self, token: &Token) {
+        match token.get_token_type() {
+            TokenType::Variable(schema_id) => self.var_matches[schema_id] += 1,
+            TokenType::Timestamp(_) => self.timestamp_matches += 1,
+            _ => self.static_text_bytes += token.get_val().len(),
+        }
+    }
+}
+
+// A token pulled by the parse loop, along with whether it starts a new log event
+enum EventToken {
+    Start(Token),
+    Continue(Token),
 }
 
 pub struct LogEvent {
     tokens: Vec<Token>,
     line_range: (usize, usize),
-    has_timestamp: bool,
-    schema_config: Rc<SchemaConfig>,
+    // Index of the timestamp token, which is preceded by the indentation or the leading tokens of
+    // the line (if any)
+    timestamp_idx: Option<usize>,
+    // Timestamp of the last event that had one, for an event without a timestamp parsed with
+    // `LogParser::inherit_timestamp`
+    inherited_timestamp: Option<Token>,
+    truncated: bool,
+    schema_config: Arc<SchemaConfig>,
+}
+
+// A token of a log event along with the name of the variable it matched (if any) and its byte
+// range in the input
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TokenAnnotation<'a> {
+    text: &'a str,
+    variable_name: Option<&'a str>,
+    byte_range: Range<usize>,
+}
+
+impl<'a> TokenAnnotation<'a> {
+    pub fn get_text(&self) -> &'a str {
+        self.text
+    }
+
+    // Returns the name of the matched variable, or `None` for static text and timestamps
+    pub fn get_variable_name(&self) -> Option<&'a str> {
+        self.variable_name
+    }
+
+    pub fn get_byte_range(&self) -> Range<usize> {
+        self.byte_range.clone()
+    }
+}
+
+// A self-contained copy of a log event (see `LogEvent::to_dto`) that can be serialized, e.g. to
+// ship parsed events to another process. Unlike `LogEvent`, it doesn't reference the schema.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LogEventDto {
+    pub tokens: Vec<TokenDto>,
+    pub line_range: (usize, usize),
+    // Index of the timestamp in `tokens`, or `None` if the event has no timestamp
+    pub timestamp_idx: Option<usize>,
+    pub truncated: bool,
+}
+
+// A token of a `LogEventDto`
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TokenDto {
+    // The token's text as it appears in the input
+    pub text: String,
+    // The name of the matched variable, or `None` for static text and timestamps
+    pub variable_name: Option<String>,
+    pub line_num: usize,
+    pub byte_offset: usize,
+}
+
+impl LogEventDto {
+    // Returns `None` rather than panicking if a deserialized DTO has an out-of-range index
+    pub fn get_timestamp_token(&self) -> Option<&TokenDto> {
+        self.tokens.get(self.timestamp_idx?)
+    }
+}
+
+// The CLP encoding of a log message (see `LogEvent::to_clp_ir`): the logtype is the static text
+// with a placeholder where each variable appeared, and the variables are the matched values in
+// order of appearance
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClpIr {
+    logtype: String,
+    variables: Vec<String>,
+}
+
+impl ClpIr {
+    pub fn get_logtype(&self) -> &str {
+        &self.logtype
+    }
+
+    pub fn get_variables(&self) -> &[String] {
+        &self.variables
+    }
 }
 
 impl LogParser {
-    pub fn new(schema_config: Rc<SchemaConfig>) -> Result<Self> {
+    pub const DEFAULT_PROGRESS_INTERVAL_LINES: usize = 1000;
+
+    pub fn new(schema_config: Arc<SchemaConfig>) -> Result<Self> {
         let lexer = Lexer::new(schema_config.clone())?;
+        let stats = ParseStats::new(schema_config.get_var_schemas().len());
         Ok((Self {
             lexer,
             schema_config,
-            tokens: Some(Vec::new()),
+            input_file_path: None,
+            tokens: None,
+            event_num_tokens: 0,
+            event_bytes: 0,
+            streaming_event_open: false,
+            max_tokens_per_event: None,
+            max_event_bytes: None,
+            max_continuation_lines: None,
+            event_continuation_lines: 0,
+            event_truncated: false,
+            skipping_event: false,
+            at_line_start: true,
+            indented_timestamp: false,
+            skip_blank_lines: false,
+            inherit_timestamp: false,
+            last_timestamp: None,
+            lexer_at_line_start: true,
+            blank_lines: Vec::new(),
+            released_tokens: VecDeque::new(),
+            progress_callback: None,
+            progress_interval_lines: Self::DEFAULT_PROGRESS_INTERVAL_LINES,
+            lines_since_progress: 0,
+            num_events_emitted: 0,
+            stats,
         }))
     }
 
+    // Creates a parser that rejects log events with more than `max_tokens_per_event` tokens or
+    // lines longer than `max_bytes_per_line` bytes. When a limit is exceeded, `LimitExceeded` is
+    // returned and the offending event is skipped, so the caller can keep parsing.
+    pub fn with_limits(
+        schema_config: Arc<SchemaConfig>,
+        max_tokens_per_event: usize,
+        max_bytes_per_line: usize,
+    ) -> Result<Self> {
+        let mut log_parser = Self::new(schema_config)?;
+        log_parser.max_tokens_per_event = Some(max_tokens_per_event);
+        log_parser
+            .lexer
+            .set_max_line_bytes(Some(max_bytes_per_line));
+        Ok(log_parser)
+    }
+
+    // Caps the number of bytes buffered for a single log event. When buffering the next token would
+    // exceed the cap, the buffered tokens are emitted as a (partial) log event and parsing resumes
+    // with the next token. Tokens are never split, so an event only exceeds the cap if it consists
+    // of a single token larger than the cap.
+    pub fn set_max_event_bytes(&mut self, max_event_bytes: usize) {
+        self.max_event_bytes = Some(max_event_bytes);
+    }
+
+    // Caps the number of continuation lines (lines without a timestamp, e.g. the frames of a stack
+    // trace) attached to a single log event. The line that would exceed the cap starts a new event
+    // without a timestamp, and the event it would have continued is marked as truncated.
+    pub fn set_max_continuation_lines(&mut self, max_continuation_lines: usize) {
+        self.max_continuation_lines = Some(max_continuation_lines);
+    }
+
+    // When enabled, runs of delimiters in static text are emitted as their own static-text tokens
+    // with their own line numbers and byte offsets. By default, delimiters are folded into the
+    // surrounding static text.
+    pub fn keep_delimiters(&mut self, keep_delimiters: bool) {
+        self.lexer.set_keep_delimiters(keep_delimiters);
+    }
+
+    // When enabled (the default), each run of consecutive delimiters emitted by `keep_delimiters`
+    // is a single token, e.g. the spaces aligning the fields of `a    b`. When disabled, every
+    // delimiter is its own token, which keeps the blank fields between adjacent delimiters.
+    pub fn collapse_delimiters(&mut self, collapse_delimiters: bool) {
+        self.lexer.set_collapse_delimiters(collapse_delimiters);
+    }
+
+    // When enabled, characters no variable or timestamp can match (anything outside of ASCII) are
+    // emitted as `TokenType::Unknown` tokens, so that the affected log events can be flagged.
+    pub fn emit_unknown_tokens(&mut self, emit_unknown_tokens: bool) {
+        self.lexer.set_emit_unknown_tokens(emit_unknown_tokens);
+    }
+
+    // When enabled, blank lines (made only of delimiters and whitespace) between log events are
+    // dropped, so they neither produce tokens nor extend the line range of the preceding event.
+    // Blank lines followed by a continuation line are inside an event, so they're kept.
+    pub fn skip_blank_lines(&mut self, skip_blank_lines: bool) {
+        self.skip_blank_lines = skip_blank_lines;
+    }
+
+    // When enabled, log events without a timestamp (e.g. continuation lines split off by
+    // `set_max_continuation_lines`) report the timestamp of the last event that had one through
+    // `LogEvent::effective_timestamp`. `LogEvent::get_timestamp_token` still returns `None` for
+    // them.
+    pub fn inherit_timestamp(&mut self, inherit_timestamp: bool) {
+        self.inherit_timestamp = inherit_timestamp;
+    }
+
+    // When enabled, runs of whitespace in static text are emitted as `TokenType::Whitespace` tokens
+    // (see `Lexer::set_classify_whitespace`)
+    pub fn classify_whitespace(&mut self, classify_whitespace: bool) {
+        self.lexer.set_classify_whitespace(classify_whitespace);
+    }
+
+    // Disables (or re-enables) the named variable from the next log event on (see
+    // `Lexer::set_variable_enabled`)
+    pub fn set_variable_enabled(&mut self, name: &str, enabled: bool) -> Result<()> {
+        self.lexer.set_variable_enabled(name, enabled)
+    }
+
+    // Lets a timestamp following up to `timestamp_lookahead_tokens` tokens at the start of a line
+    // (e.g. `host1 2024-01-01 12:00:00 msg`) start a new log event along with them (see
+    // `Lexer::set_timestamp_lookahead_tokens`)
+    pub fn set_timestamp_lookahead_tokens(&mut self, timestamp_lookahead_tokens: usize) {
+        self.lexer
+            .set_timestamp_lookahead_tokens(timestamp_lookahead_tokens);
+    }
+
+    // Registers a callback invoked from the parse loop every `set_progress_interval_lines` lines
+    // (1000 by default). It is never invoked once the end of the input is reached.
+    pub fn set_progress_callback(&mut self, progress_callback: Box<dyn FnMut(ParseProgress)>) {
+        self.progress_callback = Some(progress_callback);
+    }
+
+    pub fn clear_progress_callback(&mut self) {
+        self.progress_callback = None;
+    }
+
+    pub fn set_progress_interval_lines(&mut self, progress_interval_lines: usize) {
+        self.progress_interval_lines = progress_interval_lines.max(1);
+    }
+
+    // Sets how non-ASCII characters in the input are handled (see `BytePolicy`). With a policy set,
+    // bytes of input files that aren't valid UTF-8 are decoded as `U+FFFD` replacement characters
+    // instead of failing the parse, so the policy also applies to them. Byte offsets then count the
+    // 3 bytes of each replacement character rather than the invalid bytes it replaced. By default,
+    // non-ASCII characters are kept as static text.
+    pub fn set_byte_policy(&mut self, byte_policy: Option<BytePolicy>) {
+        self.lexer.set_byte_policy(byte_policy);
+    }
+
+    // Returns the matching statistics over the log events emitted since the input was set
+    pub fn stats(&self) -> &ParseStats {
+        &self.stats
+    }
+
+    // Sets the input to the given file. Gzip-compressed files (detected by their magic bytes) are
+    // decompressed on the fly when the `gzip` feature is enabled; line numbers then count lines of
+    // the decompressed content. Compressed files can't be seeked, so `parse_line_range` isn't
+    // supported for them.
     pub fn set_input_file(&mut self, path: &str) -> Result<()> {
-        self.tokens = Some(Vec::new());
-        let buffered_file_stream = Box::new(BufferedFileStream::new(path)?);
-        self.set_input_stream(buffered_file_stream)
+        if BufferedFileStream::is_gzip_file(path)? {
+            return self.set_input_gzip_file(path);
+        }
+        let mut buffered_file_stream = BufferedFileStream::new(path)?;
+        buffered_file_stream.set_lossy_utf8(self.lexer.get_byte_policy().is_some());
+        self.set_input_stream(Box::new(buffered_file_stream))?;
+        self.input_file_path = Some(path.to_string());
+        Ok(())
+    }
+
+    #[cfg(feature = "gzip")]
+    fn set_input_gzip_file(&mut self, path: &str) -> Result<()> {
+        let mut buffered_file_stream = BufferedFileStream::new_gzip(path)?;
+        buffered_file_stream.set_lossy_utf8(self.lexer.get_byte_policy().is_some());
+        self.set_input_stream(Box::new(buffered_file_stream))
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    fn set_input_gzip_file(&mut self, _path: &str) -> Result<()> {
+        Err(IOError(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "gzip-compressed input requires the `gzip` feature",
+        )))
+    }
+
+    // Sets the input to the lines of the given reader (see `BufferedFileStream::from_reader`).
+    // Like files, invalid UTF-8 is replaced when a byte policy is set.
+    pub fn set_input_reader<R: BufRead + 'static>(&mut self, reader: R) -> Result<()> {
+        let mut buffered_file_stream = BufferedFileStream::from_reader(reader);
+        buffered_file_stream.set_lossy_utf8(self.lexer.get_byte_policy().is_some());
+        self.set_input_stream(Box::new(buffered_file_stream))
+    }
+
+    // Sets the input to the standard input, e.g. for `cat app.log | tool`. Events are returned as
+    // soon as the line starting the next one is read, so the input can be tailed.
+    pub fn set_input_stdin(&mut self) -> Result<()> {
+        self.set_input_reader(std::io::stdin().lock())
     }
 
     pub fn set_input_stream(&mut self, input_stream: Box<dyn LexerStream>) -> Result<()> {
+        self.input_file_path = None;
+        self.tokens = None;
+        self.event_num_tokens = 0;
+        self.event_bytes = 0;
+        self.event_continuation_lines = 0;
+        self.streaming_event_open = false;
+        self.event_truncated = false;
+        self.skipping_event = false;
+        self.at_line_start = true;
+        self.indented_timestamp = false;
+        self.lexer_at_line_start = true;
+        self.blank_lines.clear();
+        self.released_tokens.clear();
+        self.last_timestamp = None;
+        self.lines_since_progress = 0;
+        self.num_events_emitted = 0;
+        self.stats = ParseStats::new(self.schema_config.get_var_schemas().len());
         self.lexer.set_input_stream(input_stream);
         Ok(())
     }
 
     pub fn parse_next_log_event(&mut self) -> Result<Option<LogEvent>> {
         loop {
-            match self.lexer.get_next_token()? {
-                Some(token) => match token.get_token_type() {
-                    TokenType::Timestamp(_) => {
-                        if self.tokens.is_none() {
-                            self.buffer_token(token);
-                            continue;
-                        }
-                        let log_event = self.emit_buffered_tokens_as_log_event()?;
-                        self.buffer_token(token);
+            match self.next_event_token()? {
+                Some(EventToken::Start(token)) => {
+                    let log_event = self.emit_buffered_tokens_as_log_event()?;
+                    // Size the buffer after the previous event, to avoid growing it token by token
+                    let capacity = log_event
+                        .as_ref()
+                        .map_or(1, |log_event| log_event.tokens.len());
+                    let mut tokens = Vec::with_capacity(capacity);
+                    tokens.push(token);
+                    self.tokens = Some(tokens);
+                    if log_event.is_some() {
                         return Ok(log_event);
                     }
-                    _ => self.buffer_token(token),
-                },
-                None => break,
+                }
+                Some(EventToken::Continue(token)) => {
+                    self.tokens.get_or_insert_with(Vec::new).push(token)
+                }
+                None => return self.emit_buffered_tokens_as_log_event(),
             }
         }
-        self.emit_buffered_tokens_as_log_event()
     }
 
-    fn buffer_token(&mut self, token: Token) {
-        if self.tokens.is_none() {
-            self.tokens = Some(Vec::new());
+    // Parses the rest of the input into log events, stopping at the first error. Every event (and
+    // all of its tokens) is held in memory at once, so for huge inputs, prefer pulling events with
+    // `parse_next_log_event` or streaming them with `for_each_token_of_event`.
+    pub fn parse_all(&mut self) -> Result<Vec<LogEvent>> {
+        let mut log_events = Vec::new();
+        while let Some(log_event) = self.parse_next_log_event()? {
+            log_events.push(log_event);
         }
-        self.tokens.as_mut().unwrap().push(token);
+        Ok(log_events)
+    }
+
+    // Streams the tokens of the next log event to the callback as they're lexed, without buffering
+    // the event, so memory stays bounded however large the event is. Returns `true` once the end
+    // of the event is reached, or `false` if the input is exhausted without any event left. The
+    // token starting the following event is held until the next call. Limits and the timestamp
+    // requirement are applied like in `parse_next_log_event`; since tokens are passed on right
+    // away, an event that fails with `LimitExceeded` has already been partially streamed.
+    pub fn for_each_token_of_event<F: FnMut(Token)>(&mut self, mut callback: F) -> Result<bool> {
+        if let Some(tokens) = self.tokens.take() {
+            for token in tokens {
+                self.stats.record(&token);
+                callback(token);
+                self.streaming_event_open = true;
+            }
+        }
+        loop {
+            match self.next_event_token()? {
+                Some(EventToken::Start(token)) if self.streaming_event_open => {
+                    self.tokens = Some(vec![token]);
+                    self.streaming_event_open = false;
+                    self.event_truncated = false;
+                    self.num_events_emitted += 1;
+                    return Ok(true);
+                }
+                Some(EventToken::Start(token)) | Some(EventToken::Continue(token)) => {
+                    self.stats.record(&token);
+                    callback(token);
+                    self.streaming_event_open = true;
+                }
+                None => {
+                    let event_ended = self.streaming_event_open;
+                    if event_ended {
+                        self.streaming_event_open = false;
+                        self.num_events_emitted += 1;
+                    }
+                    return Ok(event_ended);
+                }
+            }
+        }
+    }
+
+    // Pulls the next token from the lexer and decides whether it starts a new log event, applying
+    // the limits and the timestamp requirement. Returns `None` at the end of the input.
+    fn next_event_token(&mut self) -> Result<Option<EventToken>> {
+        loop {
+            let Some(token) = self.pull_token()? else {
+                return Ok(None);
+            };
+            let at_line_start = self.at_line_start;
+            self.at_line_start = matches!(token.get_token_type(), TokenType::StaticTextWithEndLine);
+            if self.at_line_start {
+                self.report_progress(&token);
+            }
+            if at_line_start && self.may_precede_timestamp(&token) {
+                // An indented (or prefixed) timestamp starts a new event along with the text
+                // before it
+                if self.is_followed_by_timestamp()? {
+                    self.skipping_event = false;
+                    self.indented_timestamp = true;
+                    return Ok(Some(self.start_event(token)));
+                }
+            }
+            if at_line_start && self.is_missing_required_timestamp(&token) {
+                // Drop the line (and its continuation lines) but keep the previous event
+                self.skipping_event = true;
+                return Err(MissingTimestamp {
+                    line: token.get_line_num(),
+                });
+            }
+            if let TokenType::Timestamp(_) = token.get_token_type() {
+                if false == std::mem::take(&mut self.indented_timestamp) {
+                    self.skipping_event = false;
+                    return Ok(Some(self.start_event(token)));
+                }
+            }
+            if self.skipping_event {
+                continue;
+            }
+            if self.exceeds_max_event_bytes(&token) {
+                self.event_truncated = true;
+                return Ok(Some(self.start_event(token)));
+            }
+            if at_line_start && 0 != self.event_num_tokens {
+                if Some(self.event_continuation_lines) == self.max_continuation_lines {
+                    self.event_truncated = true;
+                    return Ok(Some(self.start_event(token)));
+                }
+                self.event_continuation_lines += 1;
+            }
+            if 0 == self.event_num_tokens {
+                return Ok(Some(self.start_event(token)));
+            }
+            if let Some(limit) = self.max_tokens_per_event {
+                if self.event_num_tokens >= limit {
+                    self.skip_current_event();
+                    return Err(LimitExceeded {
+                        kind: LimitKind::TokensPerEvent,
+                        limit,
+                    });
+                }
+            }
+            self.event_num_tokens += 1;
+            self.event_bytes += token.get_val().len();
+            return Ok(Some(EventToken::Continue(token)));
+        }
+    }
+
+    // Pulls the next token, skipping the current event when a limit is exceeded
+    fn pull_token(&mut self) -> Result<Option<Token>> {
+        match self.next_lexer_token() {
+            Err(LimitExceeded { kind, limit }) => {
+                self.skip_current_event();
+                Err(LimitExceeded { kind, limit })
+            }
+            result => result,
+        }
+    }
+
+    // Pulls the next token from the lexer, dropping blank lines between log events if enabled.
+    // Blank lines are held until the next non-blank line: they're released in order if that line
+    // continues the current event, and dropped otherwise (or at the end of the input).
+    fn next_lexer_token(&mut self) -> Result<Option<Token>> {
+        if let Some(token) = self.released_tokens.pop_front() {
+            return Ok(Some(token));
+        }
+        loop {
+            let Some(token) = self.lexer.get_next_token()? else {
+                self.drop_blank_lines();
+                return Ok(None);
+            };
+            let at_line_start = self.lexer_at_line_start;
+            self.lexer_at_line_start =
+                matches!(token.get_token_type(), TokenType::StaticTextWithEndLine);
+            if false == self.skip_blank_lines {
+                return Ok(Some(token));
+            }
+            if at_line_start && self.is_blank_line(&token) {
+                self.blank_lines.push(token);
+                continue;
+            }
+            if self.blank_lines.is_empty() {
+                return Ok(Some(token));
+            }
+
+            let continues_event = false
+                == matches!(token.get_token_type(), TokenType::Timestamp(_))
+                && 0 != self.event_num_tokens
+                && false == self.skipping_event;
+            if false == continues_event {
+                self.drop_blank_lines();
+                return Ok(Some(token));
+            }
+            self.released_tokens.extend(self.blank_lines.drain(..));
+            self.released_tokens.push_back(token);
+            return Ok(self.released_tokens.pop_front());
+        }
+    }
+
+    // Returns whether the given token, which must start a line, is a whole line made only of
+    // delimiters and whitespace
+    fn is_blank_line(&self, token: &Token) -> bool {
+        matches!(token.get_token_type(), TokenType::StaticTextWithEndLine)
+            && token
+                .get_val()
+                .chars()
+                .all(|c| c.is_whitespace() || self.schema_config.has_delimiter(c))
+    }
+
+    // Returns whether the given token, which must start a line, only consists of whitespace and
+    // delimiters without ending the line, as the lexer emits before an indented timestamp
+    fn is_indentation(&self, token: &Token) -> bool {
+        matches!(
+            token.get_token_type(),
+            TokenType::StaticText | TokenType::Whitespace
+        ) && token
+            .get_val()
+            .chars()
+            .all(|c| c.is_whitespace() || self.schema_config.has_delimiter(c))
+    }
+
+    // Returns whether the given token, which must start a line, may be followed by a timestamp on
+    // that line: its indentation, or with `set_timestamp_lookahead_tokens`, any static text
+    fn may_precede_timestamp(&self, token: &Token) -> bool {
+        if self.is_indentation(token) {
+            return true;
+        }
+        0 != self.lexer.get_timestamp_lookahead_tokens()
+            && matches!(
+                token.get_token_type(),
+                TokenType::StaticText | TokenType::Whitespace
+            )
+    }
+
+    // Pulls the tokens following a token that may precede a timestamp, as long as they may precede
+    // one as well, and returns whether a timestamp follows them. The pulled tokens are released to
+    // be processed next.
+    fn is_followed_by_timestamp(&mut self) -> Result<bool> {
+        let mut next_tokens = Vec::new();
+        let mut followed_by_timestamp = false;
+        while let Some(next_token) = self.pull_token()? {
+            followed_by_timestamp = matches!(next_token.get_token_type(), TokenType::Timestamp(_));
+            let may_precede_timestamp = self.may_precede_timestamp(&next_token);
+            next_tokens.push(next_token);
+            if followed_by_timestamp || false == may_precede_timestamp {
+                break;
+            }
+        }
+        for next_token in next_tokens.into_iter().rev() {
+            self.released_tokens.push_front(next_token);
+        }
+        Ok(followed_by_timestamp)
+    }
+
+    fn drop_blank_lines(&mut self) {
+        for token in std::mem::take(&mut self.blank_lines) {
+            // Dropped lines still count towards the parsing progress
+            self.report_progress(&token);
+        }
+    }
+
+    fn start_event(&mut self, token: Token) -> EventToken {
+        self.event_num_tokens = 1;
+        self.event_bytes = token.get_val().len();
+        self.event_continuation_lines = 0;
+        EventToken::Start(token)
+    }
+
+    // Parses the log events overlapping lines `start` to `end` (1-based, inclusive) of the input
+    // file, without tokenizing the lines before them. Parsing starts from the closest line at or
+    // before `start` that begins with a timestamp, so that the first event is complete even if it
+    // starts before `start`. Only supported for inputs set with `set_input_file`; otherwise
+    // `UnseekableInputStream` is returned. Afterwards, the input is reset to the start of the file.
+    pub fn parse_line_range(&mut self, start: usize, end: usize) -> Result<Vec<LogEvent>> {
+        let path = match &self.input_file_path {
+            Some(path) => path.clone(),
+            None => return Err(UnseekableInputStream),
+        };
+        if 0 == start || start > end {
+            return Ok(Vec::new());
+        }
+
+        // Byte offsets of the lines up to `start`, found by scanning for line separators
+        let line_separator = self.schema_config.get_line_separator() as u8;
+        let mut reader = std::io::BufReader::new(std::fs::File::open(&path).map_err(IOError)?);
+        let mut line_offsets = vec![0usize];
+        let mut line = Vec::new();
+        while line_offsets.len() < start {
+            line.clear();
+            let num_bytes = reader
+                .read_until(line_separator, &mut line)
+                .map_err(IOError)?;
+            if 0 == num_bytes {
+                return Ok(Vec::new());
+            }
+            line_offsets.push(line_offsets.last().unwrap() + num_bytes);
+        }
+
+        // Scan back to the line starting the event that contains `start`
+        let mut first_line = start;
+        while first_line > 1 {
+            reader
+                .seek(SeekFrom::Start(line_offsets[first_line - 1] as u64))
+                .map_err(IOError)?;
+            line.clear();
+            reader
+                .read_until(line_separator, &mut line)
+                .map_err(IOError)?;
+            if self
+                .lexer
+                .starts_with_timestamp(String::from_utf8_lossy(&line).as_ref())
+            {
+                break;
+            }
+            first_line -= 1;
+        }
+
+        let stream = BufferedFileStream::new_at(&path, first_line, line_offsets[first_line - 1])?;
+        self.set_input_stream(Box::new(stream))?;
+
+        let mut log_events = Vec::new();
+        while let Some(log_event) = self.parse_next_log_event()? {
+            let (line_begin, line_end) = log_event.get_line_range();
+            if line_begin > end {
+                break;
+            }
+            if line_end >= start {
+                log_events.push(log_event);
+            }
+        }
+        self.set_input_file(&path)?;
+        Ok(log_events)
+    }
+
+    // Returns whether the given token, which must start a line, starts a new log event without a
+    // timestamp while the schema requires one. Lines starting with whitespace, as well as empty
+    // lines, are treated as continuations of the previous event.
+    fn is_missing_required_timestamp(&self, token: &Token) -> bool {
+        if false == self.schema_config.is_timestamp_required() {
+            return false;
+        }
+        if let TokenType::Timestamp(_) = token.get_token_type() {
+            return false;
+        }
+        false == token.get_val().starts_with(char::is_whitespace)
+    }
+
+    // Returns whether adding the given token to the current event would exceed the maximum event
+    // size. An empty event never exceeds it, so that a single oversized token still makes progress.
+    fn exceeds_max_event_bytes(&self, token: &Token) -> bool {
+        match self.max_event_bytes {
+            Some(max_event_bytes) => {
+                0 != self.event_num_tokens
+                    && self.event_bytes + token.get_val().len() > max_event_bytes
+            }
+            None => false,
+        }
+    }
+
+    // Invoked for every line-ending token; calls the progress callback every
+    // `progress_interval_lines` lines
+    fn report_progress(&mut self, token: &Token) {
+        let Some(progress_callback) = self.progress_callback.as_mut() else {
+            return;
+        };
+        self.lines_since_progress += 1;
+        if self.lines_since_progress < self.progress_interval_lines {
+            return;
+        }
+        self.lines_since_progress = 0;
+        progress_callback(ParseProgress {
+            bytes_read: token.get_byte_offset() + token.get_val().len(),
+            line_num: token.get_line_num(),
+            num_events_emitted: self.num_events_emitted,
+        });
+    }
+
+    fn skip_current_event(&mut self) {
+        self.tokens = None;
+        self.event_num_tokens = 0;
+        self.event_bytes = 0;
+        self.skipping_event = true;
     }
 
     fn emit_buffered_tokens_as_log_event(&mut self) -> Result<Option<LogEvent>> {
         match &self.tokens {
             Some(_) => {
                 let tokens = self.tokens.take().unwrap();
-                LogEvent::new(self.schema_config.clone(), tokens)
+                self.num_events_emitted += 1;
+                tokens.iter().for_each(|token| self.stats.record(token));
+                let truncated = std::mem::take(&mut self.event_truncated);
+                let mut log_event = LogEvent::new(self.schema_config.clone(), tokens, truncated)?;
+                if let Some(log_event) = log_event.as_mut().filter(|_| self.inherit_timestamp) {
+                    match log_event.get_timestamp_token() {
+                        Some(ts_token) => self.last_timestamp = Some(ts_token.clone()),
+                        None => log_event.inherited_timestamp = self.last_timestamp.clone(),
+                    }
+                }
+                Ok(log_event)
             }
             None => Ok(None),
         }
@@ -81,14 +807,21 @@ impl LogParser {
 }
 
 impl LogEvent {
-    fn new(schema_config: Rc<SchemaConfig>, tokens: Vec<Token>) -> Result<Option<Self>> {
+    pub const CLP_VARIABLE_PLACEHOLDER: char = '\x11';
+    pub const CLP_ESCAPE_CHAR: char = '\\';
+
+    fn new(
+        schema_config: Arc<SchemaConfig>,
+        tokens: Vec<Token>,
+        truncated: bool,
+    ) -> Result<Option<Self>> {
         if tokens.is_empty() {
             return Err(LogParserInternalErr("The given token vector is empty"));
         }
-        let has_timestamp = match tokens.first().unwrap().get_token_type() {
-            TokenType::Timestamp(_) => true,
-            _ => false,
-        };
+        let timestamp_idx = tokens
+            .iter()
+            .take(2)
+            .position(|token| matches!(token.get_token_type(), TokenType::Timestamp(_)));
         let line_range = (
             tokens.first().unwrap().get_line_num(),
             tokens.last().unwrap().get_line_num(),
@@ -97,29 +830,192 @@ impl LogEvent {
             (Self {
                 tokens,
                 line_range,
-                has_timestamp,
+                timestamp_idx,
+                inherited_timestamp: None,
+                truncated,
                 schema_config,
             }),
         ))
     }
 
     pub fn get_timestamp_token(&self) -> Option<&Token> {
-        match self.has_timestamp {
-            true => Some(&self.tokens[0]),
-            false => None,
-        }
+        self.timestamp_idx
+            .map(|timestamp_idx| &self.tokens[timestamp_idx])
+    }
+
+    // Returns the timestamp token of the log event or, if it has none, the timestamp it inherited
+    // (see `LogParser::inherit_timestamp`)
+    pub fn effective_timestamp(&self) -> Option<&Token> {
+        self.get_timestamp_token()
+            .or(self.inherited_timestamp.as_ref())
+    }
+
+    // Returns the timestamp exactly as it appears in the input, or `None` if the log event has no
+    // timestamp. Along with the byte offset of the timestamp token, this allows reformatting the
+    // timestamp in place.
+    pub fn timestamp_raw(&self) -> Option<&str> {
+        self.get_timestamp_token().map(|ts_token| ts_token.raw())
+    }
+
+    // Returns the fields captured by the named groups of the matched timestamp schema (see
+    // `TimestampSchema::capture_fields`), or `None` if the log event has no timestamp
+    pub fn timestamp_fields(&self) -> Option<HashMap<String, String>> {
+        let ts_token = self.get_timestamp_token()?;
+        let TokenType::Timestamp(ts_schema_id) = ts_token.get_token_type() else {
+            return None;
+        };
+        self.schema_config.get_ts_schemas()[ts_schema_id]
+            .capture_fields(ts_token.get_val())
+            .ok()
+            .flatten()
     }
 
     pub fn get_line_range(&self) -> (usize, usize) {
         self.line_range
     }
 
+    // Returns the number of lines the event spans: its first line along with its continuation
+    // lines (e.g. the frames of a stack trace)
+    pub fn body_line_count(&self) -> usize {
+        self.line_range.1 - self.line_range.0 + 1
+    }
+
+    // Returns whether the event was cut short because the next token would have exceeded the
+    // maximum event size (see `LogParser::set_max_event_bytes`) or the maximum number of
+    // continuation lines (see `LogParser::set_max_continuation_lines`), rather than ended by the
+    // next timestamp or the end of the input. The rest of the event follows as events without a
+    // timestamp, the last of which isn't truncated.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    // Returns the tokens following the timestamp, or all tokens if the event has no timestamp. The
+    // indentation (or the leading tokens, see `LogParser::set_timestamp_lookahead_tokens`) before
+    // the timestamp isn't part of the message.
     pub fn get_log_message_tokens(&self) -> &[Token] {
-        match self.has_timestamp {
-            true => &self.tokens[1..],
-            false => &self.tokens[..],
+        match self.timestamp_idx {
+            Some(timestamp_idx) => &self.tokens[timestamp_idx + 1..],
+            None => &self.tokens[..],
         }
     }
+
+    // Returns all tokens of the log event (including the timestamp) annotated with the name of the
+    // variable they matched
+    pub fn annotated_tokens(&self) -> Vec<TokenAnnotation<'_>> {
+        let var_schemas = self.schema_config.get_var_schemas();
+        self.tokens
+            .iter()
+            .map(|token| {
+                let variable_name = match token.get_token_type() {
+                    TokenType::Variable(schema_id) => Some(var_schemas[schema_id].get_name()),
+                    _ => None,
+                };
+                let byte_offset = token.get_byte_offset();
+                TokenAnnotation {
+                    text: token.get_val(),
+                    variable_name,
+                    byte_range: byte_offset..byte_offset + token.get_val().len(),
+                }
+            })
+            .collect()
+    }
+
+    // Returns the edits that replace every token matching the given variable with `replacement`, as
+    // (byte range in the input, replacement text) pairs. The ranges are non-overlapping and in
+    // increasing order, so they can be spliced into the original input back to front.
+    pub fn redact(&self, var_name: &str, replacement: &str) -> Vec<(Range<usize>, String)> {
+        self.annotated_tokens()
+            .into_iter()
+            .filter(|annotation| Some(var_name) == annotation.get_variable_name())
+            .map(|annotation| (annotation.get_byte_range(), replacement.to_string()))
+            .collect()
+    }
+
+    // Returns the original text of the log event, including the timestamp
+    pub fn raw_text(&self) -> String {
+        self.tokens.iter().map(|token| token.get_val()).collect()
+    }
+
+    pub fn to_dto(&self) -> LogEventDto {
+        let tokens = self
+            .tokens
+            .iter()
+            .map(|token| TokenDto {
+                text: token.raw().to_string(),
+                variable_name: token.variable_name().map(|name| name.to_string()),
+                line_num: token.get_line_num(),
+                byte_offset: token.get_byte_offset(),
+            })
+            .collect();
+        LogEventDto {
+            tokens,
+            line_range: self.line_range,
+            timestamp_idx: self.timestamp_idx,
+            truncated: self.truncated,
+        }
+    }
+
+    // Encodes the log message (excluding the timestamp) in the CLP intermediate representation.
+    // Each variable is replaced with `CLP_VARIABLE_PLACEHOLDER` in the logtype, and its text as it
+    // appears in the input (without the variable's transform) is appended to the variables.
+    // Placeholders and escape characters occurring in static text are preceded by
+    // `CLP_ESCAPE_CHAR`, so that the message can be rebuilt unambiguously.
+    pub fn to_clp_ir(&self) -> ClpIr {
+        let mut logtype = String::new();
+        let mut variables = Vec::new();
+        for token in self.get_log_message_tokens() {
+            if let TokenType::Variable(_) = token.get_token_type() {
+                logtype.push(Self::CLP_VARIABLE_PLACEHOLDER);
+                variables.push(token.raw().to_string());
+                continue;
+            }
+            for c in token.get_val().chars() {
+                if Self::CLP_VARIABLE_PLACEHOLDER == c || Self::CLP_ESCAPE_CHAR == c {
+                    logtype.push(Self::CLP_ESCAPE_CHAR);
+                }
+                logtype.push(c);
+            }
+        }
+        ClpIr { logtype, variables }
+    }
+
+    // Serializes the log event into a JSON object with the following fields:
+    // - `timestamp`: the timestamp text, or null if the event has no timestamp
+    // - `line_range`: [first line, last line]
+    // - `variables`: variable name -> list of matched values (with the variable's transform
+    //   applied), in the order they appear
+    // - `message`: the reconstructed log message (excluding the timestamp)
+    pub fn to_json(&self) -> String {
+        let timestamp = match self.get_timestamp_token() {
+            Some(ts_token) => Value::String(ts_token.get_val().to_string()),
+            None => Value::Null,
+        };
+
+        let var_schemas = self.schema_config.get_var_schemas();
+        let mut variables = Map::new();
+        let mut message = String::new();
+        for token in self.get_log_message_tokens() {
+            message += token.get_val();
+            if let TokenType::Variable(schema_id) = token.get_token_type() {
+                let name = var_schemas[schema_id].get_name().to_string();
+                variables
+                    .entry(name)
+                    .or_insert_with(|| Value::Array(Vec::new()))
+                    .as_array_mut()
+                    .unwrap()
+                    .push(Value::String(token.value().to_string()));
+            }
+        }
+
+        let (line_begin, line_end) = self.get_line_range();
+        json!({
+            "timestamp": timestamp,
+            "line_range": [line_begin, line_end],
+            "variables": variables,
+            "message": message,
+        })
+        .to_string()
+    }
 }
 
 impl Debug for LogEvent {
@@ -137,7 +1033,10 @@ impl Debug for LogEvent {
                 curr_line_num = token.get_line_num();
                 result += format!("Line {}:\n", curr_line_num).as_str();
             }
-            result += format!("\t{:?}\n", token).as_str();
+            match token.variable_name() {
+                Some(name) => result += format!("\t{:?} ({})\n", token, name).as_str(),
+                None => result += format!("\t{:?}\n", token).as_str(),
+            }
         }
 
         write!(f, "{}", result)
@@ -0,0 +1,133 @@
+use crate::error_handling::Result;
+use crate::lexer::LexerStream;
+use crate::log_parser::{LogEvent, LogParser};
+use crate::parser::SchemaConfig;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+// Parses several input streams (e.g. the logs of several services) and merges their log events in
+// timestamp order. An event without a timestamp stays right after the previous event of its
+// stream, so each event is grouped with the events that don't have a timestamp following it.
+// Events are compared through the named groups of their timestamp (see `timestamp_order_key`);
+// ties are broken by stream order.
+pub struct MergedLogParser {
+    sources: Vec<MergeSource>,
+    ready_events: VecDeque<LogEvent>,
+}
+
+struct MergeSource {
+    log_parser: LogParser,
+    // The next group of log events: an event along with the events without a timestamp following
+    // it, or only events without a timestamp at the start of the stream
+    group: Vec<LogEvent>,
+    group_key: Option<TimestampOrderKey>,
+    // The event starting the group after the current one, read ahead to end the current group
+    next_event: Option<LogEvent>,
+}
+
+// Numeric timestamp fields, most significant first, followed by the raw timestamp text. Each field
+// keeps its position (`None` if missing or not numeric), so that timestamps captured by different
+// patterns are compared field by field.
+type TimestampOrderKey = (Vec<Option<u64>>, String);
+
+impl LogParser {
+    // Creates a parser merging the log events of the given streams in timestamp order (see
+    // `MergedLogParser`)
+    pub fn from_streams(
+        streams: Vec<Box<dyn LexerStream>>,
+        schema_config: Arc<SchemaConfig>,
+    ) -> Result<MergedLogParser> {
+        let mut sources = Vec::with_capacity(streams.len());
+        for stream in streams {
+            let mut log_parser = LogParser::new(schema_config.clone())?;
+            log_parser.set_input_stream(stream)?;
+            sources.push(MergeSource {
+                log_parser,
+                group: Vec::new(),
+                group_key: None,
+                next_event: None,
+            });
+        }
+        Ok(MergedLogParser {
+            sources,
+            ready_events: VecDeque::new(),
+        })
+    }
+}
+
+impl MergedLogParser {
+    // Named timestamp groups compared to order log events, most significant first. A group that is
+    // missing or isn't numeric orders before any numeric value.
+    pub const TIMESTAMP_ORDER_FIELDS: [&'static str; 7] = [
+        "year",
+        "month",
+        "day",
+        "hour",
+        "minute",
+        "second",
+        "millisecond",
+    ];
+
+    pub fn parse_next_log_event(&mut self) -> Result<Option<LogEvent>> {
+        if let Some(log_event) = self.ready_events.pop_front() {
+            return Ok(Some(log_event));
+        }
+        for source in self.sources.iter_mut() {
+            if source.group.is_empty() {
+                source.read_group()?;
+            }
+        }
+        // Events without a timestamp at the start of a stream (without a key) come first
+        let next_source = self
+            .sources
+            .iter_mut()
+            .filter(|source| false == source.group.is_empty())
+            .min_by(|source, other_source| source.group_key.cmp(&other_source.group_key));
+        let Some(next_source) = next_source else {
+            return Ok(None);
+        };
+        self.ready_events.extend(next_source.group.drain(..));
+        Ok(self.ready_events.pop_front())
+    }
+
+    pub fn parse_all(&mut self) -> Result<Vec<LogEvent>> {
+        let mut log_events = Vec::new();
+        while let Some(log_event) = self.parse_next_log_event()? {
+            log_events.push(log_event);
+        }
+        Ok(log_events)
+    }
+}
+
+impl MergeSource {
+    fn read_group(&mut self) -> Result<()> {
+        let first_event = match self.next_event.take() {
+            Some(log_event) => log_event,
+            None => match self.log_parser.parse_next_log_event()? {
+                Some(log_event) => log_event,
+                None => return Ok(()),
+            },
+        };
+        self.group_key = timestamp_order_key(&first_event);
+        self.group.push(first_event);
+        while let Some(log_event) = self.log_parser.parse_next_log_event()? {
+            if log_event.get_timestamp_token().is_some() {
+                self.next_event = Some(log_event);
+                break;
+            }
+            self.group.push(log_event);
+        }
+        Ok(())
+    }
+}
+
+// Returns the key ordering the log event by timestamp, or `None` if it has no timestamp
+fn timestamp_order_key(log_event: &LogEvent) -> Option<TimestampOrderKey> {
+    let timestamp = log_event.get_timestamp_token()?.get_val().to_string();
+    let fields = log_event.timestamp_fields().unwrap_or_default();
+    let numeric_fields = MergedLogParser::TIMESTAMP_ORDER_FIELDS
+        .iter()
+        .map(|name| fields.get(*name).and_then(|v| v.parse::<u64>().ok()))
+        .collect();
+    Some((numeric_fields, timestamp))
+}
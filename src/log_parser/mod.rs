@@ -1,4 +1,12 @@
 mod log_parser;
+mod merged_log_parser;
 
+pub use log_parser::ClpIr;
 pub use log_parser::LogEvent;
+pub use log_parser::LogEventDto;
 pub use log_parser::LogParser;
+pub use log_parser::ParseProgress;
+pub use log_parser::ParseStats;
+pub use log_parser::TokenAnnotation;
+pub use log_parser::TokenDto;
+pub use merged_log_parser::MergedLogParser;
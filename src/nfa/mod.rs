@@ -1 +1,10 @@
 pub mod nfa;
+
+pub use nfa::check_supported;
+pub use nfa::CompileBudget;
+pub use nfa::MatchExplanation;
+pub use nfa::NFAStats;
+pub use nfa::PrefixStatus;
+pub use nfa::State;
+pub use nfa::Transition;
+pub use nfa::NFA;
@@ -1,18 +1,21 @@
-use crate::error_handling::Result;
+use crate::dfa::{State as DfaState, DFA};
+use crate::error_handling::{LimitKind, Result};
 use crate::parser::regex_parser::parser::RegexParser;
-use std::collections::HashMap;
-use std::fmt::Debug;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Display};
 use std::hash::Hash;
+use std::ops::Range;
+use std::time::{Duration, Instant};
 
 use crate::error_handling::Error::{
-    NegationNotSupported, NonGreedyRepetitionNotSupported, NoneASCIICharacters,
-    UnsupportedAstBracketedKind, UnsupportedAstNodeType, UnsupportedClassSetType,
-    UnsupportedGroupKindType,
+    InvalidRepetitionRange, LimitExceeded, NegationNotSupported, NonGreedyRepetitionNotSupported,
+    NoneASCIICharacters, UnsupportedAstBracketedKind, UnsupportedAstNodeType,
+    UnsupportedClassSetType, UnsupportedGroupKindType,
 };
 use regex_syntax::ast::{
-    Alternation, Ast, ClassBracketed, ClassPerl, ClassPerlKind, ClassSet, ClassSetItem,
-    ClassSetRange, ClassSetUnion, Concat, Group, GroupKind, Literal, Repetition, RepetitionKind,
-    RepetitionRange,
+    Alternation, Assertion, AssertionKind, Ast, ClassBracketed, ClassPerl, ClassPerlKind, ClassSet,
+    ClassSetItem, ClassSetRange, ClassSetUnion, Concat, Group, GroupKind, Literal, Repetition,
+    RepetitionKind, RepetitionRange,
 };
 
 const DIGIT_TRANSITION: u128 = 0x000000000000000003ff000000000000;
@@ -21,11 +24,24 @@ const WORD_TRANSITION: u128 = 0x07fffffe87fffffe03ff000000000000;
 
 const EPSILON_TRANSITION: u128 = 0x0;
 
-const DOT_TRANSITION: u128 = !EPSILON_TRANSITION;
+pub(crate) const DOT_TRANSITION: u128 = !EPSILON_TRANSITION;
+
+// Tags of the epsilon transitions added for the `\b` and `\B` assertions, which simulations only
+// cross where the assertion holds (see `NFA::epsilon_closure_at`). Capture tags are non-negative,
+// and other transitions are tagged -1.
+const WORD_BOUNDARY_TAG: i16 = -2;
+const NOT_WORD_BOUNDARY_TAG: i16 = -3;
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub(crate) struct State(pub usize);
+pub struct State(pub usize);
+
+impl Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "q{}", self.0)
+    }
+}
 
+#[derive(Clone, Eq, PartialEq)]
 pub struct Transition {
     from: State,
     to: State,
@@ -55,36 +71,34 @@ impl Debug for Transition {
 }
 
 impl Transition {
-    pub fn convert_char_to_symbol_onehot_encoding(c: char) -> u128 {
-        let mut symbol_onehot_encoding = 0;
-        let c = c as u8;
-
-        symbol_onehot_encoding |= 1 << c;
-
-        symbol_onehot_encoding
+    // The conversions below only accept ASCII symbols (`\x00` to `\x7f`), which is all the 128-bit
+    // mask can represent. Anything beyond is rejected with `NoneASCIICharacters` rather than
+    // shifted past the mask.
+    pub fn convert_char_to_symbol_onehot_encoding(c: char) -> Result<u128> {
+        Ok(1 << get_ascii_char(c)?)
     }
 
-    pub fn convert_char_range_to_symbol_onehot_encoding(range: Option<(u8, u8)>) -> u128 {
+    pub fn convert_char_range_to_symbol_onehot_encoding(range: Option<(u8, u8)>) -> Result<u128> {
         let mut symbol_onehot_encoding: u128 = 0;
 
         match range {
             Some((begin, end)) => {
-                for c in begin..=end {
+                for c in get_ascii_byte(begin)?..=get_ascii_byte(end)? {
                     symbol_onehot_encoding |= 1 << c;
                 }
             }
             None => {}
         }
 
-        symbol_onehot_encoding
+        Ok(symbol_onehot_encoding)
     }
 
-    pub fn convert_char_vec_to_symbol_onehot_encoding(char_vec: Vec<u8>) -> u128 {
+    pub fn convert_char_vec_to_symbol_onehot_encoding(char_vec: Vec<u8>) -> Result<u128> {
         let mut symbol_onehot_encoding: u128 = 0;
         for c in char_vec {
-            symbol_onehot_encoding |= 1 << c;
+            symbol_onehot_encoding |= 1 << get_ascii_byte(c)?;
         }
-        symbol_onehot_encoding
+        Ok(symbol_onehot_encoding)
     }
 
     pub fn new(from: State, to: State, symbol_onehot_encoding: u128, tag: i16) -> Self {
@@ -110,21 +124,102 @@ impl Transition {
         symbol
     }
 
+    // Returns the symbols of the transition as a list of inclusive, contiguous (begin, end) ranges
+    pub fn get_symbol_ranges(&self) -> Vec<(u8, u8)> {
+        let mut ranges: Vec<(u8, u8)> = Vec::new();
+        for i in 0..=127u8 {
+            if self.symbol_onehot_encoding & (1 << i) == 0 {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == i => *end = i,
+                _ => ranges.push((i, i)),
+            }
+        }
+        ranges
+    }
+
+    pub fn get_from_state(&self) -> State {
+        self.from.clone()
+    }
+
     pub fn get_to_state(&self) -> State {
         self.to.clone()
     }
 }
 
-pub(crate) struct NFA {
+#[derive(Clone)]
+pub struct NFA {
     start: State,
     accept: State,
     states: Vec<State>,
     transitions: HashMap<State, Vec<Transition>>,
+    // Whether the start state is accepting as well. Only set by `remove_epsilons` when the empty
+    // string is accepted, since this can't be expressed through the single accept state without
+    // epsilon transitions.
+    start_accepting: bool,
+    // Symbols that dot and Perl classes can match when building from an AST (see `with_alphabet`)
+    alphabet: u128,
+    // Names of the capture groups added from an AST, in the order of their opening parenthesis, or
+    // `None` for an unnamed group. The group with index `i` is delimited by epsilon transitions
+    // tagged `2 * i` (start) and `2 * i + 1` (end).
+    capture_names: Vec<Option<String>>,
+    // Set while building from a regex with `from_regex_with_budget`, along with the time the
+    // construction started
+    budget: Option<(CompileBudget, Instant)>,
+}
+
+// Bounds on the resources spent building an NFA from a regex (see `NFA::from_regex_with_budget`),
+// e.g. to compile untrusted regexes
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CompileBudget {
+    pub max_states: usize,
+    pub max_duration: Option<Duration>,
+}
+
+// The state of a match after consuming some input (see `NFA::prefix_status`)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PrefixStatus {
+    // The input is accepted as is
+    Accepted,
+    // The input isn't accepted, but some states are still active, so more input may be accepted
+    Possible,
+    // No state is active anymore: neither the input nor any extension of it is accepted
+    Dead,
+}
+
+// Where a match failed (see `NFA::explain_match`)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MatchExplanation {
+    // Whether the whole input is accepted
+    pub accepted: bool,
+    // Length in bytes of the longest prefix of the input the NFA consumed
+    pub consumed: usize,
+    // The states active after consuming the prefix (including the accept state if the prefix is
+    // accepted)
+    pub active_states: Vec<State>,
+    // The character following the prefix, which no active state has a transition on, or `None`
+    // if the whole input was consumed
+    pub unmatched_char: Option<char>,
+}
+
+// Size metrics of an NFA, useful to keep track of the automaton complexity of a schema
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NFAStats {
+    pub num_states: usize,
+    pub num_transitions: usize,
+    pub num_epsilon_transitions: usize,
 }
 
 impl NFA {
     pub const START_STATE: State = State(0);
     pub const ACCEPT_STATE: State = State(1);
+
+    // Maximum number of states of the automata built with the subset construction
+    pub const MAX_DETERMINIZED_STATES: usize = 10_000;
+
+    // Printable ASCII (0x20 to 0x7e) along with tab and newline
+    pub const PRINTABLE_ASCII_ALPHABET: u128 = 0x7fffffff_ffffffff_ffffffff_00000000 | 0x600;
 }
 
 // NFA implementation for NFA construction from AST
@@ -136,10 +231,97 @@ impl NFA {
             accept: NFA::ACCEPT_STATE,
             states: states_vec,
             transitions: HashMap::new(),
+            start_accepting: false,
+            alphabet: DOT_TRANSITION,
+            capture_names: Vec::new(),
+            budget: None,
+        }
+    }
+
+    // Creates an empty NFA restricted to the given alphabet: the masks of dots and Perl classes
+    // (e.g. `\s`) added from an AST only keep the symbols of the alphabet, so that `.` doesn't match
+    // bytes that never appear in the input (e.g. control characters). Literals and ranges are kept
+    // as written.
+    pub fn with_alphabet(alphabet: u128) -> Self {
+        let mut nfa = NFA::new();
+        nfa.alphabet = alphabet;
+        nfa
+    }
+
+    pub fn get_alphabet(&self) -> u128 {
+        self.alphabet
+    }
+
+    // Parses the given regex and builds the NFA from its AST, wired between the start and the
+    // accept state.
+    pub fn from_regex(pattern: &str) -> Result<Self> {
+        let mut parser = RegexParser::new();
+        let parsed_ast = parser.parse_into_ast(pattern)?;
+        let mut nfa = NFA::new();
+        nfa.add_ast_to_nfa(&parsed_ast, nfa.get_start(), nfa.get_accept())?;
+        Ok(nfa)
+    }
+
+    // Same as `from_regex`, but fails with `LimitExceeded` as soon as the NFA grows past
+    // `max_states` states (`LimitKind::NFAStates`) or the construction takes longer than
+    // `max_duration` (`LimitKind::CompileMillis`). Nested bounded repetitions multiply the number
+    // of states, e.g. `(a{100}){100}` takes over 10,000 states.
+    pub fn from_regex_with_budget(pattern: &str, budget: CompileBudget) -> Result<Self> {
+        let mut parser = RegexParser::new();
+        let parsed_ast = parser.parse_into_ast(pattern)?;
+        let mut nfa = NFA::new();
+        nfa.budget = Some((budget, Instant::now()));
+        nfa.add_ast_to_nfa(&parsed_ast, nfa.get_start(), nfa.get_accept())?;
+        nfa.budget = None;
+        Ok(nfa)
+    }
+
+    // Concatenates the given regex onto the NFA: the current accept state becomes the start of the
+    // new fragment, and a new state becomes the accept state. E.g., appending `b` to the NFA of `a`
+    // results in an NFA of `ab`. The capture groups of the regex are numbered after the existing
+    // ones.
+    pub fn append_regex(&mut self, pattern: &str) -> Result<()> {
+        let mut parser = RegexParser::new();
+        let parsed_ast = parser.parse_into_ast(pattern)?;
+        let mut fragment = NFA::with_alphabet(self.alphabet);
+        fragment.add_ast_to_nfa(&parsed_ast, fragment.get_start(), fragment.get_accept())?;
+        let (fragment_start, fragment_accept) = self.add_renumbered_nfa(fragment);
+        self.add_epsilon_transition(self.get_accept(), fragment_start);
+        self.accept = fragment_accept;
+        Ok(())
+    }
+
+    // Checks the AST with the same helpers as `add_ast_to_nfa`, without adding states (see
+    // `check_supported`)
+    fn check_ast_supported(&self, ast: &Ast) -> Result<()> {
+        match ast {
+            Ast::Literal(_) | Ast::Dot(_) | Ast::ClassPerl(_) | Ast::ClassBracketed(_) => {
+                self.get_single_symbol_onehot_encoding(ast).map(|_| ())
+            }
+            Ast::Repetition(repetition) => {
+                get_repetition_range(repetition)?;
+                self.check_ast_supported(&repetition.ast)
+            }
+            Ast::Concat(concat) => concat
+                .asts
+                .iter()
+                .try_for_each(|sub_ast| self.check_ast_supported(sub_ast)),
+            Ast::Alternation(alternation) => alternation
+                .asts
+                .iter()
+                .try_for_each(|sub_ast| self.check_ast_supported(sub_ast)),
+            Ast::Group(group) => {
+                get_group_capture(group)?;
+                self.check_ast_supported(&group.ast)
+            }
+            Ast::Empty(_) => Ok(()),
+            Ast::Assertion(assertion) => get_assertion_tag(&assertion.kind).map(|_| ()),
+            _ => Err(UnsupportedAstNodeType("Ast Type not supported")),
         }
     }
 
     pub fn add_ast_to_nfa(&mut self, ast: &Ast, start: State, end: State) -> Result<()> {
+        self.check_budget()?;
         match ast {
             Ast::Literal(literal) => self.add_literal(&**literal, start, end)?,
             Ast::Dot(dot) => self.add_dot(start, end)?,
@@ -149,6 +331,9 @@ impl NFA {
             Ast::ClassBracketed(bracketed) => self.add_bracketed(&**bracketed, start, end)?,
             Ast::Alternation(alternation) => self.add_alternation(&**alternation, start, end)?,
             Ast::Group(group) => self.add_group(&**group, start, end)?,
+            // An empty pattern, e.g. the empty branch in `(a|)`, matches the empty string
+            Ast::Empty(_) => self.add_epsilon_transition(start, end),
+            Ast::Assertion(assertion) => self.add_assertion(&**assertion, start, end)?,
             _ => {
                 return Err(UnsupportedAstNodeType("Ast Type not supported"));
             }
@@ -158,28 +343,47 @@ impl NFA {
 
     fn add_literal(&mut self, literal: &Literal, start: State, end: State) -> Result<()> {
         let c = get_ascii_char(literal.c)?;
-        self.add_transition_from_range(start, end, Some((c, c)));
-        Ok(())
+        self.add_transition_from_range(start, end, Some((c, c)))
     }
 
     fn add_dot(&mut self, start: State, end: State) -> Result<()> {
-        self.add_transition(start, end, DOT_TRANSITION);
+        self.add_transition(start, end, DOT_TRANSITION & self.alphabet);
         Ok(())
     }
 
     fn add_perl(&mut self, perl: &ClassPerl, start: State, end: State) -> Result<()> {
+        let onehot = self.get_perl_onehot_encoding(perl)?;
+        self.add_transition(start, end, onehot);
+        Ok(())
+    }
+
+    fn get_perl_onehot_encoding(&self, perl: &ClassPerl) -> Result<u128> {
         if perl.negated {
             return Err(NegationNotSupported("Negation in perl not yet supported."));
         }
-        match perl.kind {
-            ClassPerlKind::Digit => self.add_transition(start, end, DIGIT_TRANSITION),
-            ClassPerlKind::Space => self.add_transition(start, end, SPACE_TRANSITION),
-            ClassPerlKind::Word => self.add_transition(start, end, WORD_TRANSITION),
-        }
+        let onehot = match perl.kind {
+            ClassPerlKind::Digit => DIGIT_TRANSITION,
+            ClassPerlKind::Space => SPACE_TRANSITION,
+            ClassPerlKind::Word => WORD_TRANSITION,
+        };
+        Ok(onehot & self.alphabet)
+    }
+
+    // Word boundary assertions are zero-width: they're added as tagged epsilon transitions, which
+    // the simulations (e.g. `accepts`, `find` and `captures`) only cross where the assertion holds
+    // between the surrounding characters. Building a DFA crosses them unconditionally, which is why
+    // schemas reject them (see `RegexParser::with_feature_validation`).
+    fn add_assertion(&mut self, assertion: &Assertion, start: State, end: State) -> Result<()> {
+        let tag = get_assertion_tag(&assertion.kind)?;
+        self.add_tagged_epsilon_transition(start, end, tag);
         Ok(())
     }
 
     fn add_concat(&mut self, concat: &Concat, start: State, end: State) -> Result<()> {
+        if concat.asts.is_empty() {
+            self.add_epsilon_transition(start, end);
+            return Ok(());
+        }
         let mut curr_start = start.clone();
         for (idx, sub_ast) in concat.asts.iter().enumerate() {
             let curr_end = if concat.asts.len() - 1 == idx {
@@ -194,10 +398,23 @@ impl NFA {
     }
 
     fn add_group(&mut self, group: &Group, start: State, end: State) -> Result<()> {
-        match &group.kind {
-            GroupKind::CaptureIndex(_) => self.add_ast_to_nfa(&group.ast, start, end)?,
-            _ => return Err(UnsupportedGroupKindType),
+        // A repeated group is added once per repetition, but keeps a single index
+        let Some((capture_idx, name)) = get_group_capture(group)? else {
+            return self.add_ast_to_nfa(&group.ast, start, end);
+        };
+        if self.capture_names.len() <= capture_idx {
+            self.capture_names.resize(capture_idx + 1, None);
         }
+        self.capture_names[capture_idx] = name;
+
+        // Tagging the entry transition lets `captures` tell a group that matched the empty string
+        // apart from one that didn't take part in the match, e.g. `(b)?`
+        let group_start = self.new_state();
+        let group_end = self.new_state();
+        let start_tag = 2 * capture_idx as i16;
+        self.add_tagged_epsilon_transition(start, group_start.clone(), start_tag);
+        self.add_ast_to_nfa(&group.ast, group_start, group_end.clone())?;
+        self.add_tagged_epsilon_transition(group_end, end, start_tag + 1);
         Ok(())
     }
 
@@ -207,22 +424,49 @@ impl NFA {
         start: State,
         end: State,
     ) -> Result<()> {
+        // Branches matching a single character, e.g. `a|b|[0-9]`, are folded into one combined
+        // mask and added as a single transition. The other branches are wrapped in epsilon
+        // transitions.
+        let mut single_symbol_onehot = 0;
         for sub_ast in alternation.asts.iter() {
+            if let Some(onehot) = self.get_single_symbol_onehot_encoding(sub_ast)? {
+                single_symbol_onehot |= onehot;
+                continue;
+            }
             let sub_ast_start = self.new_state();
             let sub_ast_end = self.new_state();
             self.add_epsilon_transition(start.clone(), sub_ast_start.clone());
             self.add_epsilon_transition(sub_ast_end.clone(), end.clone());
             self.add_ast_to_nfa(sub_ast, sub_ast_start, sub_ast_end)?;
         }
+        if 0 != single_symbol_onehot {
+            self.add_transition(start, end, single_symbol_onehot);
+        }
         Ok(())
     }
 
-    fn add_repetition(&mut self, repetition: &Repetition, start: State, end: State) -> Result<()> {
-        if false == repetition.greedy {
-            return Err(NonGreedyRepetitionNotSupported);
-        }
+    // Returns the mask of the characters matched by the AST if it matches a single character, or
+    // `None` if it matches anything else (e.g. a concatenation or a group)
+    fn get_single_symbol_onehot_encoding(&self, ast: &Ast) -> Result<Option<u128>> {
+        let onehot = match ast {
+            Ast::Literal(literal) => {
+                let c = get_ascii_char(literal.c)?;
+                Transition::convert_char_range_to_symbol_onehot_encoding(Some((c, c)))?
+            }
+            Ast::Dot(_) => DOT_TRANSITION & self.alphabet,
+            Ast::ClassPerl(perl) => self.get_perl_onehot_encoding(perl)?,
+            Ast::ClassBracketed(bracketed) => self.get_bracketed_onehot_encoding(bracketed)?,
+            _ => return Ok(None),
+        };
+        Ok(Some(onehot))
+    }
 
-        let (min, optional_max) = Self::get_repetition_range(&repetition.op.kind);
+    fn add_repetition(&mut self, repetition: &Repetition, start: State, end: State) -> Result<()> {
+        let (min, optional_max) = get_repetition_range(repetition)?;
+        // The repeated AST is built once and a copy of it is spliced in for each repetition, so
+        // nested repetitions (e.g. `((ab){2,3}){4,8}`) are only walked once per level. The copies
+        // get the same states as walking the AST in their place would.
+        let fragment = self.build_fragment(&repetition.ast)?;
         let mut start_state = start.clone();
         let range_bound_state = self.new_state();
 
@@ -232,25 +476,17 @@ impl NFA {
         } else {
             for _ in 1..min {
                 let intermediate_state = self.new_state();
-                self.add_ast_to_nfa(
-                    &repetition.ast,
-                    start_state.clone(),
-                    intermediate_state.clone(),
-                )?;
+                self.add_fragment_copy(&fragment, start_state.clone(), intermediate_state.clone())?;
                 start_state = intermediate_state;
             }
-            self.add_ast_to_nfa(
-                &repetition.ast,
-                start_state.clone(),
-                range_bound_state.clone(),
-            )?;
+            self.add_fragment_copy(&fragment, start_state.clone(), range_bound_state.clone())?;
         }
 
         self.add_epsilon_transition(range_bound_state.clone(), end.clone());
         match optional_max {
             None => {
-                self.add_ast_to_nfa(
-                    &repetition.ast,
+                self.add_fragment_copy(
+                    &fragment,
                     range_bound_state.clone(),
                     range_bound_state.clone(),
                 )?;
@@ -263,8 +499,8 @@ impl NFA {
                 start_state = range_bound_state.clone();
                 for _ in min..max {
                     let intermediate_state = self.new_state();
-                    self.add_ast_to_nfa(
-                        &repetition.ast,
+                    self.add_fragment_copy(
+                        &fragment,
                         start_state.clone(),
                         intermediate_state.clone(),
                     )?;
@@ -277,71 +513,141 @@ impl NFA {
         Ok(())
     }
 
+    // Builds the NFA of the given AST on its own, with the alphabet, the capture groups and the
+    // compile budget of this NFA. Its capture tags keep the group indices of the full regex.
+    fn build_fragment(&mut self, ast: &Ast) -> Result<NFA> {
+        let mut fragment = NFA::with_alphabet(self.alphabet);
+        fragment.budget = self.budget;
+        fragment.add_ast_to_nfa(ast, fragment.get_start(), fragment.get_accept())?;
+        if self.capture_names.len() < fragment.capture_names.len() {
+            self.capture_names
+                .resize(fragment.capture_names.len(), None);
+        }
+        for (idx, name) in fragment.capture_names.iter().enumerate() {
+            if name.is_some() {
+                self.capture_names[idx] = name.clone();
+            }
+        }
+        Ok(fragment)
+    }
+
+    // Copies the transitions of a fragment built by `build_fragment` between the given states: the
+    // fragment's start and accept states are mapped to `start` and `end`, and every other state to
+    // a new one. Tags are kept as is.
+    fn add_fragment_copy(&mut self, fragment: &NFA, start: State, end: State) -> Result<()> {
+        self.check_budget()?;
+        let inner_states = fragment
+            .states
+            .iter()
+            .filter(|state| **state != fragment.start && **state != fragment.accept)
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut state_map = HashMap::new();
+        state_map.insert(fragment.start.clone(), start);
+        state_map.insert(fragment.accept.clone(), end);
+        for state in inner_states {
+            state_map.insert(state, self.new_state());
+        }
+        for state in fragment.states.iter() {
+            let Some(transitions) = fragment.transitions.get(state) else {
+                continue;
+            };
+            for transition in transitions {
+                let from = state_map[&transition.from].clone();
+                self.transitions
+                    .entry(from.clone())
+                    .or_default()
+                    .push(Transition {
+                        from,
+                        to: state_map[&transition.to].clone(),
+                        symbol_onehot_encoding: transition.symbol_onehot_encoding,
+                        tag: transition.tag,
+                    });
+            }
+        }
+        if fragment.start_accepting {
+            let start = state_map[&fragment.start].clone();
+            let accept = state_map[&fragment.accept].clone();
+            self.add_epsilon_transition(start, accept);
+        }
+        Ok(())
+    }
+
+    // A bracketed class matches a single character, so all of its items (including nested
+    // brackets) are folded into one combined mask and added as a single transition.
     fn add_bracketed(
         &mut self,
         bracketed: &ClassBracketed,
         start: State,
         end: State,
     ) -> Result<()> {
+        let onehot = self.get_bracketed_onehot_encoding(bracketed)?;
+        self.add_transition(start, end, onehot);
+        Ok(())
+    }
+
+    fn get_bracketed_onehot_encoding(&self, bracketed: &ClassBracketed) -> Result<u128> {
         if bracketed.negated {
             return Err(NegationNotSupported(
                 "Negation in bracket not yet supported",
             ));
         }
         match &bracketed.kind {
-            ClassSet::Item(item) => self.add_class_set_item(item, start, end)?,
-            _ => return Err(UnsupportedAstBracketedKind),
+            ClassSet::Item(item) => self.get_class_set_item_onehot_encoding(item),
+            _ => Err(UnsupportedAstBracketedKind),
         }
-        Ok(())
     }
 
-    fn add_class_set_item(&mut self, item: &ClassSetItem, start: State, end: State) -> Result<()> {
+    fn get_class_set_item_onehot_encoding(&self, item: &ClassSetItem) -> Result<u128> {
         match item {
-            ClassSetItem::Literal(literal) => self.add_literal(literal, start, end)?,
-            ClassSetItem::Bracketed(bracketed) => self.add_bracketed(bracketed, start, end)?,
-            ClassSetItem::Range(range) => self.add_range(range, start, end)?,
-            ClassSetItem::Perl(perl) => self.add_perl(perl, start, end)?,
-            ClassSetItem::Union(union) => self.add_union(union, start, end)?,
-            _ => return Err(UnsupportedClassSetType),
+            ClassSetItem::Literal(literal) => {
+                let c = get_ascii_char(literal.c)?;
+                Transition::convert_char_range_to_symbol_onehot_encoding(Some((c, c)))
+            }
+            ClassSetItem::Bracketed(bracketed) => self.get_bracketed_onehot_encoding(bracketed),
+            ClassSetItem::Range(range) => Self::get_range_onehot_encoding(range),
+            ClassSetItem::Perl(perl) => self.get_perl_onehot_encoding(perl),
+            ClassSetItem::Union(union) => self.get_union_onehot_encoding(union),
+            _ => Err(UnsupportedClassSetType),
         }
-        Ok(())
     }
 
-    fn add_range(&mut self, range: &ClassSetRange, start: State, end: State) -> Result<()> {
-        self.add_transition_from_range(
-            start,
-            end,
-            Some((get_ascii_char(range.start.c)?, get_ascii_char(range.end.c)?)),
-        );
-        Ok(())
+    fn get_range_onehot_encoding(range: &ClassSetRange) -> Result<u128> {
+        Transition::convert_char_range_to_symbol_onehot_encoding(Some((
+            get_ascii_char(range.start.c)?,
+            get_ascii_char(range.end.c)?,
+        )))
     }
 
-    fn add_union(&mut self, union: &ClassSetUnion, start: State, end: State) -> Result<()> {
-        let mut curr_start = start.clone();
-        for (idx, item) in union.items.iter().enumerate() {
-            let curr_end = if union.items.len() - 1 == idx {
-                end.clone()
-            } else {
-                self.new_state()
-            };
-            self.add_class_set_item(item, curr_start.clone(), curr_end.clone())?;
-            curr_start = curr_end.clone();
+    fn get_union_onehot_encoding(&self, union: &ClassSetUnion) -> Result<u128> {
+        let mut onehot = EPSILON_TRANSITION;
+        for item in &union.items {
+            onehot |= self.get_class_set_item_onehot_encoding(item)?;
         }
-
-        Ok(())
+        Ok(onehot)
     }
 
-    fn get_repetition_range(kind: &RepetitionKind) -> (u32, Option<u32>) {
-        match kind {
-            RepetitionKind::ZeroOrOne => (0, Some(1)),
-            RepetitionKind::ZeroOrMore => (0, None),
-            RepetitionKind::OneOrMore => (1, None),
-            RepetitionKind::Range(range) => match range {
-                RepetitionRange::Exactly(num) => (*num, Some(*num)),
-                RepetitionRange::AtLeast(num) => (*num, None),
-                RepetitionRange::Bounded(begin, end) => (*begin, Some(*end)),
-            },
+    // Checked before adding each AST node, so that a construction exceeding the budget stops at
+    // most one node past the limit
+    fn check_budget(&self) -> Result<()> {
+        let Some((budget, start_time)) = &self.budget else {
+            return Ok(());
+        };
+        if self.states.len() > budget.max_states {
+            return Err(LimitExceeded {
+                kind: LimitKind::NFAStates,
+                limit: budget.max_states,
+            });
+        }
+        if let Some(max_duration) = budget.max_duration {
+            if start_time.elapsed() > max_duration {
+                return Err(LimitExceeded {
+                    kind: LimitKind::CompileMillis,
+                    limit: max_duration.as_millis() as usize,
+                });
+            }
         }
+        Ok(())
     }
 
     fn new_state(&mut self) -> State {
@@ -349,17 +655,25 @@ impl NFA {
         self.states.last().unwrap().clone()
     }
 
-    fn add_transition_from_range(&mut self, from: State, to: State, range: Option<(u8, u8)>) {
+    fn add_transition_from_range(
+        &mut self,
+        from: State,
+        to: State,
+        range: Option<(u8, u8)>,
+    ) -> Result<()> {
         let transition = Transition {
             from: from.clone(),
             to: to.clone(),
-            symbol_onehot_encoding: Transition::convert_char_range_to_symbol_onehot_encoding(range),
+            symbol_onehot_encoding: Transition::convert_char_range_to_symbol_onehot_encoding(
+                range,
+            )?,
             tag: -1,
         };
         self.transitions
             .entry(from)
             .or_insert(vec![])
             .push(transition);
+        Ok(())
     }
 
     fn add_transition(&mut self, from: State, to: State, onehot: u128) {
@@ -378,14 +692,51 @@ impl NFA {
     fn add_epsilon_transition(&mut self, from: State, to: State) {
         self.add_transition(from, to, EPSILON_TRANSITION);
     }
+
+    fn add_tagged_epsilon_transition(&mut self, from: State, to: State, tag: i16) {
+        let transition = Transition {
+            from: from.clone(),
+            to,
+            symbol_onehot_encoding: EPSILON_TRANSITION,
+            tag,
+        };
+        self.transitions.entry(from).or_default().push(transition);
+    }
+}
+
+// Two NFAs are equal if they have the same start/accept states, the same set of states, and the
+// same transitions from each state, regardless of the order the transitions were added in.
+impl PartialEq for NFA {
+    fn eq(&self, other: &Self) -> bool {
+        let mut states = self
+            .states
+            .iter()
+            .map(|state| state.0)
+            .collect::<Vec<usize>>();
+        let mut other_states = other
+            .states
+            .iter()
+            .map(|state| state.0)
+            .collect::<Vec<usize>>();
+        states.sort();
+        other_states.sort();
+
+        self.start == other.start
+            && self.accept == other.accept
+            && self.start_accepting == other.start_accepting
+            && states == other_states
+            && self.get_sorted_transitions() == other.get_sorted_transitions()
+    }
 }
 
+impl Eq for NFA {}
+
 impl Debug for NFA {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "NFA( start: {:?}, accept: {:?}, states: {:?}, transitions: {{\n",
-            self.start, self.accept, self.states
+            "NFA( start: {:?}, accept: {:?}, start accepting: {}, states: {:?}, transitions: {{\n",
+            self.start, self.accept, self.start_accepting, self.states
         )?;
 
         for state in &self.states {
@@ -404,9 +755,42 @@ impl Debug for NFA {
 
 // NFA implementation for NFA to dfa conversion helper functions
 impl NFA {
+    // Returns whether the NFA has word boundary assertions, which `epsilon_closure` and the
+    // transformations built on it (e.g. `determinize` and `remove_epsilons`) cross unconditionally
+    pub fn has_assertions(&self) -> bool {
+        self.transitions
+            .values()
+            .flatten()
+            .any(|transition| matches!(transition.tag, WORD_BOUNDARY_TAG | NOT_WORD_BOUNDARY_TAG))
+    }
+
+    // Returns the states reachable from the given states through epsilon transitions (including
+    // the given states), sorted by state index
     pub fn epsilon_closure(&self, states: &Vec<State>) -> Vec<State> {
-        let mut closure = states.clone();
-        let mut stack = states.clone();
+        self.get_epsilon_closure(states, |_| true)
+    }
+
+    // Returns the epsilon closure of the given states at a position of the input between the
+    // characters `prev` and `next` (`None` at the ends of the input), only crossing the assertion
+    // transitions that hold there
+    fn epsilon_closure_at(
+        &self,
+        states: &[State],
+        prev: Option<char>,
+        next: Option<char>,
+    ) -> Vec<State> {
+        self.get_epsilon_closure(states, |transition| {
+            is_assertion_satisfied(transition.tag, prev, next)
+        })
+    }
+
+    fn get_epsilon_closure<F: Fn(&Transition) -> bool>(
+        &self,
+        states: &[State],
+        crosses: F,
+    ) -> Vec<State> {
+        let mut closure = states.to_vec();
+        let mut stack = states.to_vec();
 
         while let Some(state) = stack.pop() {
             let transitions = self.transitions.get(&state);
@@ -415,7 +799,7 @@ impl NFA {
             }
 
             for transition in transitions.unwrap() {
-                if transition.symbol_onehot_encoding == 0 {
+                if transition.symbol_onehot_encoding == 0 && crosses(transition) {
                     let to_state = transition.to.clone();
                     if !closure.contains(&to_state) {
                         closure.push(to_state.clone());
@@ -425,120 +809,1105 @@ impl NFA {
             }
         }
 
+        // Sorted so that the result doesn't depend on the order transitions were added in
+        closure.sort_by_key(|state| state.0);
+        closure.dedup();
         closure
     }
 
-    // Static function to get the combined state names
-    pub fn get_combined_state_names(states: &Vec<State>) -> String {
-        let mut names = states
-            .iter()
-            .map(|state| state.0.to_string())
-            .collect::<Vec<String>>();
-        names.sort();
-        names.join(",")
-    }
-}
-
-// Getter functions for NFA
-impl NFA {
-    pub fn get_start(&self) -> State {
-        self.start.clone()
+    // Returns whether the NFA accepts the whole input, by simulating the set of reachable states
+    pub fn accepts(&self, input: &str) -> bool {
+        PrefixStatus::Accepted == self.prefix_status(input)
     }
 
-    pub fn get_accept(&self) -> State {
-        self.accept.clone()
+    // Returns whether the input is accepted, or could still be accepted with more input appended,
+    // or can't be accepted whatever follows. Useful to decide whether to wait for more input when
+    // matching a stream.
+    pub fn prefix_status(&self, input: &str) -> PrefixStatus {
+        let mut states = self.epsilon_closure_at(&[self.start.clone()], None, input.chars().next());
+        for (pos, c) in input.char_indices() {
+            states = self.get_next_states(&states, c, input[pos + c.len_utf8()..].chars().next());
+            if states.is_empty() {
+                return PrefixStatus::Dead;
+            }
+        }
+        match states.iter().any(|state| self.is_accept_state(state)) {
+            true => PrefixStatus::Accepted,
+            false => PrefixStatus::Possible,
+        }
     }
 
-    pub fn get_transitions(&self) -> &HashMap<State, Vec<Transition>> {
-        &self.transitions
+    // Simulates the NFA on the input and reports where the match failed, e.g. to find out why a
+    // variable doesn't match some text. The simulation tracks the set of active states, so it
+    // never backtracks.
+    pub fn explain_match(&self, input: &str) -> MatchExplanation {
+        let mut states = self.epsilon_closure_at(&[self.start.clone()], None, input.chars().next());
+        for (pos, c) in input.char_indices() {
+            let next = input[pos + c.len_utf8()..].chars().next();
+            let next_states = self.get_next_states(&states, c, next);
+            if next_states.is_empty() {
+                return MatchExplanation {
+                    accepted: false,
+                    consumed: pos,
+                    active_states: states,
+                    unmatched_char: Some(c),
+                };
+            }
+            states = next_states;
+        }
+        MatchExplanation {
+            accepted: states.iter().any(|state| self.is_accept_state(state)),
+            consumed: input.len(),
+            active_states: states,
+            unmatched_char: None,
+        }
     }
 
-    pub fn get_transitions_from_state(&self, state: &State) -> Option<&Vec<Transition>> {
-        self.transitions.get(state)
+    // Returns the leftmost-longest match of the NFA in the haystack as a byte range, or `None` if
+    // there's none. Unlike `accepts`, assertions see the characters around the match, e.g.
+    // `\bcat\b` is found in " cat " but not in "category".
+    pub fn find(&self, haystack: &str) -> Option<Range<usize>> {
+        let match_starts = haystack
+            .char_indices()
+            .map(|(pos, _)| pos)
+            .chain(std::iter::once(haystack.len()));
+        for match_start in match_starts {
+            let prev = haystack[..match_start].chars().next_back();
+            let mut pos = match_start;
+            let mut states = self.epsilon_closure_at(
+                &[self.start.clone()],
+                prev,
+                haystack[pos..].chars().next(),
+            );
+            let mut match_end = None;
+            loop {
+                if states.iter().any(|state| self.is_accept_state(state)) {
+                    match_end = Some(pos);
+                }
+                let Some(c) = haystack[pos..].chars().next() else {
+                    break;
+                };
+                pos += c.len_utf8();
+                states = self.get_next_states(&states, c, haystack[pos..].chars().next());
+                if states.is_empty() {
+                    break;
+                }
+            }
+            if let Some(match_end) = match_end {
+                return Some(match_start..match_end);
+            }
+        }
+        None
     }
-}
 
-// Helper functions
-fn get_ascii_char(c: char) -> Result<u8> {
-    if false == c.is_ascii() {
-        return Err(NoneASCIICharacters);
+    // Returns the epsilon closure of the states reached from the given states on the character,
+    // where `next` is the character following it. Characters outside of the ASCII alphabet have no
+    // transition.
+    fn get_next_states(&self, states: &[State], c: char, next: Option<char>) -> Vec<State> {
+        let Ok(symbol_onehot_encoding) = Transition::convert_char_to_symbol_onehot_encoding(c)
+        else {
+            return Vec::new();
+        };
+        let mut next_states = Vec::new();
+        for state in states {
+            let Some(transitions) = self.transitions.get(state) else {
+                continue;
+            };
+            for transition in transitions {
+                if 0 != transition.symbol_onehot_encoding & symbol_onehot_encoding
+                    && false == next_states.contains(&transition.to)
+                {
+                    next_states.push(transition.to.clone());
+                }
+            }
+        }
+        if next_states.is_empty() {
+            return next_states;
+        }
+        self.epsilon_closure_at(&next_states, Some(c), next)
     }
-    Ok(c as u8)
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // Returns up to `limit` strings of at most `max_len` characters accepted by the NFA, shortest
+    // first and in symbol order for the same length. Meant for small patterns (e.g. to generate
+    // examples): `limit` bounds infinite languages like `a*`, and at most
+    // `MAX_DETERMINIZED_STATES` prefixes are explored per length, so languages with many prefixes
+    // may be sampled partially.
+    pub fn sample_accepted(&self, max_len: usize, limit: usize) -> Vec<String> {
+        // States from which the accept state can be reached. Prefixes only made of other states
+        // are dropped, as no extension of them is accepted.
+        let mut co_reachable = vec![false; self.states.len()];
+        for state in self.reverse().reachable_states() {
+            if state.0 < co_reachable.len() {
+                co_reachable[state.0] = true;
+            }
+        }
 
-    #[test]
-    fn test_single_char() -> Result<()> {
-        let mut parser = RegexParser::new();
-        let parsed_ast = parser.parse_into_ast(r"&")?;
-        let mut nfa = NFA::new();
-        nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+        let mut samples = Vec::new();
+        let mut prefixes = vec![(
+            String::new(),
+            self.epsilon_closure(&vec![self.start.clone()]),
+        )];
+        for len in 0..=max_len {
+            let mut next_prefixes = Vec::new();
+            for (prefix, states) in prefixes {
+                if samples.len() >= limit {
+                    return samples;
+                }
+                if states.iter().any(|state| self.is_accept_state(state)) {
+                    samples.push(prefix.clone());
+                }
+                if len == max_len {
+                    continue;
+                }
 
-        assert!(has_transition(
-            &nfa,
-            NFA::START_STATE,
-            NFA::ACCEPT_STATE,
-            Transition::convert_char_to_symbol_onehot_encoding('&')
-        ));
-        Ok(())
+                let transitions: Vec<&Transition> = states
+                    .iter()
+                    .filter_map(|state| self.transitions.get(state))
+                    .flatten()
+                    .filter(|transition| co_reachable[transition.to.0])
+                    .collect();
+                let symbols = transitions.iter().fold(0u128, |symbols, transition| {
+                    symbols | transition.symbol_onehot_encoding
+                });
+                for symbol in 0..128u8 {
+                    if 0 == symbols & (1 << symbol)
+                        || next_prefixes.len() >= Self::MAX_DETERMINIZED_STATES
+                    {
+                        continue;
+                    }
+                    let mut next_states = Vec::new();
+                    for transition in &transitions {
+                        if 0 != transition.symbol_onehot_encoding & (1 << symbol)
+                            && false == next_states.contains(&transition.to)
+                        {
+                            next_states.push(transition.to.clone());
+                        }
+                    }
+                    let mut next_prefix = prefix.clone();
+                    next_prefix.push(symbol as char);
+                    next_prefixes.push((next_prefix, self.epsilon_closure(&next_states)));
+                }
+            }
+            prefixes = next_prefixes;
+        }
+        samples.truncate(limit);
+        samples
     }
 
-    #[test]
-    fn test_dot() -> Result<()> {
-        {
-            let mut parser = RegexParser::new();
-            let parsed_ast = parser.parse_into_ast(r".")?;
-            let mut nfa = NFA::new();
-            nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+    // Returns the name of each capture group, or `None` for an unnamed group. The group numbered `i`
+    // in the regex (counting from 1) is at index `i - 1`.
+    pub fn get_capture_names(&self) -> &[Option<String>] {
+        &self.capture_names
+    }
 
-            assert!(has_transition(
-                &nfa,
-                NFA::START_STATE,
-                NFA::ACCEPT_STATE,
-                Transition::convert_char_range_to_symbol_onehot_encoding(Some((0, 127)))
-            ));
+    // Matches the whole input and returns the byte range captured by each capture group (in the
+    // order of `get_capture_names`), or `None` if the input doesn't match. A group that didn't take
+    // part in the match has no range, while a group that matched the empty string has an empty
+    // range. If the input can be matched in multiple ways, the captures of one of them are
+    // returned.
+    pub fn captures(&self, input: &str) -> Option<Vec<Option<Range<usize>>>> {
+        if false == input.is_ascii() {
+            return None;
+        }
+        let num_slots = 2 * self.capture_names.len();
+        let mut threads = Vec::new();
+        let mut visited = vec![false; self.states.len()];
+        let mut chars = input.chars();
+        self.add_capture_thread(
+            &mut threads,
+            &mut visited,
+            self.start.clone(),
+            vec![None; num_slots],
+            (0, None, input.chars().next()),
+        );
+        for (pos, c) in input.chars().enumerate() {
+            chars.next();
+            let next = chars.clone().next();
+            // The input is ASCII
+            let symbol_onehot_encoding = 1u128 << c as u8;
+            let mut next_threads = Vec::new();
+            let mut visited = vec![false; self.states.len()];
+            for (state, slots) in threads {
+                let Some(transitions) = self.transitions.get(&state) else {
+                    continue;
+                };
+                for transition in transitions {
+                    if 0 != transition.symbol_onehot_encoding & symbol_onehot_encoding {
+                        self.add_capture_thread(
+                            &mut next_threads,
+                            &mut visited,
+                            transition.to.clone(),
+                            slots.clone(),
+                            (pos + 1, Some(c), next),
+                        );
+                    }
+                }
+            }
+            if next_threads.is_empty() {
+                return None;
+            }
+            threads = next_threads;
         }
 
-        {
-            // Testing escaped `.`
-            let mut parser = RegexParser::new();
-            let parsed_ast = parser.parse_into_ast(r"\.")?;
-            let mut nfa = NFA::new();
-            nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+        let (_, slots) = threads
+            .into_iter()
+            .find(|(state, _)| self.is_accept_state(state))?;
+        Some(
+            slots
+                .chunks(2)
+                .map(|slot_pair| match (slot_pair[0], slot_pair[1]) {
+                    (Some(begin), Some(end)) => Some(begin..end),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
 
-            assert!(has_transition(
-                &nfa,
-                NFA::START_STATE,
-                NFA::ACCEPT_STATE,
-                Transition::convert_char_to_symbol_onehot_encoding('.')
-            ));
+    // Adds a thread at the given state for `captures`, following epsilon transitions and recording
+    // the position of the capture tags they cross. Each state holds at most one thread. The
+    // position comes with the characters before and after it, to check assertions.
+    fn add_capture_thread(
+        &self,
+        threads: &mut Vec<(State, Vec<Option<usize>>)>,
+        visited: &mut Vec<bool>,
+        state: State,
+        slots: Vec<Option<usize>>,
+        (pos, prev, next): (usize, Option<char>, Option<char>),
+    ) {
+        if visited[state.0] {
+            return;
         }
-        Ok(())
+        visited[state.0] = true;
+        if let Some(transitions) = self.transitions.get(&state) {
+            for transition in transitions {
+                if EPSILON_TRANSITION != transition.symbol_onehot_encoding
+                    || false == is_assertion_satisfied(transition.tag, prev, next)
+                {
+                    continue;
+                }
+                let mut slots = slots.clone();
+                if let Some(slot) = slots.get_mut(transition.tag as usize) {
+                    *slot = Some(pos);
+                }
+                self.add_capture_thread(
+                    threads,
+                    visited,
+                    transition.to.clone(),
+                    slots,
+                    (pos, prev, next),
+                );
+            }
+        }
+        threads.push((state, slots));
     }
 
-    #[test]
-    fn test_perl() -> Result<()> {
-        {
-            let mut parser = RegexParser::new();
-            let parsed_ast = parser.parse_into_ast(r"\d")?;
-
-            let mut nfa = NFA::new();
-            nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+    // Returns all transitions as (from, to, symbol, tag) tuples in a deterministic order
+    fn get_sorted_transitions(&self) -> Vec<(usize, usize, u128, i16)> {
+        let mut transitions = self
+            .transitions
+            .values()
+            .flatten()
+            .map(|transition| {
+                (
+                    transition.from.0,
+                    transition.to.0,
+                    transition.symbol_onehot_encoding,
+                    transition.tag,
+                )
+            })
+            .collect::<Vec<_>>();
+        transitions.sort();
+        transitions
+    }
 
-            let char_vec: Vec<u8> = (b'0'..=b'9').collect();
-            assert!(has_transition(
-                &nfa,
-                NFA::START_STATE,
-                NFA::ACCEPT_STATE,
-                Transition::convert_char_vec_to_symbol_onehot_encoding(char_vec)
-            ));
+    // Returns the states reachable from the start state (including itself) through any transition,
+    // including epsilon transitions, in BFS order
+    pub fn reachable_states(&self) -> Vec<State> {
+        let mut visited = vec![false; self.states.len()];
+        let mut reachable = Vec::new();
+        let mut queue = VecDeque::new();
+        visited[self.start.0] = true;
+        queue.push_back(self.start.clone());
+
+        while let Some(state) = queue.pop_front() {
+            if let Some(transitions) = self.transitions.get(&state) {
+                for transition in transitions {
+                    let to_state = transition.get_to_state();
+                    if false == visited[to_state.0] {
+                        visited[to_state.0] = true;
+                        queue.push_back(to_state);
+                    }
+                }
+            }
+            reachable.push(state);
         }
 
-        {
-            let mut parser = RegexParser::new();
+        reachable
+    }
+
+    // Returns the NFA accepting the reversed strings: start and accept are swapped and every
+    // transition is flipped, keeping its symbols (epsilon transitions stay epsilon). If the start
+    // state is accepting, a new start state is added with epsilon transitions to both former
+    // accepting states. Word boundary assertions keep their tags, since they hold the same way in
+    // both directions.
+    pub fn reverse(&self) -> NFA {
+        let mut transitions: HashMap<State, Vec<Transition>> = HashMap::new();
+        for transition in self.transitions.values().flatten() {
+            transitions
+                .entry(transition.to.clone())
+                .or_default()
+                .push(Transition {
+                    from: transition.to.clone(),
+                    to: transition.from.clone(),
+                    symbol_onehot_encoding: transition.symbol_onehot_encoding,
+                    tag: transition.tag,
+                });
+        }
+        let mut reversed_nfa = NFA {
+            start: self.accept.clone(),
+            accept: self.start.clone(),
+            states: self.states.clone(),
+            transitions,
+            start_accepting: false,
+            alphabet: self.alphabet,
+            capture_names: Vec::new(),
+            budget: None,
+        };
+        if self.start_accepting {
+            let new_start = reversed_nfa.new_state();
+            reversed_nfa.add_epsilon_transition(new_start.clone(), self.accept.clone());
+            reversed_nfa.add_epsilon_transition(new_start.clone(), self.start.clone());
+            reversed_nfa.start = new_start;
+        }
+        reversed_nfa
+    }
+
+    // Returns an equivalent NFA without epsilon transitions. Each state takes over the non-epsilon
+    // transitions of its epsilon closure (keeping their tags), and any transition into a state
+    // whose closure contains the accept state also gets a copy going to the accept state. States
+    // unreachable from the start state are dropped and the remaining ones are renumbered, with the
+    // start state first and the accept state second. Word boundary assertions are crossed as if
+    // they always held, so the result may accept more strings (see `has_assertions`).
+    pub fn remove_epsilons(&self) -> NFA {
+        let reaches_accept = |state: &State| -> bool {
+            self.epsilon_closure(&vec![state.clone()])
+                .iter()
+                .any(|closure_state| self.is_accept_state(closure_state))
+        };
+
+        let mut transitions: Vec<(State, State, u128, i16)> = Vec::new();
+        for state in &self.states {
+            for closure_state in self.epsilon_closure(&vec![state.clone()]) {
+                let Some(closure_transitions) = self.transitions.get(&closure_state) else {
+                    continue;
+                };
+                for transition in closure_transitions {
+                    if EPSILON_TRANSITION == transition.symbol_onehot_encoding {
+                        continue;
+                    }
+                    let mut to_states = vec![transition.to.clone()];
+                    if transition.to != self.accept && reaches_accept(&transition.to) {
+                        to_states.push(self.accept.clone());
+                    }
+                    for to_state in to_states {
+                        let new_transition = (
+                            state.clone(),
+                            to_state,
+                            transition.symbol_onehot_encoding,
+                            transition.tag,
+                        );
+                        if false == transitions.contains(&new_transition) {
+                            transitions.push(new_transition);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut epsilon_free_nfa = NFA {
+            start: self.start.clone(),
+            accept: self.accept.clone(),
+            states: self.states.clone(),
+            transitions: HashMap::new(),
+            start_accepting: reaches_accept(&self.start),
+            alphabet: self.alphabet,
+            capture_names: Vec::new(),
+            budget: None,
+        };
+        for (from, to, symbol_onehot_encoding, tag) in transitions {
+            epsilon_free_nfa
+                .transitions
+                .entry(from.clone())
+                .or_default()
+                .push(Transition {
+                    from,
+                    to,
+                    symbol_onehot_encoding,
+                    tag,
+                });
+        }
+
+        // Renumber the reachable states, keeping the accept state even if it is unreachable
+        let mut kept_states = epsilon_free_nfa.reachable_states();
+        if false == kept_states.contains(&self.accept) {
+            kept_states.push(self.accept.clone());
+        }
+        kept_states.retain(|state| *state != self.start && *state != self.accept);
+        let mut renumbering = HashMap::new();
+        renumbering.insert(self.start.clone(), NFA::START_STATE);
+        renumbering.insert(self.accept.clone(), NFA::ACCEPT_STATE);
+        for (idx, state) in kept_states.into_iter().enumerate() {
+            renumbering.insert(state, State(idx + 2));
+        }
+
+        let mut renumbered_transitions: HashMap<State, Vec<Transition>> = HashMap::new();
+        for transition in epsilon_free_nfa.transitions.values().flatten() {
+            let Some(from) = renumbering.get(&transition.from) else {
+                continue;
+            };
+            renumbered_transitions
+                .entry(from.clone())
+                .or_default()
+                .push(Transition {
+                    from: from.clone(),
+                    to: renumbering.get(&transition.to).unwrap().clone(),
+                    symbol_onehot_encoding: transition.symbol_onehot_encoding,
+                    tag: transition.tag,
+                });
+        }
+
+        NFA {
+            start: NFA::START_STATE,
+            accept: NFA::ACCEPT_STATE,
+            states: (0..renumbering.len()).map(State).collect(),
+            transitions: renumbered_transitions,
+            start_accepting: epsilon_free_nfa.start_accepting,
+            alphabet: self.alphabet,
+            capture_names: Vec::new(),
+            budget: None,
+        }
+    }
+
+    // Returns an NFA accepting the strings accepted by either NFA, without re-parsing them: a new
+    // start state has epsilon transitions into both (renumbered) NFAs, whose accept states have
+    // epsilon transitions into a new accept state. The capture groups of `b` are numbered after the
+    // ones of `a`.
+    pub fn union(a: NFA, b: NFA) -> NFA {
+        let mut union = NFA::with_alphabet(a.alphabet | b.alphabet);
+        for nfa in [a, b] {
+            let (start, accept) = union.add_renumbered_nfa(nfa);
+            union.add_epsilon_transition(NFA::START_STATE, start);
+            union.add_epsilon_transition(accept, NFA::ACCEPT_STATE);
+        }
+        union
+    }
+
+    // Copies the states and transitions of the given NFA into this one, shifting state IDs past the
+    // existing states and capture tags past the existing capture groups. Returns the copied start
+    // and accept states.
+    fn add_renumbered_nfa(&mut self, nfa: NFA) -> (State, State) {
+        let state_offset = self.states.len();
+        let tag_offset = 2 * self.capture_names.len() as i16;
+        for _ in 0..nfa.states.len() {
+            self.new_state();
+        }
+        for transition in nfa.transitions.into_values().flatten() {
+            let from = State(transition.from.0 + state_offset);
+            self.transitions
+                .entry(from.clone())
+                .or_default()
+                .push(Transition {
+                    from,
+                    to: State(transition.to.0 + state_offset),
+                    symbol_onehot_encoding: transition.symbol_onehot_encoding,
+                    tag: match transition.tag {
+                        tag if tag < 0 => tag,
+                        tag => tag + tag_offset,
+                    },
+                });
+        }
+        self.capture_names.extend(nfa.capture_names);
+
+        let start = State(nfa.start.0 + state_offset);
+        let accept = State(nfa.accept.0 + state_offset);
+        if nfa.start_accepting {
+            self.add_epsilon_transition(start.clone(), accept.clone());
+        }
+        (start, accept)
+    }
+
+    // Returns an NFA accepting the strings accepted by both NFAs, built as the product of their
+    // epsilon-free equivalents. Each reachable pair of states becomes a state, with a transition
+    // on the symbols both NFAs can consume. Transitions into a pair of accepting states also lead
+    // to the new accept state, which has no outgoing transitions. Like `remove_epsilons`, word
+    // boundary assertions are treated as always holding.
+    pub fn intersect(&self, other: &NFA) -> NFA {
+        let lhs = self.remove_epsilons();
+        let rhs = other.remove_epsilons();
+
+        let mut product = NFA::with_alphabet(self.alphabet & other.alphabet);
+        product.start_accepting =
+            lhs.is_accept_state(&lhs.start) && rhs.is_accept_state(&rhs.start);
+        let mut pair_states: HashMap<(State, State), State> = HashMap::new();
+        pair_states.insert((lhs.start.clone(), rhs.start.clone()), NFA::START_STATE);
+        let mut queue = VecDeque::new();
+        queue.push_back((lhs.start.clone(), rhs.start.clone()));
+
+        while let Some((lhs_state, rhs_state)) = queue.pop_front() {
+            let from = pair_states
+                .get(&(lhs_state.clone(), rhs_state.clone()))
+                .unwrap()
+                .clone();
+            let (Some(lhs_transitions), Some(rhs_transitions)) = (
+                lhs.transitions.get(&lhs_state),
+                rhs.transitions.get(&rhs_state),
+            ) else {
+                continue;
+            };
+            for lhs_transition in lhs_transitions {
+                for rhs_transition in rhs_transitions {
+                    let onehot = lhs_transition.symbol_onehot_encoding
+                        & rhs_transition.symbol_onehot_encoding;
+                    if EPSILON_TRANSITION == onehot {
+                        continue;
+                    }
+                    let pair = (lhs_transition.to.clone(), rhs_transition.to.clone());
+                    let to = match pair_states.get(&pair) {
+                        Some(to) => to.clone(),
+                        None => {
+                            let to = product.new_state();
+                            pair_states.insert(pair.clone(), to.clone());
+                            queue.push_back(pair.clone());
+                            to
+                        }
+                    };
+                    product.add_transition(from.clone(), to, onehot);
+                    if lhs.is_accept_state(&pair.0) && rhs.is_accept_state(&pair.1) {
+                        product.add_transition(from.clone(), NFA::ACCEPT_STATE, onehot);
+                    }
+                }
+            }
+        }
+
+        product
+    }
+
+    // Returns an NFA accepting exactly the strings over the NFA's alphabet (see `with_alphabet`)
+    // that this NFA rejects. The epsilon-free equivalent is determinized with the subset
+    // construction, completed with a dead state (the empty subset) looping on the whole alphabet,
+    // and its accepting and non-accepting states are swapped. Transitions into states that are
+    // accepting after the swap also lead to the new accept state, which has no outgoing
+    // transitions. Returns `LimitExceeded` if the determinized automaton would have more than
+    // `MAX_DETERMINIZED_STATES` states, and `UnsupportedAstNodeType` if the NFA has word boundary
+    // assertions, whose complement can't be expressed by swapping states.
+    pub fn complement(&self) -> Result<NFA> {
+        if self.has_assertions() {
+            return Err(UnsupportedAstNodeType(
+                "Word boundary assertions can't be complemented",
+            ));
+        }
+        let epsilon_free_nfa = self.remove_epsilons();
+        let is_accepting_subset = |subset: &Vec<State>| -> bool {
+            subset
+                .iter()
+                .any(|state| epsilon_free_nfa.is_accept_state(state))
+        };
+
+        let mut complement = NFA::with_alphabet(self.alphabet);
+        let start_subset = vec![epsilon_free_nfa.start.clone()];
+        complement.start_accepting = false == is_accepting_subset(&start_subset);
+        let mut subset_states: HashMap<Vec<State>, State> = HashMap::new();
+        subset_states.insert(start_subset.clone(), NFA::START_STATE);
+        let mut queue = VecDeque::new();
+        queue.push_back(start_subset);
+
+        while let Some(subset) = queue.pop_front() {
+            let from = subset_states.get(&subset).unwrap().clone();
+
+            // Group the symbols of the alphabet by the subset they lead to
+            let mut onehots_by_subset: Vec<(Vec<State>, u128)> = Vec::new();
+            for symbol in 0..128u8 {
+                let symbol_onehot_encoding = 1u128 << symbol;
+                if 0 == self.alphabet & symbol_onehot_encoding {
+                    continue;
+                }
+                let mut next_subset: Vec<State> = subset
+                    .iter()
+                    .filter_map(|state| epsilon_free_nfa.transitions.get(state))
+                    .flatten()
+                    .filter(|transition| {
+                        0 != transition.symbol_onehot_encoding & symbol_onehot_encoding
+                    })
+                    .map(|transition| transition.to.clone())
+                    .collect();
+                next_subset.sort_by_key(|state| state.0);
+                next_subset.dedup();
+                match onehots_by_subset
+                    .iter_mut()
+                    .find(|(other_subset, _)| *other_subset == next_subset)
+                {
+                    Some((_, onehot)) => *onehot |= symbol_onehot_encoding,
+                    None => onehots_by_subset.push((next_subset, symbol_onehot_encoding)),
+                }
+            }
+
+            for (next_subset, onehot) in onehots_by_subset {
+                let to = match subset_states.get(&next_subset) {
+                    Some(to) => to.clone(),
+                    None => {
+                        if subset_states.len() >= Self::MAX_DETERMINIZED_STATES {
+                            return Err(LimitExceeded {
+                                kind: LimitKind::DeterminizedStates,
+                                limit: Self::MAX_DETERMINIZED_STATES,
+                            });
+                        }
+                        let to = complement.new_state();
+                        subset_states.insert(next_subset.clone(), to.clone());
+                        queue.push_back(next_subset.clone());
+                        to
+                    }
+                };
+                complement.add_transition(from.clone(), to, onehot);
+                if false == is_accepting_subset(&next_subset) {
+                    complement.add_transition(from.clone(), NFA::ACCEPT_STATE, onehot);
+                }
+            }
+        }
+
+        Ok(complement)
+    }
+
+    // Returns an NFA accepting the prefixes of the strings accepted by this NFA (including the
+    // empty string if this NFA accepts anything): every state from which the accept state can be
+    // reached gets an epsilon transition to it.
+    pub fn prefixes(&self) -> NFA {
+        let mut prefix_nfa = self.clone();
+        prefix_nfa.budget = None;
+        let co_reachable_states = self.reverse().reachable_states();
+        for state in co_reachable_states {
+            if state.0 < self.states.len() && state != self.accept {
+                prefix_nfa.add_epsilon_transition(state, self.accept.clone());
+            }
+        }
+        prefix_nfa
+    }
+
+    // Returns whether the NFA accepts no string at all
+    pub fn is_empty_language(&self) -> bool {
+        false == self.start_accepting && false == self.reachable_states().contains(&self.accept)
+    }
+
+    // Returns the length of the shortest accepted string (0 if the empty string is accepted), or
+    // `None` if no string is accepted. Epsilon transitions don't consume any character.
+    pub fn min_match_length(&self) -> Option<usize> {
+        let mut lengths: Vec<Option<usize>> = vec![None; self.states.len()];
+        let mut queue = VecDeque::new();
+        lengths[self.start.0] = Some(0);
+        queue.push_back(self.start.clone());
+
+        // 0-1 BFS: states reached through epsilon transitions are visited first
+        while let Some(state) = queue.pop_front() {
+            let length = lengths[state.0].unwrap();
+            if self.is_accept_state(&state) {
+                return Some(length);
+            }
+            let Some(transitions) = self.transitions.get(&state) else {
+                continue;
+            };
+            for transition in transitions {
+                let is_epsilon = EPSILON_TRANSITION == transition.symbol_onehot_encoding;
+                let to_length = if is_epsilon { length } else { length + 1 };
+                if lengths[transition.to.0].is_some_and(|known_length| known_length <= to_length) {
+                    continue;
+                }
+                lengths[transition.to.0] = Some(to_length);
+                if is_epsilon {
+                    queue.push_front(transition.to.clone());
+                } else {
+                    queue.push_back(transition.to.clone());
+                }
+            }
+        }
+        None
+    }
+
+    // Returns the length of the longest accepted string, or `None` if arbitrarily long strings are
+    // accepted (e.g. through `*` or `+`) or if no string is accepted at all. Only the states lying
+    // on a path from the start state to an accepting state are considered, so a loop that can't
+    // lead to a match doesn't make the length unbounded.
+    pub fn max_match_length(&self) -> Option<usize> {
+        let reachable = self.reachable_states();
+        let reversed_nfa = self.reverse();
+        let mut co_reachable = vec![false; reversed_nfa.states.len()];
+        for state in reversed_nfa.reachable_states() {
+            co_reachable[state.0] = true;
+        }
+        let mut is_useful = vec![false; self.states.len()];
+        for state in reachable {
+            is_useful[state.0] = co_reachable[state.0];
+        }
+        if false == is_useful[self.start.0] {
+            return None;
+        }
+
+        // Longest paths with Bellman-Ford: a path without cycles takes fewer transitions than the
+        // number of states, so a length still growing after that many rounds comes from a cycle
+        // consuming characters
+        let mut lengths: Vec<Option<usize>> = vec![None; self.states.len()];
+        lengths[self.start.0] = Some(0);
+        for _ in 0..self.states.len() {
+            let mut updated = false;
+            for transition in self.transitions.values().flatten() {
+                if false == is_useful[transition.from.0] || false == is_useful[transition.to.0] {
+                    continue;
+                }
+                let Some(length) = lengths[transition.from.0] else {
+                    continue;
+                };
+                let to_length = if EPSILON_TRANSITION == transition.symbol_onehot_encoding {
+                    length
+                } else {
+                    length + 1
+                };
+                if lengths[transition.to.0].is_some_and(|known_length| known_length >= to_length) {
+                    continue;
+                }
+                lengths[transition.to.0] = Some(to_length);
+                updated = true;
+            }
+            if false == updated {
+                return self
+                    .states
+                    .iter()
+                    .filter(|state| self.is_accept_state(state))
+                    .filter_map(|state| lengths[state.0])
+                    .max();
+            }
+        }
+        None
+    }
+
+    // Merges the transitions sharing the same from state, to state and tag into a single transition
+    // whose symbol mask is the union of theirs. Epsilon transitions are kept separate.
+    pub fn coalesce_parallel_transitions(&mut self) {
+        for transitions in self.transitions.values_mut() {
+            let mut coalesced_transitions: Vec<Transition> = Vec::with_capacity(transitions.len());
+            for transition in transitions.drain(..) {
+                if EPSILON_TRANSITION != transition.symbol_onehot_encoding {
+                    if let Some(parallel_transition) =
+                        coalesced_transitions
+                            .iter_mut()
+                            .find(|coalesced_transition| {
+                                EPSILON_TRANSITION != coalesced_transition.symbol_onehot_encoding
+                                    && coalesced_transition.to == transition.to
+                                    && coalesced_transition.tag == transition.tag
+                            })
+                    {
+                        parallel_transition.symbol_onehot_encoding |=
+                            transition.symbol_onehot_encoding;
+                        continue;
+                    }
+                }
+                coalesced_transitions.push(transition);
+            }
+            *transitions = coalesced_transitions;
+        }
+    }
+
+    // Builds the DFA of this NFA with the subset construction, along with the NFA states each DFA
+    // state stands for (sorted by number), e.g. to trace an accepting DFA state back to the NFA
+    // states it was built from. See `get_combined_state_names` to name a subset. Word boundary
+    // assertions are crossed unconditionally, as the DFA has no notion of the previous character.
+    pub fn determinize(&self) -> (DFA, HashMap<DfaState, Vec<State>>) {
+        let (dfa, state_mapping) = DFA::from_multiple_nfas_with_state_mapping(vec![self.clone()]);
+        let state_mapping = state_mapping
+            .into_iter()
+            .map(|(dfa_state, nfa_states)| {
+                let nfa_states = nfa_states.into_iter().map(|(_, state)| state).collect();
+                (dfa_state, nfa_states)
+            })
+            .collect();
+        (dfa, state_mapping)
+    }
+
+    // Static function to get the combined state names
+    pub fn get_combined_state_names(states: &Vec<State>) -> String {
+        let mut names = states
+            .iter()
+            .map(|state| state.0.to_string())
+            .collect::<Vec<String>>();
+        names.sort();
+        names.join(",")
+    }
+
+    // Static function to get the combined state names in a human-friendly form, e.g. `{q3,q5}`,
+    // with the states sorted by number
+    pub fn get_combined_state_display_names(states: &[State]) -> String {
+        let mut state_ids = states.iter().map(|state| state.0).collect::<Vec<usize>>();
+        state_ids.sort();
+        let names = state_ids
+            .into_iter()
+            .map(|state_id| State(state_id).to_string())
+            .collect::<Vec<String>>();
+        format!("{{{}}}", names.join(","))
+    }
+}
+
+// Getter functions for NFA
+impl NFA {
+    pub fn get_start(&self) -> State {
+        self.start.clone()
+    }
+
+    pub fn get_accept(&self) -> State {
+        self.accept.clone()
+    }
+
+    pub fn is_start_accepting(&self) -> bool {
+        self.start_accepting
+    }
+
+    pub fn is_accept_state(&self, state: &State) -> bool {
+        *state == self.accept || (self.start_accepting && *state == self.start)
+    }
+
+    pub fn get_transitions(&self) -> &HashMap<State, Vec<Transition>> {
+        &self.transitions
+    }
+
+    pub fn get_transitions_from_state(&self, state: &State) -> Option<&Vec<Transition>> {
+        self.transitions.get(state)
+    }
+
+    // Returns the transitions targeting the given state. This scans all transitions.
+    pub fn get_transitions_to_state(&self, state: &State) -> Vec<&Transition> {
+        self.transitions
+            .values()
+            .flatten()
+            .filter(|transition| transition.to == *state)
+            .collect()
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    // Returns the number of transitions, including epsilon transitions
+    pub fn num_transitions(&self) -> usize {
+        self.transitions
+            .values()
+            .map(|transitions| transitions.len())
+            .sum()
+    }
+
+    pub fn num_epsilon_transitions(&self) -> usize {
+        self.transitions
+            .values()
+            .flatten()
+            .filter(|transition| EPSILON_TRANSITION == transition.symbol_onehot_encoding)
+            .count()
+    }
+
+    pub fn stats(&self) -> NFAStats {
+        NFAStats {
+            num_states: self.num_states(),
+            num_transitions: self.num_transitions(),
+            num_epsilon_transitions: self.num_epsilon_transitions(),
+        }
+    }
+}
+
+// Helper functions
+
+// Symbols are encoded into a 128-bit one-hot mask, so only ASCII characters (`\x00` to `\x7f`,
+// including control characters written as hex or octal escapes) are supported. Any character
+// beyond `\x7f` is rejected with `NoneASCIICharacters`.
+fn get_ascii_char(c: char) -> Result<u8> {
+    if false == c.is_ascii() {
+        return Err(NoneASCIICharacters);
+    }
+    Ok(c as u8)
+}
+
+fn get_ascii_byte(byte: u8) -> Result<u8> {
+    if false == byte.is_ascii() {
+        return Err(NoneASCIICharacters);
+    }
+    Ok(byte)
+}
+
+// Walks the AST and returns the error `add_ast_to_nfa` would return for the first construct it
+// doesn't support, without building the NFA. Unlike `RegexParser::with_feature_validation`, the
+// errors are the same as the NFA construction's, so it can be used as a pre-flight check.
+pub fn check_supported(ast: &Ast) -> Result<()> {
+    NFA::new().check_ast_supported(ast)
+}
+
+// Returns the minimum and the maximum (if bounded) number of repetitions, or an error if the
+// repetition isn't supported
+fn get_repetition_range(repetition: &Repetition) -> Result<(u32, Option<u32>)> {
+    if false == repetition.greedy {
+        return Err(NonGreedyRepetitionNotSupported);
+    }
+    let (min, optional_max) = match &repetition.op.kind {
+        RepetitionKind::ZeroOrOne => (0, Some(1)),
+        RepetitionKind::ZeroOrMore => (0, None),
+        RepetitionKind::OneOrMore => (1, None),
+        RepetitionKind::Range(range) => match range {
+            RepetitionRange::Exactly(num) => (*num, Some(*num)),
+            RepetitionRange::AtLeast(num) => (*num, None),
+            RepetitionRange::Bounded(begin, end) => (*begin, Some(*end)),
+        },
+    };
+    if let Some(max) = optional_max {
+        // The regex parser already rejects these, but an AST can be built by hand
+        if max < min {
+            return Err(InvalidRepetitionRange { min, max });
+        }
+    }
+    Ok((min, optional_max))
+}
+
+// Returns the index (groups are numbered from 1 by the parser) and the name of a capture group, or
+// `None` for a non-capturing group without flags, e.g. `(?:ab)`, which only groups its pattern
+fn get_group_capture(group: &Group) -> Result<Option<(usize, Option<String>)>> {
+    match &group.kind {
+        GroupKind::CaptureIndex(index) => Ok(Some((*index as usize - 1, None))),
+        GroupKind::CaptureName { name, .. } => {
+            Ok(Some((name.index as usize - 1, Some(name.name.clone()))))
+        }
+        GroupKind::NonCapturing(flags) if flags.items.is_empty() => Ok(None),
+        _ => Err(UnsupportedGroupKindType),
+    }
+}
+
+fn get_assertion_tag(kind: &AssertionKind) -> Result<i16> {
+    match kind {
+        AssertionKind::WordBoundary => Ok(WORD_BOUNDARY_TAG),
+        AssertionKind::NotWordBoundary => Ok(NOT_WORD_BOUNDARY_TAG),
+        _ => Err(UnsupportedAstNodeType(
+            "Only word boundary assertions are supported",
+        )),
+    }
+}
+
+// Returns whether the assertion the transition tag stands for (if any) holds between the characters
+// `prev` and `next`, where `None` is an end of the input. A word boundary separates a word
+// character (`\w`) from a non-word character or an end of the input.
+fn is_assertion_satisfied(tag: i16, prev: Option<char>, next: Option<char>) -> bool {
+    let is_word_char = |c: Option<char>| match c {
+        Some(c) if c.is_ascii() => 0 != WORD_TRANSITION & (1u128 << c as u8),
+        _ => false,
+    };
+    let is_word_boundary = is_word_char(prev) != is_word_char(next);
+    match tag {
+        WORD_BOUNDARY_TAG => is_word_boundary,
+        NOT_WORD_BOUNDARY_TAG => false == is_word_boundary,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error_handling::Error::RegexParsingError;
+
+    #[test]
+    fn test_single_char() -> Result<()> {
+        let mut parser = RegexParser::new();
+        let parsed_ast = parser.parse_into_ast(r"&")?;
+        let mut nfa = NFA::new();
+        nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+
+        assert!(has_transition(
+            &nfa,
+            NFA::START_STATE,
+            NFA::ACCEPT_STATE,
+            Transition::convert_char_to_symbol_onehot_encoding('&')?
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_ascii_symbols_rejected() -> Result<()> {
+        assert_eq!(
+            Transition::convert_char_to_symbol_onehot_encoding('\x7f')?,
+            1 << 127
+        );
+        assert!(matches!(
+            Transition::convert_char_to_symbol_onehot_encoding(char::from(200u8)),
+            Err(NoneASCIICharacters)
+        ));
+        assert!(matches!(
+            Transition::convert_char_range_to_symbol_onehot_encoding(Some((b'a', 200))),
+            Err(NoneASCIICharacters)
+        ));
+        assert!(matches!(
+            Transition::convert_char_vec_to_symbol_onehot_encoding(vec![b'a', 128]),
+            Err(NoneASCIICharacters)
+        ));
+
+        // Non-ASCII input never matches rather than aliasing an ASCII symbol
+        let nfa = NFA::from_regex(".")?;
+        assert_eq!(nfa.accepts(char::from(200u8).to_string().as_str()), false);
+        assert_eq!(nfa.prefix_status("\u{148}"), PrefixStatus::Dead);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dot() -> Result<()> {
+        {
+            let mut parser = RegexParser::new();
+            let parsed_ast = parser.parse_into_ast(r".")?;
+            let mut nfa = NFA::new();
+            nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+
+            assert!(has_transition(
+                &nfa,
+                NFA::START_STATE,
+                NFA::ACCEPT_STATE,
+                Transition::convert_char_range_to_symbol_onehot_encoding(Some((0, 127)))?
+            ));
+        }
+
+        {
+            // Testing escaped `.`
+            let mut parser = RegexParser::new();
+            let parsed_ast = parser.parse_into_ast(r"\.")?;
+            let mut nfa = NFA::new();
+            nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+
+            assert!(has_transition(
+                &nfa,
+                NFA::START_STATE,
+                NFA::ACCEPT_STATE,
+                Transition::convert_char_to_symbol_onehot_encoding('.')?
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_perl() -> Result<()> {
+        {
+            let mut parser = RegexParser::new();
+            let parsed_ast = parser.parse_into_ast(r"\d")?;
+
+            let mut nfa = NFA::new();
+            nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+
+            let char_vec: Vec<u8> = (b'0'..=b'9').collect();
+            assert!(has_transition(
+                &nfa,
+                NFA::START_STATE,
+                NFA::ACCEPT_STATE,
+                Transition::convert_char_vec_to_symbol_onehot_encoding(char_vec)?
+            ));
+        }
+
+        {
+            let mut parser = RegexParser::new();
             let parsed_ast = parser.parse_into_ast(r"\s")?;
 
             let mut nfa = NFA::new();
@@ -556,7 +1925,7 @@ mod tests {
                 &nfa,
                 NFA::START_STATE,
                 NFA::ACCEPT_STATE,
-                Transition::convert_char_vec_to_symbol_onehot_encoding(char_vec)
+                Transition::convert_char_vec_to_symbol_onehot_encoding(char_vec)?
             ));
         }
 
@@ -576,7 +1945,7 @@ mod tests {
                 &nfa,
                 NFA::START_STATE,
                 NFA::ACCEPT_STATE,
-                Transition::convert_char_vec_to_symbol_onehot_encoding(char_vec)
+                Transition::convert_char_vec_to_symbol_onehot_encoding(char_vec)?
             ));
         }
 
@@ -604,14 +1973,14 @@ mod tests {
             &nfa,
             NFA::START_STATE,
             State(2),
-            Transition::convert_char_to_symbol_onehot_encoding('<')
+            Transition::convert_char_to_symbol_onehot_encoding('<')?
         ));
         assert!(has_transition(&nfa, State(2), State(3), DIGIT_TRANSITION));
         assert!(has_transition(
             &nfa,
             State(3),
             NFA::ACCEPT_STATE,
-            Transition::convert_char_to_symbol_onehot_encoding('>')
+            Transition::convert_char_to_symbol_onehot_encoding('>')?
         ));
 
         Ok(())
@@ -625,76 +1994,82 @@ mod tests {
         let mut nfa = NFA::new();
         nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
 
+        // The single character branches are folded into one transition
         assert!(has_transition(
             &nfa,
             NFA::START_STATE,
-            State(2),
-            EPSILON_TRANSITION
-        ));
-        assert!(has_transition(&nfa, State(2), State(3), DIGIT_TRANSITION));
-        assert!(has_transition(
-            &nfa,
-            State(3),
             NFA::ACCEPT_STATE,
-            EPSILON_TRANSITION
+            DIGIT_TRANSITION | Transition::convert_char_to_symbol_onehot_encoding('a')?
         ));
 
         assert!(has_transition(
             &nfa,
             NFA::START_STATE,
-            State(4),
+            State(2),
             EPSILON_TRANSITION
         ));
         assert!(has_transition(
             &nfa,
+            State(2),
             State(4),
-            State(5),
-            Transition::convert_char_to_symbol_onehot_encoding('a')
+            Transition::convert_char_to_symbol_onehot_encoding('b')?
         ));
         assert!(has_transition(
             &nfa,
+            State(4),
             State(5),
-            NFA::ACCEPT_STATE,
-            EPSILON_TRANSITION
+            Transition::convert_char_to_symbol_onehot_encoding('c')?
         ));
-
         assert!(has_transition(
             &nfa,
-            NFA::START_STATE,
-            State(6),
-            EPSILON_TRANSITION
-        ));
-        assert!(has_transition(
-            &nfa,
-            State(6),
-            State(8),
-            Transition::convert_char_to_symbol_onehot_encoding('b')
-        ));
-        assert!(has_transition(
-            &nfa,
-            State(8),
-            State(9),
-            Transition::convert_char_to_symbol_onehot_encoding('c')
+            State(5),
+            State(3),
+            Transition::convert_char_to_symbol_onehot_encoding('d')?
         ));
         assert!(has_transition(
             &nfa,
-            State(9),
-            State(7),
-            Transition::convert_char_to_symbol_onehot_encoding('d')
+            State(3),
+            NFA::ACCEPT_STATE,
+            EPSILON_TRANSITION
         ));
+        assert_eq!(nfa.states.len(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alternation_single_symbols() -> Result<()> {
+        // Rather than 2 states per branch along with the start and accept states
+        let nfa = NFA::from_regex(r"a|b|c")?;
+        assert_eq!(nfa.num_states(), 2);
+        assert_eq!(nfa.num_transitions(), 1);
         assert!(has_transition(
             &nfa,
-            State(7),
+            NFA::START_STATE,
             NFA::ACCEPT_STATE,
-            EPSILON_TRANSITION
+            Transition::convert_char_range_to_symbol_onehot_encoding(Some((b'a', b'c')))?
         ));
 
+        let nfa = NFA::from_regex(r"[0-9]|[a-f]|\s|.")?;
+        assert_eq!(nfa.num_states(), 2);
+        assert_eq!(nfa.num_transitions(), 1);
+
+        // Other branches keep the general construction
+        let nfa = NFA::from_regex(r"a|bc|d|")?;
+        assert_eq!(nfa.num_states(), 2 + 3 + 2);
+        for input in ["a", "bc", "d", ""] {
+            assert!(nfa.accepts(input));
+        }
+        for input in ["b", "ad", "bcd"] {
+            assert!(false == nfa.accepts(input));
+        }
+
         Ok(())
     }
 
     #[test]
     fn test_repetition() -> Result<()> {
-        let a_transition = Transition::convert_char_to_symbol_onehot_encoding('a');
+        let a_transition = Transition::convert_char_to_symbol_onehot_encoding('a')?;
         let range_bound_state = State(2);
 
         {
@@ -1036,6 +2411,88 @@ mod tests {
 
         let mut nfa = NFA::new();
         nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+        println!("{:?}", nfa);
+        // Each repetition of the group is delimited by tagged epsilon transitions, e.g. 0 -> 3 and
+        // 4 -> 2 for the first one. The alternation is a single transition.
+        let eps = EPSILON_TRANSITION;
+        let expected = [
+            (0, 3, eps),
+            (3, 4, SPACE_TRANSITION | DIGIT_TRANSITION),
+            (4, 2, eps),
+            (2, 1, eps),
+            (2, 5, eps),
+            (5, 6, SPACE_TRANSITION | DIGIT_TRANSITION),
+            (6, 2, eps),
+        ];
+        for (from, to, symbol) in expected {
+            assert!(has_transition(&nfa, State(from), State(to), symbol));
+        }
+        assert_eq!(nfa.get_transitions_from_state(&State(0)).unwrap()[0].tag, 0);
+        assert_eq!(nfa.get_transitions_from_state(&State(4)).unwrap()[0].tag, 1);
+
+        assert_eq!(nfa.states.len(), 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bracketed() -> Result<()> {
+        let mut parser = RegexParser::new();
+        let parsed_ast = parser.parse_into_ast(r"[a-c3-9[A-X]]")?;
+
+        let mut nfa = NFA::new();
+        nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+
+        // All items (including the nested bracket) are merged into a single transition
+        assert_eq!(nfa.num_states(), 2);
+        assert_eq!(nfa.num_transitions(), 1);
+        assert!(has_transition(
+            &nfa,
+            NFA::START_STATE,
+            NFA::ACCEPT_STATE,
+            Transition::convert_char_range_to_symbol_onehot_encoding(Some((b'a', b'c')))?
+                | Transition::convert_char_range_to_symbol_onehot_encoding(Some((b'3', b'9')))?
+                | Transition::convert_char_range_to_symbol_onehot_encoding(Some((b'A', b'X')))?
+        ));
+
+        // Overlapping ranges are merged
+        let mut parser = RegexParser::new();
+        let parsed_ast = parser.parse_into_ast(r"[a-cb-d]")?;
+        let mut nfa = NFA::new();
+        nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+        assert_eq!(nfa.num_transitions(), 1);
+        assert!(has_transition(
+            &nfa,
+            NFA::START_STATE,
+            NFA::ACCEPT_STATE,
+            Transition::convert_char_range_to_symbol_onehot_encoding(Some((b'a', b'd')))?
+        ));
+
+        let mut parser = RegexParser::new();
+        let parsed_ast = parser.parse_into_ast(r"[\d_[x[y-z]]]")?;
+        let mut nfa = NFA::new();
+        nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+        assert_eq!(nfa.num_transitions(), 1);
+        assert!(has_transition(
+            &nfa,
+            NFA::START_STATE,
+            NFA::ACCEPT_STATE,
+            DIGIT_TRANSITION
+                | Transition::convert_char_to_symbol_onehot_encoding('_')?
+                | Transition::convert_char_range_to_symbol_onehot_encoding(Some((b'x', b'z')))?
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_floating_point_regex() -> Result<()> {
+        let mut parser = RegexParser::new();
+        let parsed_ast = parser.parse_into_ast(r"\-{0,1}[0-9]+\.\d+")?;
+
+        let mut nfa = NFA::new();
+        nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+
         println!("{:?}", nfa);
 
         assert!(has_transition(
@@ -1044,135 +2501,696 @@ mod tests {
             State(3),
             EPSILON_TRANSITION
         ));
-        assert!(has_transition(&nfa, State(3), State(4), SPACE_TRANSITION));
+        assert!(has_transition(
+            &nfa,
+            State(3),
+            State(4),
+            Transition::convert_char_to_symbol_onehot_encoding('-')?
+        ));
         assert!(has_transition(&nfa, State(4), State(2), EPSILON_TRANSITION));
+
+        assert!(has_transition(&nfa, State(2), State(6), DIGIT_TRANSITION));
+        assert!(has_transition(&nfa, State(6), State(6), DIGIT_TRANSITION));
+
+        assert!(has_transition(&nfa, State(6), State(5), EPSILON_TRANSITION));
+
+        assert!(has_transition(
+            &nfa,
+            State(5),
+            State(7),
+            Transition::convert_char_to_symbol_onehot_encoding('.')?
+        ));
+        assert!(has_transition(&nfa, State(7), State(8), DIGIT_TRANSITION));
+        assert!(has_transition(&nfa, State(8), State(8), DIGIT_TRANSITION));
+        assert!(has_transition(&nfa, State(8), State(1), EPSILON_TRANSITION));
+
+        assert_eq!(nfa.states.len(), 9);
+
+        Ok(())
+    }
+
+    fn has_transition(nfa: &NFA, from: State, to: State, onehot_trans: u128) -> bool {
+        if from.0 >= nfa.states.len() || to.0 >= nfa.states.len() {
+            return false;
+        }
+        if false == nfa.transitions.contains_key(&from) {
+            return false;
+        }
+        for trans in nfa.transitions.get(&from).unwrap() {
+            if to != trans.to {
+                continue;
+            }
+            if trans.symbol_onehot_encoding == onehot_trans {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn has_no_transition(nfa: &NFA, from: State, to: State, onehot_trans: u128) -> bool {
+        false == has_transition(nfa, from, to, onehot_trans)
+    }
+
+    #[test]
+    fn test_symbol_ranges() {
+        let digit = Transition::new(State(0), State(1), DIGIT_TRANSITION, -1);
+        assert_eq!(digit.get_symbol_ranges(), vec![(b'0', b'9')]);
+
+        let word = Transition::new(State(0), State(1), WORD_TRANSITION, -1);
+        assert_eq!(
+            word.get_symbol_ranges(),
+            vec![(b'0', b'9'), (b'A', b'Z'), (b'_', b'_'), (b'a', b'z')]
+        );
+
+        let dot = Transition::new(State(0), State(1), DOT_TRANSITION, -1);
+        assert_eq!(dot.get_symbol_ranges(), vec![(0, 127)]);
+
+        let epsilon = Transition::new(State(0), State(1), EPSILON_TRANSITION, -1);
+        assert!(epsilon.get_symbol_ranges().is_empty());
+    }
+
+    #[test]
+    fn test_escaped_literals() -> Result<()> {
+        let nfa = NFA::from_regex(r"\x1b")?;
+        assert!(has_transition(
+            &nfa,
+            NFA::START_STATE,
+            NFA::ACCEPT_STATE,
+            Transition::convert_char_to_symbol_onehot_encoding('\x1b')?
+        ));
+
+        let nfa = NFA::from_regex(r"\t")?;
+        assert!(has_transition(
+            &nfa,
+            NFA::START_STATE,
+            NFA::ACCEPT_STATE,
+            Transition::convert_char_to_symbol_onehot_encoding('\t')?
+        ));
+
+        let nfa = NFA::from_regex(r"\012")?;
+        assert!(has_transition(
+            &nfa,
+            NFA::START_STATE,
+            NFA::ACCEPT_STATE,
+            Transition::convert_char_to_symbol_onehot_encoding('\n')?
+        ));
+
+        // The full ASCII range builds valid single-byte transitions
+        for byte in 0..=127u8 {
+            let nfa = NFA::from_regex(format!(r"\x{:02x}", byte).as_str())?;
+            assert!(has_transition(
+                &nfa,
+                NFA::START_STATE,
+                NFA::ACCEPT_STATE,
+                1u128 << byte
+            ));
+        }
+
+        // Non-ASCII characters aren't supported yet
+        assert!(NFA::from_regex(r"\x80").is_err());
+        assert!(NFA::from_regex(r"\xff").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> Result<()> {
+        let nfa = NFA::from_regex(r"a{3,6}")?;
+        // start, accept, the range bound state, 2 states for the mandatory repetitions and 3 for
+        // the optional ones
+        assert_eq!(nfa.num_states(), 8);
+        // 3 mandatory and 3 optional `a` transitions, range bound -> accept, and each optional
+        // repetition -> accept
+        assert_eq!(nfa.num_transitions(), 10);
+        assert_eq!(nfa.num_epsilon_transitions(), 4);
+        assert_eq!(
+            nfa.stats(),
+            NFAStats {
+                num_states: 8,
+                num_transitions: 10,
+                num_epsilon_transitions: 4,
+            }
+        );
+
+        let nfa = NFA::new();
+        assert_eq!(nfa.num_states(), 2);
+        assert_eq!(nfa.num_transitions(), 0);
+        assert_eq!(nfa.num_epsilon_transitions(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_degenerate_repetition_range() -> Result<()> {
+        for pattern in [r"a{0,0}", r"a{0}", r"(a){0}"] {
+            let nfa = NFA::from_regex(pattern)?;
+            assert_eq!(nfa.num_transitions(), nfa.num_epsilon_transitions());
+            assert!(nfa.accepts(""));
+            assert!(false == nfa.accepts("a"));
+            assert_eq!(nfa.min_match_length(), Some(0));
+            assert_eq!(nfa.max_match_length(), Some(0));
+        }
+        // The group is known, but never takes part in a match
+        assert_eq!(NFA::from_regex(r"(a){0}b")?.captures("b"), Some(vec![None]));
+        assert!(NFA::from_regex(r"xa{0,0}y")?.accepts("xy"));
+
+        // The regex parser rejects inverted ranges with their position
+        match NFA::from_regex(r"a{5,2}") {
+            Err(RegexParsingError(e)) => {
+                assert_eq!(
+                    e.kind(),
+                    &regex_syntax::ast::ErrorKind::RepetitionCountInvalid
+                );
+                assert_eq!(e.span().start.offset, 1);
+            }
+            _ => panic!("Expected a regex parsing error"),
+        }
+
+        // So does the NFA construction, for ASTs built by hand
+        let mut ast = RegexParser::new().parse_into_ast(r"a{2,5}")?;
+        if let Ast::Repetition(repetition) = &mut ast {
+            repetition.op.kind = RepetitionKind::Range(RepetitionRange::Bounded(5, 2));
+        }
+        let mut nfa = NFA::new();
+        let result = nfa.add_ast_to_nfa(&ast, NFA::START_STATE, NFA::ACCEPT_STATE);
+        assert!(matches!(
+            result,
+            Err(InvalidRepetitionRange { min: 5, max: 2 })
+        ));
+        assert!(matches!(
+            check_supported(&ast),
+            Err(InvalidRepetitionRange { min: 5, max: 2 })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_repetition_num_states() -> Result<()> {
+        // Each copy of `(abc)` takes 4 states: the group start and end, and 2 states between the
+        // literals. Along with start and accept, `(abc){4,8}` takes 3 states between the mandatory
+        // copies, the range bound state and a state after each optional copy.
+        let nfa = NFA::from_regex(r"(abc){4,8}")?;
+        assert_eq!(nfa.num_states(), 2 + 3 + 1 + 4 + 8 * 4);
+        for num_copies in 0..10 {
+            let input = "abc".repeat(num_copies);
+            assert_eq!(nfa.accepts(&input), (4..=8).contains(&num_copies));
+            if nfa.accepts(&input) {
+                let last_copy_start = 3 * (num_copies - 1);
+                assert_eq!(
+                    nfa.captures(&input),
+                    Some(vec![Some(last_copy_start..last_copy_start + 3)])
+                );
+            }
+        }
+        assert!(false == nfa.accepts("abcabcabcabcab"));
+
+        let nfa = NFA::from_regex(r"a{0,8}")?;
+        assert_eq!(nfa.num_states(), 2 + 1 + 8);
+        for num_copies in 0..10 {
+            assert_eq!(nfa.accepts(&"a".repeat(num_copies)), num_copies <= 8);
+        }
+
+        // A copy of `((ab){2,3})` takes the outer group's 2 states and the 12 states of `(ab){2,3}`:
+        // 3 per copy of `(ab)`, the state between the mandatory copies, the range bound state and
+        // the state after the optional copy
+        let nfa = NFA::from_regex(r"((ab){2,3}){4,8}")?;
+        assert_eq!(
+            nfa.num_states(),
+            2 + 3 + 1 + 4 + 8 * (2 + 3 * 3 + 1 + 1 + 1)
+        );
+        assert!(nfa.accepts(&"ab".repeat(8)));
+        assert!(nfa.accepts(&"ab".repeat(24)));
+        assert!(false == nfa.accepts(&"ab".repeat(7)));
+        assert!(false == nfa.accepts(&"ab".repeat(25)));
+
+        let nfa = NFA::from_regex(r"x((ab){2,3}){2}y")?;
+        assert!(nfa.accepts("xababababy"));
+        assert!(nfa.accepts("xabababababy"));
+        assert!(nfa.accepts("xababababababy"));
+        assert!(false == nfa.accepts("xabababy"));
+        assert!(false == nfa.accepts("xabababababababy"));
+        assert_eq!(
+            nfa.captures("xababababy"),
+            Some(vec![Some(5..9), Some(7..9)])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eq() -> Result<()> {
+        assert_eq!(NFA::from_regex(r"a{0,3}")?, NFA::from_regex(r"a{0,3}")?);
+        assert_eq!(
+            NFA::from_regex(r"\d+(\.\d+)?")?,
+            NFA::from_regex(r"\d+(\.\d+)?")?
+        );
+        assert_ne!(NFA::from_regex(r"a{0,3}")?, NFA::from_regex(r"a{0,2}")?);
+        assert_ne!(NFA::from_regex(r"a")?, NFA::from_regex(r"b")?);
+
+        // Golden NFA for `a{0,3}`, with transitions added in a different order
+        let a_transition = Transition::convert_char_to_symbol_onehot_encoding('a')?;
+        let mut expected = NFA::new();
+        let range_bound_state = expected.new_state();
+        let states = [
+            expected.new_state(),
+            expected.new_state(),
+            expected.new_state(),
+        ];
+        for state in states.iter().rev() {
+            expected.add_epsilon_transition(state.clone(), NFA::ACCEPT_STATE);
+        }
+        expected.add_transition(states[1].clone(), states[2].clone(), a_transition);
+        expected.add_transition(states[0].clone(), states[1].clone(), a_transition);
+        expected.add_transition(range_bound_state.clone(), states[0].clone(), a_transition);
+        expected.add_epsilon_transition(range_bound_state.clone(), NFA::ACCEPT_STATE);
+        expected.add_epsilon_transition(NFA::START_STATE, range_bound_state);
+        assert_eq!(NFA::from_regex(r"a{0,3}")?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reachable_states() -> Result<()> {
+        let a_transition = Transition::convert_char_to_symbol_onehot_encoding('a')?;
+        let mut nfa = NFA::new();
+        let intermediate_state = nfa.new_state();
+        let isolated_state = nfa.new_state();
+        let dead_end_state = nfa.new_state();
+        nfa.add_transition(NFA::START_STATE, intermediate_state.clone(), a_transition);
+        nfa.add_epsilon_transition(intermediate_state.clone(), NFA::ACCEPT_STATE);
+        // Only reachable from the isolated state, which itself is unreachable
+        nfa.add_transition(isolated_state.clone(), dead_end_state.clone(), a_transition);
+
+        let reachable_states = nfa.reachable_states();
+        assert_eq!(
+            reachable_states,
+            vec![NFA::START_STATE, intermediate_state, NFA::ACCEPT_STATE]
+        );
+        assert_eq!(reachable_states.contains(&isolated_state), false);
+        assert_eq!(reachable_states.contains(&dead_end_state), false);
+
+        let transition = &nfa.get_transitions_from_state(&isolated_state).unwrap()[0];
+        assert_eq!(transition.get_from_state(), isolated_state);
+        assert_eq!(transition.get_to_state(), dead_end_state);
+
+        // Every state built from a regex is reachable
+        let nfa = NFA::from_regex(r"a{0,3}b+")?;
+        assert_eq!(nfa.reachable_states().len(), nfa.num_states());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alternation_with_empty_branch() -> Result<()> {
+        let nfa = NFA::from_regex(r"(a|)")?;
+        // The empty branch gets its own start state connected to the alternation start/end, which
+        // are the states 2 and 3 delimiting the group. The single character branch is a direct
+        // transition between them.
         assert!(has_transition(
             &nfa,
-            NFA::START_STATE,
-            State(5),
-            EPSILON_TRANSITION
+            State(2),
+            State(3),
+            Transition::convert_char_to_symbol_onehot_encoding('a')?
         ));
-        assert!(has_transition(&nfa, State(5), State(6), DIGIT_TRANSITION));
-        assert!(has_transition(&nfa, State(6), State(2), EPSILON_TRANSITION));
-
-        assert!(has_transition(&nfa, State(2), State(7), EPSILON_TRANSITION));
-        assert!(has_transition(&nfa, State(7), State(8), SPACE_TRANSITION));
-        assert!(has_transition(&nfa, State(8), State(2), EPSILON_TRANSITION));
-        assert!(has_transition(&nfa, State(2), State(9), EPSILON_TRANSITION));
-        assert!(has_transition(&nfa, State(9), State(10), DIGIT_TRANSITION));
+        assert!(has_transition(&nfa, State(4), State(5), EPSILON_TRANSITION));
+        assert_eq!(nfa.reachable_states().len(), nfa.num_states());
+
+        let nfa = NFA::from_regex(r"(|a)")?;
+        assert!(has_transition(&nfa, State(4), State(5), EPSILON_TRANSITION));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_regex() -> Result<()> {
+        let mut nfa = NFA::from_regex(r"a")?;
+        nfa.append_regex(r"b")?;
+        assert_eq!(nfa.get_start(), NFA::START_STATE);
+        assert_eq!(nfa.get_accept(), State(3));
         assert!(has_transition(
             &nfa,
-            State(10),
+            NFA::ACCEPT_STATE,
             State(2),
             EPSILON_TRANSITION
         ));
-
         assert!(has_transition(
             &nfa,
             State(2),
-            NFA::ACCEPT_STATE,
-            EPSILON_TRANSITION
+            State(3),
+            Transition::convert_char_to_symbol_onehot_encoding('b')?
         ));
+        assert!(nfa.accepts("ab"));
 
-        assert_eq!(nfa.states.len(), 11);
+        // The accept state is unchanged if the pattern fails to parse
+        assert!(nfa.append_regex(r"(c").is_err());
+        assert_eq!(nfa.get_accept(), State(3));
 
         Ok(())
     }
 
     #[test]
-    fn test_bracketed() -> Result<()> {
-        let mut parser = RegexParser::new();
-        let parsed_ast = parser.parse_into_ast(r"[a-c3-9[A-X]]")?;
-
-        let mut nfa = NFA::new();
-        nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
-
+    fn test_reverse() -> Result<()> {
+        let a_transition = Transition::convert_char_to_symbol_onehot_encoding('a')?;
+        let b_transition = Transition::convert_char_to_symbol_onehot_encoding('b')?;
+        let nfa = NFA::from_regex(r"ab")?;
+
+        let transitions_to_accept = nfa.get_transitions_to_state(&NFA::ACCEPT_STATE);
+        assert_eq!(transitions_to_accept.len(), 1);
+        assert_eq!(transitions_to_accept[0].get_from_state(), State(2));
+        assert!(nfa.get_transitions_to_state(&NFA::START_STATE).is_empty());
+
+        let reversed_nfa = nfa.reverse();
+        assert_eq!(reversed_nfa.get_start(), NFA::ACCEPT_STATE);
+        assert_eq!(reversed_nfa.get_accept(), NFA::START_STATE);
         assert!(has_transition(
-            &nfa,
-            NFA::START_STATE,
+            &reversed_nfa,
+            NFA::ACCEPT_STATE,
             State(2),
-            Transition::convert_char_range_to_symbol_onehot_encoding(Some((b'a', b'c')))
+            b_transition
         ));
         assert!(has_transition(
-            &nfa,
+            &reversed_nfa,
             State(2),
-            State(3),
-            Transition::convert_char_range_to_symbol_onehot_encoding(Some((b'3', b'9')))
+            NFA::START_STATE,
+            a_transition
         ));
+        assert_eq!(reversed_nfa.stats(), nfa.stats());
+
+        // Reversing twice gives back the original NFA
+        let nfa = NFA::from_regex(r"a{0,3}(b|c)+")?;
+        assert_eq!(nfa.reverse().reverse(), nfa);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_epsilons() -> Result<()> {
+        let a_transition = Transition::convert_char_to_symbol_onehot_encoding('a')?;
+        let nfa = NFA::from_regex(r"a*")?;
+        assert!(nfa.num_epsilon_transitions() > 0);
+
+        let epsilon_free_nfa = nfa.remove_epsilons();
+        assert_eq!(epsilon_free_nfa.num_epsilon_transitions(), 0);
+        assert!(epsilon_free_nfa.is_start_accepting());
+        assert!(epsilon_free_nfa.is_accept_state(&NFA::START_STATE));
         assert!(has_transition(
-            &nfa,
-            State(3),
+            &epsilon_free_nfa,
+            NFA::START_STATE,
             NFA::ACCEPT_STATE,
-            Transition::convert_char_range_to_symbol_onehot_encoding(Some((b'A', b'X')))
+            a_transition
         ));
+        assert_eq!(
+            epsilon_free_nfa.reachable_states().len(),
+            epsilon_free_nfa.num_states()
+        );
+
+        let nfa = NFA::from_regex(r"(\s|\d)+")?;
+        let epsilon_free_nfa = nfa.remove_epsilons();
+        assert_eq!(epsilon_free_nfa.num_epsilon_transitions(), 0);
+        assert_eq!(epsilon_free_nfa.is_start_accepting(), false);
+        assert!(epsilon_free_nfa.num_states() < nfa.num_states());
 
         Ok(())
     }
 
     #[test]
-    fn test_floating_point_regex() -> Result<()> {
-        let mut parser = RegexParser::new();
-        let parsed_ast = parser.parse_into_ast(r"\-{0,1}[0-9]+\.\d+")?;
+    fn test_accepts() -> Result<()> {
+        let nfa = NFA::from_regex(r"(0x)?\d+(\.\d+)?")?;
+        for input in ["0", "0x12", "3.14", "0x1.5"] {
+            assert!(nfa.accepts(input));
+        }
+        for input in ["", "0x", "3.", ".5", "12a", "é"] {
+            assert_eq!(nfa.accepts(input), false);
+        }
+        assert!(NFA::from_regex(r"a*")?.accepts(""));
+        assert!(NFA::from_regex(r"a|b*")?.remove_epsilons().accepts(""));
+
+        Ok(())
+    }
 
+    #[test]
+    fn test_coalesce_parallel_transitions() -> Result<()> {
+        let a_transition = Transition::convert_char_to_symbol_onehot_encoding('a')?;
+        let b_transition = Transition::convert_char_to_symbol_onehot_encoding('b')?;
         let mut nfa = NFA::new();
-        nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+        let state = nfa.new_state();
+        nfa.add_transition(NFA::START_STATE, NFA::ACCEPT_STATE, a_transition);
+        nfa.add_transition(NFA::START_STATE, state.clone(), a_transition);
+        nfa.add_transition(NFA::START_STATE, NFA::ACCEPT_STATE, b_transition);
+        nfa.add_epsilon_transition(NFA::START_STATE, NFA::ACCEPT_STATE);
+        nfa.add_epsilon_transition(NFA::START_STATE, NFA::ACCEPT_STATE);
+        nfa.transitions
+            .get_mut(&NFA::START_STATE)
+            .unwrap()
+            .push(Transition::new(
+                NFA::START_STATE,
+                NFA::ACCEPT_STATE,
+                b_transition,
+                0,
+            ));
 
-        println!("{:?}", nfa);
+        nfa.coalesce_parallel_transitions();
+        let transitions = nfa.get_transitions_from_state(&NFA::START_STATE).unwrap();
+        assert_eq!(transitions.len(), 5);
+        let symbol_transitions_to_accept: Vec<&Transition> = transitions
+            .iter()
+            .filter(|transition| {
+                NFA::ACCEPT_STATE == transition.get_to_state()
+                    && EPSILON_TRANSITION != transition.get_symbol_onehot_encoding()
+                    && -1 == transition.tag
+            })
+            .collect();
+        assert_eq!(symbol_transitions_to_accept.len(), 1);
+        assert_eq!(
+            symbol_transitions_to_accept[0].get_symbol_onehot_encoding(),
+            a_transition | b_transition
+        );
+        assert!(has_transition(&nfa, NFA::START_STATE, state, a_transition));
+        assert_eq!(nfa.num_epsilon_transitions(), 2);
+
+        // Removing epsilons from an alternation creates parallel transitions to the accept state
+        // (groups keep their branches apart, unlike single characters). Coalescing them keeps the
+        // language unchanged.
+        let mut nfa = NFA::from_regex(r"(a|(b)|(\d))+")?.remove_epsilons();
+        let num_transitions = nfa.num_transitions();
+        nfa.coalesce_parallel_transitions();
+        assert!(nfa.num_transitions() < num_transitions);
+        for input in ["a", "ab1", "", "abc"] {
+            assert_eq!(
+                nfa.accepts(input),
+                NFA::from_regex(r"(a|(b)|(\d))+")?.accepts(input)
+            );
+        }
 
-        assert!(has_transition(
-            &nfa,
-            NFA::START_STATE,
-            State(3),
-            EPSILON_TRANSITION
+        Ok(())
+    }
+
+    #[test]
+    fn test_word_boundary() -> Result<()> {
+        let nfa = NFA::from_regex(r"\bcat\b")?;
+        assert_eq!(nfa.find(" cat "), Some(1..4));
+        assert_eq!(nfa.find("category"), None);
+        assert_eq!(nfa.find("bobcat, cat"), Some(8..11));
+        assert!(nfa.accepts("cat"));
+        assert_eq!(nfa.captures("cat"), Some(vec![]));
+
+        // Assertions are zero-width, and only hold between the right kinds of characters
+        let nfa = NFA::from_regex(r"a\b-\B")?;
+        assert!(nfa.accepts("a-"));
+        assert_eq!(nfa.find("xa-y"), None);
+        assert!(NFA::from_regex(r"-\B-")?.accepts("--"));
+        assert_eq!(NFA::from_regex(r"\w+\B")?.find("abc d"), Some(0..2));
+        assert_eq!(
+            NFA::from_regex(r"(\d+)\b(\w*)")?.captures("123"),
+            Some(vec![Some(0..3), Some(3..3)])
+        );
+        assert_eq!(
+            NFA::from_regex(r"ab\bc")?
+                .explain_match("abc")
+                .unmatched_char,
+            Some('c')
+        );
+
+        // Transformations that cross epsilon transitions unconditionally can't enforce assertions
+        assert!(NFA::from_regex(r"\bcat\b")?.has_assertions());
+        assert_eq!(NFA::from_regex("cat")?.has_assertions(), false);
+        assert!(matches!(
+            NFA::from_regex(r"a\Bb")?.complement(),
+            Err(UnsupportedAstNodeType(_))
         ));
-        assert!(has_transition(
-            &nfa,
-            State(3),
-            State(4),
-            Transition::convert_char_to_symbol_onehot_encoding('-')
+        assert!(NFA::from_regex(r"\w+\b")?.reverse().accepts("cba"));
+
+        let check = |pattern: &str| check_supported(&RegexParser::new().parse_into_ast(pattern)?);
+        assert!(check(r"\bERROR\b").is_ok());
+        assert!(matches!(check(r"^ERROR"), Err(UnsupportedAstNodeType(_))));
+        assert!(matches!(
+            NFA::from_regex(r"ERROR$"),
+            Err(UnsupportedAstNodeType(_))
         ));
-        assert!(has_transition(&nfa, State(4), State(2), EPSILON_TRANSITION));
 
-        assert!(has_transition(&nfa, State(2), State(6), DIGIT_TRANSITION));
-        assert!(has_transition(&nfa, State(6), State(6), DIGIT_TRANSITION));
+        Ok(())
+    }
 
-        assert!(has_transition(&nfa, State(6), State(5), EPSILON_TRANSITION));
+    #[test]
+    fn test_explain_match() -> Result<()> {
+        let nfa = NFA::from_regex(r"abc")?;
+        let explanation = nfa.explain_match("abx");
+        assert_eq!(explanation.accepted, false);
+        assert_eq!(explanation.consumed, 2);
+        assert_eq!(explanation.unmatched_char, Some('x'));
+        assert_eq!(explanation.active_states.len(), 1);
+        let expected_symbol = Transition::convert_char_to_symbol_onehot_encoding('c')?;
+        let transitions = nfa
+            .get_transitions_from_state(&explanation.active_states[0])
+            .unwrap();
+        assert_eq!(transitions[0].get_symbol_onehot_encoding(), expected_symbol);
+
+        // The input ends before the match does
+        let explanation = nfa.explain_match("ab");
+        assert_eq!(explanation.accepted, false);
+        assert_eq!(explanation.consumed, 2);
+        assert_eq!(explanation.unmatched_char, None);
+
+        let explanation = nfa.explain_match("abc");
+        assert!(explanation.accepted);
+        assert!(explanation.active_states.contains(&NFA::ACCEPT_STATE));
+
+        // Every alternative is tracked, and positions are in bytes
+        let nfa = NFA::from_regex(r"\d+(\.\d+)?")?;
+        let explanation = nfa.explain_match("3.1e5");
+        assert_eq!(
+            (explanation.consumed, explanation.unmatched_char),
+            (3, Some('e'))
+        );
+        let explanation = NFA::from_regex(r"a+")?.explain_match("a\u{e9}a");
+        assert_eq!(
+            (explanation.consumed, explanation.unmatched_char),
+            (1, Some('\u{e9}'))
+        );
+        assert_eq!(explanation.accepted, false);
 
-        assert!(has_transition(
-            &nfa,
-            State(5),
-            State(7),
-            Transition::convert_char_to_symbol_onehot_encoding('.')
-        ));
-        assert!(has_transition(&nfa, State(7), State(8), DIGIT_TRANSITION));
-        assert!(has_transition(&nfa, State(8), State(8), DIGIT_TRANSITION));
-        assert!(has_transition(&nfa, State(8), State(1), EPSILON_TRANSITION));
+        Ok(())
+    }
 
-        assert_eq!(nfa.states.len(), 9);
+    #[test]
+    fn test_prefixes() -> Result<()> {
+        let nfa = NFA::from_regex(r"\d{4}-\d{2}")?.prefixes();
+        for input in ["", "2", "2024", "2024-", "2024-01"] {
+            assert!(nfa.accepts(input));
+        }
+        for input in ["a", "2024-012", "2024-x"] {
+            assert!(false == nfa.accepts(input));
+        }
+
+        let nfa = NFA::from_regex(r"(ab)+c")?.prefixes();
+        assert!(nfa.accepts("aba"));
+        assert!(nfa.accepts("ababc"));
+        assert!(false == nfa.accepts("abb"));
+
+        // An empty language has no prefixes
+        let nfa = NFA::from_regex(r"[0-9]")?.intersect(&NFA::from_regex(r"[a-z]")?);
+        assert!(nfa.prefixes().is_empty_language());
 
         Ok(())
     }
 
-    fn has_transition(nfa: &NFA, from: State, to: State, onehot_trans: u128) -> bool {
-        if from.0 >= nfa.states.len() || to.0 >= nfa.states.len() {
-            return false;
-        }
-        if false == nfa.transitions.contains_key(&from) {
-            return false;
+    #[test]
+    fn test_intersect() -> Result<()> {
+        let digit_nfa = NFA::from_regex(r"[0-9]")?;
+        assert_eq!(digit_nfa.is_empty_language(), false);
+
+        let intersection = digit_nfa.intersect(&NFA::from_regex(r"[a-z]")?);
+        assert!(intersection.is_empty_language());
+
+        let intersection = digit_nfa.intersect(&NFA::from_regex(r"[5-9a]")?);
+        assert_eq!(intersection.is_empty_language(), false);
+        for c in ['0', '4', 'a'] {
+            assert_eq!(intersection.accepts(c.to_string().as_str()), false);
         }
-        for trans in nfa.transitions.get(&from).unwrap() {
-            if to != trans.to {
-                continue;
-            }
-            if trans.symbol_onehot_encoding == onehot_trans {
-                return true;
-            }
+        for c in ['5', '9'] {
+            assert!(intersection.accepts(c.to_string().as_str()));
         }
-        false
+
+        // Multi-character patterns
+        let hex_nfa = NFA::from_regex(r"0x(\d|[a-f])+")?;
+        let intersection = hex_nfa.intersect(&NFA::from_regex(r"\w+")?);
+        assert!(intersection.accepts("0x1f"));
+        assert_eq!(intersection.accepts("0x"), false);
+        assert!(hex_nfa
+            .intersect(&NFA::from_regex(r"\d+")?)
+            .is_empty_language());
+        assert!(NFA::from_regex(r"a*")?
+            .intersect(&NFA::from_regex(r"b*")?)
+            .accepts(""));
+
+        Ok(())
     }
 
-    fn has_no_transition(nfa: &NFA, from: State, to: State, onehot_trans: u128) -> bool {
-        false == has_transition(nfa, from, to, onehot_trans)
+    #[test]
+    fn test_state_display() {
+        assert_eq!(State(3).to_string(), "q3");
+        assert_eq!(
+            NFA::get_combined_state_display_names(&[State(5), State(3)]),
+            "{q3,q5}"
+        );
+        assert_eq!(
+            NFA::get_combined_state_display_names(&[State(10), State(2)]),
+            "{q2,q10}"
+        );
+        assert_eq!(NFA::get_combined_state_display_names(&[]), "{}");
+    }
+
+    #[test]
+    fn test_from_regex() -> Result<()> {
+        let mut parser = RegexParser::new();
+        let parsed_ast = parser.parse_into_ast(r"a+")?;
+        let mut nfa = NFA::new();
+        nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+
+        let nfa_from_regex = NFA::from_regex(r"a+")?;
+        assert_eq!(nfa_from_regex.get_start(), NFA::START_STATE);
+        assert_eq!(nfa_from_regex.get_accept(), NFA::ACCEPT_STATE);
+        assert_eq!(format!("{:?}", nfa_from_regex), format!("{:?}", nfa));
+
+        assert!(NFA::from_regex(r"\D").is_err());
+        assert!(NFA::from_regex(r"(a").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clone() -> Result<()> {
+        let mut parser = RegexParser::new();
+        let parsed_ast = parser.parse_into_ast(r"ab")?;
+        let mut nfa = NFA::new();
+        nfa.add_ast_to_nfa(&parsed_ast, NFA::START_STATE, NFA::ACCEPT_STATE)?;
+
+        let mut cloned_nfa = nfa.clone();
+        let new_state = cloned_nfa.new_state();
+        cloned_nfa.add_epsilon_transition(NFA::ACCEPT_STATE, new_state.clone());
+        cloned_nfa.add_transition(
+            NFA::START_STATE,
+            new_state.clone(),
+            Transition::convert_char_to_symbol_onehot_encoding('c')?,
+        );
+
+        assert_eq!(nfa.states.len(), 3);
+        assert_eq!(cloned_nfa.states.len(), 4);
+        assert_eq!(false, nfa.transitions.contains_key(&NFA::ACCEPT_STATE));
+        assert_eq!(nfa.transitions.get(&NFA::START_STATE).unwrap().len(), 1);
+        assert_eq!(
+            cloned_nfa.transitions.get(&NFA::START_STATE).unwrap().len(),
+            2
+        );
+        assert!(has_no_transition(
+            &nfa,
+            NFA::START_STATE,
+            new_state,
+            Transition::convert_char_to_symbol_onehot_encoding('c')?
+        ));
+
+        Ok(())
     }
 
     #[test]
@@ -1187,7 +3205,7 @@ mod tests {
         nfa.add_transition(
             State(2),
             State(3),
-            Transition::convert_char_to_symbol_onehot_encoding('a'),
+            Transition::convert_char_to_symbol_onehot_encoding('a').unwrap(),
         );
         nfa.add_epsilon_transition(State(3), State(5));
         nfa.add_epsilon_transition(State(3), State(4));
@@ -1207,4 +3225,379 @@ mod tests {
         assert_eq!(closure.contains(&State(5)), true);
         assert_eq!(closure.contains(&State(6)), true);
     }
+
+    #[test]
+    fn test_complement() -> Result<()> {
+        let nfa = NFA::from_regex("[0-9]+")?;
+        let complement = nfa.complement()?;
+        assert_eq!(complement.accepts("123"), false);
+        assert_eq!(complement.accepts("0"), false);
+        assert!(complement.accepts("12a"));
+        assert!(complement.accepts("a"));
+        assert!(complement.accepts(""));
+        assert!(complement.intersect(&nfa).is_empty_language());
+
+        // Complementing twice gives back the original language
+        let double_complement = complement.complement()?;
+        for input in ["", "7", "123", "12a", "a1"] {
+            assert_eq!(double_complement.accepts(input), nfa.accepts(input));
+        }
+
+        // The complement is restricted to the alphabet
+        let mut nfa = NFA::with_alphabet(NFA::PRINTABLE_ASCII_ALPHABET);
+        nfa.append_regex("[0-9]+")?;
+        let complement = nfa.complement()?;
+        assert!(complement.accepts("12a"));
+        assert_eq!(complement.accepts("12\x01"), false);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_status() -> Result<()> {
+        let nfa = NFA::from_regex("a{3}")?;
+        assert_eq!(nfa.prefix_status(""), PrefixStatus::Possible);
+        assert_eq!(nfa.prefix_status("aa"), PrefixStatus::Possible);
+        assert_eq!(nfa.prefix_status("aaa"), PrefixStatus::Accepted);
+        assert_eq!(nfa.prefix_status("aaaa"), PrefixStatus::Dead);
+        assert_eq!(nfa.prefix_status("b"), PrefixStatus::Dead);
+        assert_eq!(nfa.prefix_status("é"), PrefixStatus::Dead);
+
+        // An accepted input can still be extended
+        let nfa = NFA::from_regex(r"\d+")?;
+        assert_eq!(nfa.prefix_status("4"), PrefixStatus::Accepted);
+        assert_eq!(nfa.prefix_status("42"), PrefixStatus::Accepted);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_accepted() -> Result<()> {
+        let nfa = NFA::from_regex("a{2,3}")?;
+        assert_eq!(nfa.sample_accepted(10, 10), vec!["aa", "aaa"]);
+        assert_eq!(nfa.sample_accepted(2, 10), vec!["aa"]);
+        assert_eq!(nfa.sample_accepted(10, 1), vec!["aa"]);
+
+        // The limit bounds infinite languages, shortest strings first
+        let nfa = NFA::from_regex("a*")?;
+        assert_eq!(nfa.sample_accepted(100, 3), vec!["", "a", "aa"]);
+
+        let nfa = NFA::from_regex("[b-c]x|a")?;
+        assert_eq!(nfa.sample_accepted(2, 10), vec!["a", "bx", "cx"]);
+        assert!(NFA::from_regex("ab")?.sample_accepted(1, 10).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_determinize() -> Result<()> {
+        let nfa = NFA::from_regex(r"ab|c")?;
+        let (dfa, state_mapping) = nfa.determinize();
+        assert_eq!(state_mapping.len(), 4);
+        assert_eq!(
+            state_mapping[&dfa.get_root()],
+            vec![NFA::START_STATE, State(2)]
+        );
+
+        // Each accepting DFA state contains the NFA accept state, along with the end of the branch
+        // that led to it, if it has one (`c` is a transition to the accept state)
+        let mut accepting_subsets = Vec::new();
+        for input in ["ab", "c"] {
+            let mut dfa_state = dfa.get_root();
+            for c in input.bytes() {
+                dfa_state = dfa.get_next_state(dfa_state, c).unwrap();
+            }
+            assert_eq!(dfa.is_accept_state(dfa_state.clone()), Some(0));
+            accepting_subsets.push(NFA::get_combined_state_names(&state_mapping[&dfa_state]));
+        }
+        assert_eq!(accepting_subsets, vec!["1,3", "1"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_union() -> Result<()> {
+        let union = NFA::union(NFA::from_regex("a")?, NFA::from_regex("b")?);
+        assert_eq!(union.num_states(), 6);
+        assert!(union.accepts("a"));
+        assert!(union.accepts("b"));
+        assert_eq!(union.accepts(""), false);
+        assert_eq!(union.accepts("ab"), false);
+        assert_eq!(union.accepts("c"), false);
+        assert_eq!(union.sample_accepted(3, 10), vec!["a", "b"]);
+
+        // An epsilon-free NFA accepting the empty string keeps accepting it
+        let union = NFA::union(
+            NFA::from_regex("a*")?.remove_epsilons(),
+            NFA::from_regex("b")?,
+        );
+        assert!(union.accepts(""));
+        assert!(union.accepts("aa"));
+        assert!(union.accepts("b"));
+        assert_eq!(union.accepts("ab"), false);
+
+        // Capture groups of the second NFA are numbered after the ones of the first
+        let union = NFA::union(
+            NFA::from_regex("(?P<num>\\d+)")?,
+            NFA::from_regex("(?P<word>[a-z]+)")?,
+        );
+        assert_eq!(
+            union.get_capture_names(),
+            [Some("num".to_string()), Some("word".to_string())]
+        );
+        assert_eq!(union.captures("42"), Some(vec![Some(0..2), None]));
+        assert_eq!(union.captures("ab"), Some(vec![None, Some(0..2)]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_captures() -> Result<()> {
+        let nfa = NFA::from_regex(r"(?P<key>[a-z]+)=((?P<num>\d+)|(?P<word>[a-z]+))")?;
+        assert_eq!(
+            nfa.get_capture_names(),
+            [
+                Some("key".to_string()),
+                None,
+                Some("num".to_string()),
+                Some("word".to_string())
+            ]
+        );
+        assert_eq!(
+            nfa.captures("id=42"),
+            Some(vec![Some(0..2), Some(3..5), Some(3..5), None])
+        );
+        assert_eq!(
+            nfa.captures("user=root"),
+            Some(vec![Some(0..4), Some(5..9), None, Some(5..9)])
+        );
+        assert_eq!(nfa.captures("id="), None);
+        assert!(nfa.accepts("id=42"));
+
+        // A repeated group reports its last repetition
+        let nfa = NFA::from_regex(r"((?P<digit>\d)\.){2}")?;
+        assert_eq!(nfa.captures("1.2."), Some(vec![Some(2..4), Some(2..3)]));
+
+        // Groups appended to an NFA are numbered after the existing ones
+        let mut nfa = NFA::from_regex(r"(a)")?;
+        nfa.append_regex(r"(b)")?;
+        assert_eq!(nfa.captures("ab"), Some(vec![Some(0..1), Some(1..2)]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_absent_captures() -> Result<()> {
+        // An optional group that doesn't take part in the match has no range
+        let nfa = NFA::from_regex(r"a(b)?c")?;
+        assert_eq!(nfa.get_capture_names(), [None]);
+        assert_eq!(nfa.captures("ac"), Some(vec![None]));
+        assert_eq!(nfa.captures("abc"), Some(vec![Some(1..2)]));
+
+        // While a group matching the empty string has an empty range
+        let nfa = NFA::from_regex(r"a(b?)c")?;
+        assert_eq!(nfa.captures("ac"), Some(vec![Some(1..1)]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_supported() -> Result<()> {
+        let check = |pattern: &str| check_supported(&RegexParser::new().parse_into_ast(pattern)?);
+        assert!(matches!(check(r"\D"), Err(NegationNotSupported(_))));
+        assert!(matches!(
+            check(r"a.*?b"),
+            Err(NonGreedyRepetitionNotSupported)
+        ));
+        assert!(matches!(check(r"[^a]"), Err(NegationNotSupported(_))));
+        assert!(matches!(check(r"[a&&b]"), Err(UnsupportedAstBracketedKind)));
+        assert!(check(r"(?:a|b)+").is_ok());
+        assert!(NFA::from_regex(r"(?:ab)+(c)")?.accepts("ababc"));
+        assert!(matches!(check(r"(?i:a)"), Err(UnsupportedGroupKindType)));
+        assert!(matches!(check(r"^a"), Err(UnsupportedAstNodeType(_))));
+        assert!(matches!(check("é"), Err(NoneASCIICharacters)));
+
+        // The NFA construction rejects the same constructs with the same errors
+        let mut inverted_range = RegexParser::new().parse_into_ast(r"(a{2,5})")?;
+        if let Ast::Group(group) = &mut inverted_range {
+            if let Ast::Repetition(repetition) = &mut *group.ast {
+                repetition.op.kind = RepetitionKind::Range(RepetitionRange::Bounded(5, 2));
+            }
+        }
+        let mut unsupported_asts = vec![inverted_range];
+        for pattern in [
+            r"\D",
+            r"a.*?b",
+            r"[^a]",
+            r"[a&&b]",
+            r"[[:alpha:]]",
+            r"[a\W]",
+            r"(?i:a)",
+            r"^a",
+            r"x|a$",
+            r"\pL",
+            "é",
+            "[aé]",
+            r"(?:b(c[^d])+)",
+        ] {
+            unsupported_asts.push(RegexParser::new().parse_into_ast(pattern)?);
+        }
+        for ast in unsupported_asts {
+            let check_result = check_supported(&ast);
+            let mut nfa = NFA::new();
+            let build_result = nfa.add_ast_to_nfa(&ast, NFA::START_STATE, NFA::ACCEPT_STATE);
+            assert!(check_result.is_err(), "{}", ast);
+            assert_eq!(
+                format!("{:?}", check_result),
+                format!("{:?}", build_result),
+                "{}",
+                ast
+            );
+        }
+        check(r"(?<name>\d+)(\.[0-9a-f\w]*)?|x{2,3}|()")?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_regex_with_budget() -> Result<()> {
+        let budget = CompileBudget {
+            max_states: 1000,
+            max_duration: None,
+        };
+        let nfa = NFA::from_regex_with_budget(r"(a{10}){10}", budget)?;
+        assert_eq!(nfa, NFA::from_regex(r"(a{10}){10}")?);
+        assert!(nfa.num_states() <= budget.max_states);
+
+        let result = NFA::from_regex_with_budget(r"(a{100}){100}", budget);
+        assert!(matches!(
+            result,
+            Err(LimitExceeded {
+                kind: LimitKind::NFAStates,
+                limit: 1000,
+            })
+        ));
+        let result = NFA::from_regex_with_budget(r"((a{2,5}b){10}c){100}", budget);
+        assert!(matches!(
+            result,
+            Err(LimitExceeded {
+                kind: LimitKind::NFAStates,
+                ..
+            })
+        ));
+
+        let budget = CompileBudget {
+            max_states: usize::MAX,
+            max_duration: Some(Duration::ZERO),
+        };
+        let result = NFA::from_regex_with_budget(r"(a{100}){100}", budget);
+        assert!(matches!(
+            result,
+            Err(LimitExceeded {
+                kind: LimitKind::CompileMillis,
+                limit: 0,
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_length() -> Result<()> {
+        let test_cases = [
+            (r"a{3,6}", Some(3), Some(6)),
+            (r"a+", Some(1), None),
+            (r"a*", Some(0), None),
+            (r"(ab|c)?d", Some(1), Some(3)),
+            (r"\d{2}-\d{2}(:\d+)?", Some(5), None),
+            (r"a|bcd|", Some(0), Some(3)),
+        ];
+        for (pattern, expected_min, expected_max) in test_cases {
+            let nfa = NFA::from_regex(pattern)?;
+            assert_eq!(nfa.min_match_length(), expected_min, "{}", pattern);
+            assert_eq!(nfa.max_match_length(), expected_max, "{}", pattern);
+            // Removing epsilon transitions doesn't change the lengths
+            let nfa = nfa.remove_epsilons();
+            assert_eq!(nfa.min_match_length(), expected_min, "{}", pattern);
+            assert_eq!(nfa.max_match_length(), expected_max, "{}", pattern);
+        }
+
+        // A loop that can't lead to a match doesn't make the length unbounded
+        let mut nfa = NFA::from_regex(r"ab")?;
+        let dead_end = nfa.new_state();
+        nfa.add_transition(nfa.get_start(), dead_end.clone(), 1 << b'x');
+        nfa.add_transition(dead_end.clone(), dead_end, 1 << b'x');
+        assert_eq!(nfa.max_match_length(), Some(2));
+
+        // No string is accepted
+        let empty_nfa = NFA::new();
+        assert_eq!(empty_nfa.min_match_length(), None);
+        assert_eq!(empty_nfa.max_match_length(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_alphabet() -> Result<()> {
+        let mut parser = RegexParser::new();
+        let parsed_ast = parser.parse_into_ast(r"a.\s")?;
+
+        let mut nfa = NFA::new();
+        nfa.add_ast_to_nfa(&parsed_ast, nfa.get_start(), nfa.get_accept())?;
+        assert!(nfa.accepts("a\x01 "));
+        assert!(nfa.accepts("a-\x0b"));
+
+        // Dots and Perl classes only match the printable ASCII characters, tabs and newlines
+        let mut nfa = NFA::with_alphabet(NFA::PRINTABLE_ASCII_ALPHABET);
+        nfa.add_ast_to_nfa(&parsed_ast, nfa.get_start(), nfa.get_accept())?;
+        assert_eq!(nfa.accepts("a\x01 "), false);
+        assert_eq!(nfa.accepts("a\x7f "), false);
+        assert_eq!(nfa.accepts("a-\x0b"), false);
+        assert!(nfa.accepts("a- "));
+        assert!(nfa.accepts("a~\t"));
+        assert!(nfa.accepts("a \n"));
+
+        // Explicit literals are kept as written
+        let mut nfa = NFA::with_alphabet(NFA::PRINTABLE_ASCII_ALPHABET);
+        nfa.add_ast_to_nfa(
+            &RegexParser::new().parse_into_ast(r"[\x01-\x02]")?,
+            nfa.get_start(),
+            nfa.get_accept(),
+        )?;
+        assert!(nfa.accepts("\x01"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_epsilon_closure_is_deterministic() -> Result<()> {
+        let regex = r"(a|b|c)*(d|e)?[0-9]{0,3}";
+        let nfa = NFA::from_regex(regex)?;
+        let other_nfa = NFA::from_regex(regex)?;
+        for state in nfa.states.iter() {
+            let closure = nfa.epsilon_closure(&vec![state.clone()]);
+            assert!(closure.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            assert_eq!(closure, other_nfa.epsilon_closure(&vec![state.clone()]));
+        }
+
+        // The result doesn't depend on the order of the given states either
+        let states: Vec<State> = nfa.states.iter().take(4).cloned().collect();
+        let reversed_states: Vec<State> = states.iter().rev().cloned().collect();
+        assert_eq!(
+            nfa.epsilon_closure(&states),
+            nfa.epsilon_closure(&reversed_states)
+        );
+
+        // Reversing goes through `HashMap` iteration, which varies between runs
+        let reversed_nfa = nfa.reverse();
+        let other_reversed_nfa = nfa.reverse();
+        for state in reversed_nfa.states.iter() {
+            assert_eq!(
+                reversed_nfa.epsilon_closure(&vec![state.clone()]),
+                other_reversed_nfa.epsilon_closure(&vec![state.clone()])
+            );
+        }
+
+        Ok(())
+    }
 }
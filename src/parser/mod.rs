@@ -2,6 +2,9 @@ pub(crate) mod regex_parser;
 
 mod schema_parser;
 
+pub use schema_parser::lint::LintWarning;
 pub use schema_parser::parser::SchemaConfig;
+pub use schema_parser::parser::SchemaConfigBuilder;
 pub use schema_parser::parser::TimestampSchema;
+pub use schema_parser::parser::Transform;
 pub use schema_parser::parser::VarSchema;
@@ -1,30 +1,155 @@
-use crate::error_handling::{Error, Error::RegexParsingError, Result};
-use regex_syntax::ast::{parse::Parser, Ast};
+use crate::error_handling::{Error, Error::UnsupportedRegexFeature, Result};
+use regex_syntax::ast::{
+    parse::Parser, parse::ParserBuilder, Ast, ClassSet, ClassSetItem, ErrorKind, LiteralKind,
+};
 
 // This is a wrapper of `regex_syntax::ast::parse::Parser`, which can be extended to hold
 // program-specific data members.
 pub struct RegexParser {
     m_parser: Parser,
+    // Whether to reject the constructs the NFA construction doesn't support at parse time
+    m_validate_features: bool,
 }
 
 impl RegexParser {
     pub fn new() -> RegexParser {
+        // Octal escapes (e.g. `\012`) are enabled so that control bytes can be written in octal, in
+        // addition to the hex escapes (e.g. `\x1b`) that are supported by default.
         Self {
-            m_parser: Parser::new(),
+            m_parser: ParserBuilder::new().octal(true).build(),
+            m_validate_features: false,
+        }
+    }
+
+    // Creates a parser that returns `UnsupportedRegexFeature` with the byte position of the first
+    // construct the lexer can't handle: look-arounds, backreferences, non-ASCII characters,
+    // non-greedy repetitions and assertions. Word boundaries (`\b` and `\B`) are rejected too,
+    // since the lexer's DFA doesn't enforce them. Since octal escapes are enabled, single-digit
+    // escapes like `\1` are treated as backreferences; control bytes can be written as `\001`
+    // instead.
+    pub fn with_feature_validation() -> RegexParser {
+        Self {
+            m_validate_features: true,
+            ..Self::new()
         }
     }
 
     pub fn parse_into_ast(&mut self, pattern: &str) -> Result<Ast> {
         match self.m_parser.parse(pattern) {
-            Ok(ast) => Ok(ast),
-            Err(e) => Err(RegexParsingError(e)),
+            Ok(ast) => {
+                if self.m_validate_features {
+                    Self::validate_features(&ast)?;
+                }
+                Ok(ast)
+            }
+            Err(e) if self.m_validate_features => {
+                let position = e.span().start.offset;
+                let feature = match e.kind() {
+                    ErrorKind::UnsupportedLookAround => "look-around",
+                    ErrorKind::UnsupportedBackreference => "backreference",
+                    // `\8` and `\9` aren't octal escapes
+                    ErrorKind::EscapeUnrecognized
+                        if pattern[position..].starts_with(r"\8")
+                            || pattern[position..].starts_with(r"\9") =>
+                    {
+                        "backreference"
+                    }
+                    _ => return Err(e.into()),
+                };
+                Err(UnsupportedRegexFeature { feature, position })
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn validate_features(ast: &Ast) -> Result<()> {
+        match ast {
+            Ast::Literal(literal) => {
+                if LiteralKind::Octal == literal.kind
+                    && literal.span.end.offset - literal.span.start.offset == r"\1".len()
+                {
+                    return Err(UnsupportedRegexFeature {
+                        feature: "backreference",
+                        position: literal.span.start.offset,
+                    });
+                }
+                Self::validate_char(literal.c, literal.span.start.offset)
+            }
+            Ast::ClassUnicode(class) => Err(UnsupportedRegexFeature {
+                feature: "non-ASCII character",
+                position: class.span.start.offset,
+            }),
+            Ast::ClassBracketed(bracketed) => Self::validate_class_set(&bracketed.kind),
+            Ast::Repetition(repetition) => {
+                if false == repetition.greedy {
+                    return Err(UnsupportedRegexFeature {
+                        feature: "non-greedy repetition",
+                        position: repetition.span.start.offset,
+                    });
+                }
+                Self::validate_features(&repetition.ast)
+            }
+            Ast::Assertion(assertion) => Err(UnsupportedRegexFeature {
+                feature: "assertion",
+                position: assertion.span.start.offset,
+            }),
+            Ast::Group(group) => Self::validate_features(&group.ast),
+            Ast::Alternation(alternation) => alternation
+                .asts
+                .iter()
+                .try_for_each(Self::validate_features),
+            Ast::Concat(concat) => concat.asts.iter().try_for_each(Self::validate_features),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_class_set(class_set: &ClassSet) -> Result<()> {
+        match class_set {
+            ClassSet::Item(item) => Self::validate_class_set_item(item),
+            ClassSet::BinaryOp(op) => {
+                Self::validate_class_set(&op.lhs)?;
+                Self::validate_class_set(&op.rhs)
+            }
+        }
+    }
+
+    fn validate_class_set_item(item: &ClassSetItem) -> Result<()> {
+        match item {
+            ClassSetItem::Literal(literal) => {
+                Self::validate_char(literal.c, literal.span.start.offset)
+            }
+            ClassSetItem::Range(range) => {
+                Self::validate_char(range.start.c, range.start.span.start.offset)?;
+                Self::validate_char(range.end.c, range.end.span.start.offset)
+            }
+            ClassSetItem::Unicode(class) => Err(UnsupportedRegexFeature {
+                feature: "non-ASCII character",
+                position: class.span.start.offset,
+            }),
+            ClassSetItem::Bracketed(bracketed) => Self::validate_class_set(&bracketed.kind),
+            ClassSetItem::Union(union) => union
+                .items
+                .iter()
+                .try_for_each(Self::validate_class_set_item),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_char(c: char, position: usize) -> Result<()> {
+        if false == c.is_ascii() {
+            return Err(UnsupportedRegexFeature {
+                feature: "non-ASCII character",
+                position,
+            });
         }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error_handling::Error::RegexParsingError;
     use regex_syntax::ast;
 
     #[test]
@@ -55,4 +180,60 @@ mod tests {
             panic!("Type mismatched")
         };
     }
+
+    #[test]
+    fn test_escaped_literals() {
+        let test_cases = [
+            (r"\x1b", '\x1b'),
+            (r"\012", '\n'),
+            (r"\t", '\t'),
+            (r"\x7f", '\x7f'),
+        ];
+        for (pattern, expected) in test_cases {
+            let mut parser = RegexParser::new();
+            let parse_result = parser.parse_into_ast(pattern);
+            assert!(parse_result.is_ok());
+            let Ast::Literal(literal) = &parse_result.unwrap() else {
+                panic!("Type mismatched")
+            };
+            assert_eq!(literal.c, expected);
+        }
+    }
+
+    #[test]
+    fn test_feature_validation() {
+        let test_cases = [
+            (r"a(?=x)", "look-around", 1),
+            (r"(a)\1", "backreference", 3),
+            (r"(a)\9", "backreference", 3),
+            (r"ab?c é", "non-ASCII character", 5),
+            (r"[a-zé]", "non-ASCII character", 4),
+            (r"\pL", "non-ASCII character", 0),
+            (r"(ab)+?", "non-greedy repetition", 0),
+            (r"ab$", "assertion", 2),
+            (r"a\Bb", "assertion", 1),
+        ];
+        for (pattern, expected_feature, expected_position) in test_cases {
+            let mut parser = RegexParser::with_feature_validation();
+            match parser.parse_into_ast(pattern) {
+                Err(UnsupportedRegexFeature { feature, position }) => {
+                    assert_eq!(feature, expected_feature);
+                    assert_eq!(position, expected_position);
+                }
+                _ => panic!("Expected an unsupported feature error for {}", pattern),
+            }
+        }
+
+        let mut parser = RegexParser::with_feature_validation();
+        assert!(parser.parse_into_ast(r"\d+(\.\d+)?[a-f]*\001\x7f").is_ok());
+
+        // Without validation, only the constructs `regex_syntax` rejects fail to parse
+        let mut parser = RegexParser::new();
+        assert!(parser.parse_into_ast(r"(ab)+?").is_ok());
+        let mut parser = RegexParser::new();
+        assert!(matches!(
+            parser.parse_into_ast(r"a(?=x)"),
+            Err(RegexParsingError(_))
+        ));
+    }
 }
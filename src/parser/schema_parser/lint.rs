@@ -0,0 +1,258 @@
+use crate::dfa::{State, DFA};
+use crate::error_handling::Result;
+use crate::nfa::nfa::{DOT_TRANSITION, NFA};
+use crate::parser::SchemaConfig;
+use std::collections::{HashSet, VecDeque};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LintWarning {
+    // The variable matches any character right from the start (e.g. `.*` or `.+`), so under the
+    // longest-match policy it swallows the input meant for other variables
+    OverBroadVariable { name: String },
+
+    // Every short input matched by `name` is also matched by `subsumed_by`
+    SubsumedVariable { name: String, subsumed_by: String },
+
+    // The variable can only start with a delimiter (e.g. `: +` when `:` is a delimiter). Since the
+    // lexer starts matching variables after delimiters, such a variable never matches right after
+    // text: in `key: value`, the `:` delimits `key` instead.
+    StartsWithDelimiter { name: String },
+
+    // The variable and the timestamp can match the same text at the start of a line, one being a
+    // prefix of the other (e.g. `\d{4}` and `\d{4}-\d{2}-\d{2}`), so whether the line starts a
+    // new event depends on how far the match goes
+    TimestampOverlap { name: String, timestamp: String },
+}
+
+impl SchemaConfig {
+    // Inputs up to this length are explored when checking whether a variable subsumes another
+    const LINT_MAX_INPUT_LEN: usize = 6;
+
+    // Reports variables that are likely to make other variables unreachable, or to be confused
+    // with timestamps. This is a heuristic dry run: it doesn't affect how logs are parsed.
+    pub fn lint(&self) -> Result<Vec<LintWarning>> {
+        let var_schemas = self.get_var_schemas();
+        let mut nfas: Vec<NFA> = Vec::new();
+        for schema in var_schemas {
+            let mut nfa = NFA::new();
+            nfa.add_ast_to_nfa(schema.get_ast(), nfa.get_start(), nfa.get_accept())?;
+            nfas.push(nfa);
+        }
+
+        let delimiter_mask = self
+            .delimiters()
+            .into_iter()
+            .fold(0u128, |mask, c| mask | (1u128 << c as u8));
+        let mut warnings = Vec::new();
+        for (schema, nfa) in var_schemas.iter().zip(nfas.iter()) {
+            if Self::accepts_any_char_from_start(nfa) {
+                warnings.push(LintWarning::OverBroadVariable {
+                    name: schema.get_name().to_string(),
+                });
+            }
+            let first_symbols = Self::get_first_symbols(nfa);
+            if 0 != first_symbols && 0 == first_symbols & !delimiter_mask {
+                warnings.push(LintWarning::StartsWithDelimiter {
+                    name: schema.get_name().to_string(),
+                });
+            }
+        }
+
+        // Over-broad variables overlap every timestamp, and are already reported
+        let ts_nfas = self
+            .get_ts_schemas()
+            .iter()
+            .map(|schema| {
+                let mut nfa = NFA::new();
+                nfa.add_ast_to_nfa(schema.get_ast(), nfa.get_start(), nfa.get_accept())?;
+                Ok(nfa)
+            })
+            .collect::<Result<Vec<NFA>>>()?;
+        for (schema, nfa) in var_schemas.iter().zip(nfas.iter()) {
+            if Self::accepts_any_char_from_start(nfa) {
+                continue;
+            }
+            for (ts_schema, ts_nfa) in self.get_ts_schemas().iter().zip(ts_nfas.iter()) {
+                if Self::overlaps_at_start(nfa, ts_nfa) {
+                    warnings.push(LintWarning::TimestampOverlap {
+                        name: schema.get_name().to_string(),
+                        timestamp: ts_schema.get_regex().to_string(),
+                    });
+                }
+            }
+        }
+
+        for (i, schema) in var_schemas.iter().enumerate() {
+            for (j, other_schema) in var_schemas.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+                let dfa = DFA::from_multiple_nfas(vec![nfas[i].clone(), nfas[j].clone()]);
+                if Self::is_subsumed_for_short_inputs(&dfa) {
+                    warnings.push(LintWarning::SubsumedVariable {
+                        name: schema.get_name().to_string(),
+                        subsumed_by: other_schema.get_name().to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(warnings)
+    }
+
+    fn accepts_any_char_from_start(nfa: &NFA) -> bool {
+        nfa.epsilon_closure(&vec![nfa.get_start()])
+            .iter()
+            .filter_map(|state| nfa.get_transitions_from_state(state))
+            .flatten()
+            .any(|transition| DOT_TRANSITION == transition.get_symbol_onehot_encoding())
+    }
+
+    // Returns whether a non-empty string accepted by one of the NFAs is a prefix of a string
+    // accepted by the other one. The intersections are epsilon-free, so their accept state can
+    // only be reached by consuming at least one character.
+    fn overlaps_at_start(nfa: &NFA, other_nfa: &NFA) -> bool {
+        [
+            nfa.intersect(&other_nfa.prefixes()),
+            other_nfa.intersect(&nfa.prefixes()),
+        ]
+        .iter()
+        .any(|intersection| {
+            intersection
+                .reachable_states()
+                .contains(&intersection.get_accept())
+        })
+    }
+
+    // Returns the mask of the symbols that can be consumed first from the start state
+    fn get_first_symbols(nfa: &NFA) -> u128 {
+        nfa.epsilon_closure(&vec![nfa.get_start()])
+            .iter()
+            .filter_map(|state| nfa.get_transitions_from_state(state))
+            .flatten()
+            .fold(0, |mask, transition| {
+                mask | transition.get_symbol_onehot_encoding()
+            })
+    }
+
+    // Given a DFA built from two NFAs, returns whether the second NFA accepts at least one input
+    // of length up to `LINT_MAX_INPUT_LEN`, and every such input is also accepted by the first NFA.
+    fn is_subsumed_for_short_inputs(dfa: &DFA) -> bool {
+        let mut has_match = false;
+        let mut visited: HashSet<State> = HashSet::new();
+        let mut queue: VecDeque<(State, usize)> = VecDeque::new();
+        visited.insert(dfa.get_root());
+        queue.push_back((dfa.get_root(), 0));
+
+        while let Some((state, depth)) = queue.pop_front() {
+            if dfa.is_nfa_accepted(state.clone(), 0) {
+                has_match = true;
+                if false == dfa.is_nfa_accepted(state.clone(), 1) {
+                    return false;
+                }
+            }
+            if Self::LINT_MAX_INPUT_LEN == depth {
+                continue;
+            }
+            for c in 0..128u8 {
+                if let Some(next_state) = dfa.get_next_state(state.clone(), c) {
+                    if visited.insert(next_state.clone()) {
+                        queue.push_back((next_state, depth + 1));
+                    }
+                }
+            }
+        }
+
+        has_match
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint() -> Result<()> {
+        let schema_prefix = "timestamp:\n  - 'TIMESTAMP'\ndelimiters: ' '\nvariables:\n";
+
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "  int: '\\d+'\n  catchall: '.*'\n").as_str(),
+        )?;
+        let warnings = parsed_schema.lint()?;
+        assert!(warnings.contains(&LintWarning::OverBroadVariable {
+            name: "catchall".to_string()
+        }));
+        assert!(warnings.contains(&LintWarning::SubsumedVariable {
+            name: "int".to_string(),
+            subsumed_by: "catchall".to_string(),
+        }));
+        assert_eq!(warnings.len(), 2);
+
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "  int: '\\d+'\n  hex: '(\\d|[a-f])+'\n").as_str(),
+        )?;
+        assert_eq!(
+            parsed_schema.lint()?,
+            vec![LintWarning::SubsumedVariable {
+                name: "int".to_string(),
+                subsumed_by: "hex".to_string(),
+            }]
+        );
+
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "  int: '\\d+'\n  float: '\\d+\\.\\d+'\n").as_str(),
+        )?;
+        assert!(parsed_schema.lint()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_timestamp_overlap() -> Result<()> {
+        let schema_prefix =
+            "timestamp:\n  - '\\d{4}-\\d{2}-\\d{2} \\d{2}:\\d{2}'\ndelimiters: ' '\nvariables:\n";
+
+        // `2024` is the start of a timestamp, and so is `2024-01-01` for `\d+(-\d+)*`
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "  year: '\\d{4}'\n  date: '\\d+(-\\d+)*'\n").as_str(),
+        )?;
+        let warnings = parsed_schema.lint()?;
+        for name in ["year", "date"] {
+            assert!(warnings.contains(&LintWarning::TimestampOverlap {
+                name: name.to_string(),
+                timestamp: r"\d{4}-\d{2}-\d{2} \d{2}:\d{2}".to_string(),
+            }));
+        }
+
+        // A variable that can't start a timestamp doesn't overlap it
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "  hex: '0x[0-9a-f]+'\n  word: '[a-z]+'\n").as_str(),
+        )?;
+        assert!(parsed_schema.lint()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_starts_with_delimiter() -> Result<()> {
+        let schema_prefix = "timestamp:\n  - 'TIMESTAMP'\ndelimiters: ' :'\nvariables:\n";
+
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "  sep: ': +'\n  key: '[a-z]+:'\n").as_str(),
+        )?;
+        assert_eq!(
+            parsed_schema.lint()?,
+            vec![LintWarning::StartsWithDelimiter {
+                name: "sep".to_string()
+            }]
+        );
+
+        // A variable that may also start with a non-delimiter can still match
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "  sep: '(:|=) +'\n").as_str(),
+        )?;
+        assert!(parsed_schema.lint()?.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,23 +1,35 @@
 use crate::error_handling::Error::{
-    IOError, InvalidSchema, MissingSchemaKey, NoneASCIICharacters, YamlParsingError,
+    DuplicateVariableName, IOError, InvalidSchema, MissingSchemaKey, NoneASCIICharacters,
+    UnresolvedSchemaReference, YamlParsingError,
 };
 use crate::error_handling::Result;
+use crate::nfa::nfa::NFA;
 use crate::parser::regex_parser::parser::RegexParser;
-use regex_syntax::ast::Ast;
+use regex_syntax::ast::{Ast, GroupKind, Span};
+use serde::de::{Error as DeError, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
 use serde_yaml::Value;
 use std::collections::{HashMap, HashSet};
 use std::io::Read;
+use std::sync::OnceLock;
 
 pub struct TimestampSchema {
     regex: String,
     ast: Ast,
+
+    // NFA built from the AST on the first call to `capture_fields`
+    nfa: OnceLock<NFA>,
 }
 
 impl TimestampSchema {
     pub fn new(regex: String) -> Result<TimestampSchema> {
-        let mut regex_parser = RegexParser::new();
+        let mut regex_parser = RegexParser::with_feature_validation();
         let ast = regex_parser.parse_into_ast(regex.as_str())?;
-        Ok(Self { regex, ast })
+        Ok(Self {
+            regex,
+            ast,
+            nfa: OnceLock::new(),
+        })
     }
 
     pub fn get_regex(&self) -> &str {
@@ -27,19 +39,125 @@ impl TimestampSchema {
     pub fn get_ast(&self) -> &Ast {
         &self.ast
     }
+
+    // Returns the pattern of the named group of the regex, e.g. `\d{4}` for `year` in
+    // `(?P<year>\d{4})-\d{2}`
+    pub fn get_group_regex(&self, name: &str) -> Option<&str> {
+        let span = Self::find_group_span(&self.ast, name)?;
+        Some(&self.regex[span.start.offset..span.end.offset])
+    }
+
+    fn find_group_span(ast: &Ast, name: &str) -> Option<Span> {
+        match ast {
+            Ast::Group(group) => match &group.kind {
+                GroupKind::CaptureName {
+                    name: group_name, ..
+                } if group_name.name == name => Some(*group.ast.span()),
+                _ => Self::find_group_span(&group.ast, name),
+            },
+            Ast::Repetition(repetition) => Self::find_group_span(&repetition.ast, name),
+            Ast::Concat(concat) => concat
+                .asts
+                .iter()
+                .find_map(|ast| Self::find_group_span(ast, name)),
+            Ast::Alternation(alternation) => alternation
+                .asts
+                .iter()
+                .find_map(|ast| Self::find_group_span(ast, name)),
+            _ => None,
+        }
+    }
+
+    // Matches the whole timestamp and returns the text captured by each named group of the regex
+    // (e.g. `(?P<year>\d{4})`), keyed by group name. Groups that didn't take part in the match are
+    // left out. Returns `None` if the timestamp doesn't match.
+    pub fn capture_fields(&self, timestamp: &str) -> Result<Option<HashMap<String, String>>> {
+        if self.nfa.get().is_none() {
+            let mut nfa = NFA::new();
+            nfa.add_ast_to_nfa(&self.ast, nfa.get_start(), nfa.get_accept())?;
+            let _ = self.nfa.set(nfa);
+        }
+        let nfa = self.nfa.get().unwrap();
+        let Some(captures) = nfa.captures(timestamp) else {
+            return Ok(None);
+        };
+        Ok(Some(
+            nfa.get_capture_names()
+                .iter()
+                .zip(captures)
+                .filter_map(|(name, range)| Some((name.clone()?, timestamp[range?].to_string())))
+                .collect(),
+        ))
+    }
+}
+
+// Normalization applied to the value reported for a matched variable. The raw matched text is
+// still available through `Token::raw`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Transform {
+    Lowercase,
+    Uppercase,
+    Trim,
+    // Strips the leading zeros of the value, keeping a single `0` if it's all zeros
+    StripLeadingZeros,
+}
+
+impl Transform {
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Transform::Lowercase => value.to_ascii_lowercase(),
+            Transform::Uppercase => value.to_ascii_uppercase(),
+            Transform::Trim => value.trim().to_string(),
+            Transform::StripLeadingZeros => {
+                let stripped = value.trim_start_matches('0');
+                if stripped.is_empty() && false == value.is_empty() {
+                    return "0".to_string();
+                }
+                stripped.to_string()
+            }
+        }
+    }
 }
 
 pub struct VarSchema {
     pub name: String,
     pub regex: String,
     pub ast: Ast,
+
+    // Whether a match must extend to the next delimiter (or the end of the stream). Only takes
+    // effect under `MatchPolicy::FirstDeclared`, since `MatchPolicy::LongestMatch` always requires
+    // a variable match to be followed by a delimiter.
+    pub anchored: bool,
+
+    // Applied to the value reported for the tokens matching this variable
+    pub transform: Option<Transform>,
+
+    // When multiple variables match the same input, the one with the highest priority wins, and
+    // ties fall back to declaration order
+    pub priority: i32,
+
+    // Named timestamp groups the regex references (see `SchemaConfig::expand_timestamp_refs`),
+    // which a match must agree with
+    pub timestamp_refs: Vec<String>,
+
+    // NFA built from the AST on the first call to `matches` or `matches_timestamp_refs`
+    nfa: OnceLock<NFA>,
 }
 
 impl VarSchema {
     pub fn new(name: String, regex: String) -> Result<VarSchema> {
-        let mut regex_parser = RegexParser::new();
+        let mut regex_parser = RegexParser::with_feature_validation();
         let ast = regex_parser.parse_into_ast(regex.as_str())?;
-        Ok(Self { name, regex, ast })
+        Ok(Self {
+            name,
+            regex,
+            ast,
+            anchored: false,
+            transform: None,
+            priority: 0,
+            timestamp_refs: Vec::new(),
+            nfa: OnceLock::new(),
+        })
     }
 
     pub fn get_name(&self) -> &str {
@@ -53,12 +171,215 @@ impl VarSchema {
     pub fn get_ast(&self) -> &Ast {
         &self.ast
     }
+
+    pub fn is_anchored(&self) -> bool {
+        self.anchored
+    }
+
+    pub fn get_transform(&self) -> Option<Transform> {
+        self.transform
+    }
+
+    pub fn get_priority(&self) -> i32 {
+        self.priority
+    }
+
+    pub fn get_timestamp_refs(&self) -> &[String] {
+        &self.timestamp_refs
+    }
+
+    // Returns whether the regex matches the whole input
+    pub fn matches(&self, input: &str) -> Result<bool> {
+        Ok(self.get_nfa()?.accepts(input))
+    }
+
+    // Returns whether the text captured for each referenced timestamp group in the input (a match
+    // of the regex) is the text the timestamp captured for that group. Always true if the regex
+    // references no timestamp group.
+    pub fn matches_timestamp_refs(
+        &self,
+        input: &str,
+        timestamp_fields: &HashMap<String, String>,
+    ) -> Result<bool> {
+        if self.timestamp_refs.is_empty() {
+            return Ok(true);
+        }
+        let nfa = self.get_nfa()?;
+        let Some(captures) = nfa.captures(input) else {
+            return Ok(false);
+        };
+        Ok(self.timestamp_refs.iter().all(|name| {
+            let captured = nfa
+                .get_capture_names()
+                .iter()
+                .position(|capture_name| capture_name.as_deref() == Some(name.as_str()))
+                .and_then(|capture_idx| captures[capture_idx].clone())
+                .map(|range| &input[range]);
+            captured.is_some() && captured == timestamp_fields.get(name).map(String::as_str)
+        }))
+    }
+
+    fn get_nfa(&self) -> Result<&NFA> {
+        if self.nfa.get().is_none() {
+            let mut nfa = NFA::new();
+            nfa.add_ast_to_nfa(&self.ast, nfa.get_start(), nfa.get_accept())?;
+            let _ = self.nfa.set(nfa);
+        }
+        Ok(self.nfa.get().unwrap())
+    }
+
+    // Returns the regex if it's a pure literal string (i.e., it contains no regex metacharacters)
+    pub fn as_literal(&self) -> Option<&str> {
+        if self.regex.is_empty() || self.regex.chars().any(regex_syntax::is_meta_character) {
+            return None;
+        }
+        Some(&self.regex)
+    }
+}
+
+// The layout of a schema file. Unknown keys and values of the wrong type are rejected while
+// deserializing, before any regex is parsed. The required keys are optional here so that a missing
+// key is reported with `MissingSchemaKey`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSchema {
+    timestamp: Option<Vec<String>>,
+    variables: Option<RawVariables>,
+    delimiters: Option<RawDelimiters>,
+    #[serde(default)]
+    multibyte_delimiters: Vec<String>,
+    escape: Option<String>,
+    line_separator: Option<String>,
+    #[serde(default)]
+    require_timestamp: bool,
+    #[serde(default)]
+    normalize_crlf: bool,
+    #[serde(default)]
+    definitions: HashMap<String, String>,
+    #[serde(default)]
+    classes: HashMap<String, String>,
+}
+
+// The variables of a schema file, in declaration order (which sets their schema IDs)
+struct RawVariables(Vec<(String, RawVarSchema)>);
+
+// A variable is either written as a regex, or as a mapping of options (see
+// `SchemaConfig::load_var_schema_with_options`)
+enum RawVarSchema {
+    Regex(String),
+    WithOptions(RawVarOptions),
+}
+
+#[derive(Default)]
+struct RawVarOptions {
+    regex: Option<String>,
+    anchored: bool,
+    transform: Option<String>,
+    priority: i32,
+    timestamp_refs: bool,
+}
+
+// Delimiters are either written as a string of characters, or as a sequence of items (see
+// `SchemaConfig::parse_delimiter_item`)
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDelimiters {
+    Chars(String),
+    Items(Vec<String>),
+}
+
+impl<'de> Deserialize<'de> for RawVariables {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct RawVariablesVisitor;
+
+        impl<'de> Visitor<'de> for RawVariablesVisitor {
+            type Value = RawVariables;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a mapping of variable names to regexes")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<RawVariables, A::Error> {
+                let mut variables = Vec::new();
+                while let Some(name) = map.next_key::<String>()? {
+                    let var = map
+                        .next_value::<RawVarSchema>()
+                        .map_err(|e| A::Error::custom(format_args!("variables.{}: {}", name, e)))?;
+                    variables.push((name, var));
+                }
+                Ok(RawVariables(variables))
+            }
+        }
+
+        deserializer.deserialize_map(RawVariablesVisitor)
+    }
+}
+
+// Deserialized by hand rather than as an untagged enum, so that an error names the offending option
+// (e.g. `priority: invalid type`). `RawVariables` then prefixes it with the variable.
+impl<'de> Deserialize<'de> for RawVarSchema {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct RawVarSchemaVisitor;
+
+        impl<'de> Visitor<'de> for RawVarSchemaVisitor {
+            type Value = RawVarSchema;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a regex or a mapping of variable options")
+            }
+
+            fn visit_str<E: DeError>(self, regex: &str) -> std::result::Result<RawVarSchema, E> {
+                Ok(RawVarSchema::Regex(regex.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<RawVarSchema, A::Error> {
+                const OPTIONS: &[&str] = &[
+                    "regex",
+                    "anchored",
+                    "transform",
+                    "priority",
+                    "timestamp_refs",
+                ];
+                let mut options = RawVarOptions::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    let with_key = |e: A::Error| A::Error::custom(format_args!("{}: {}", key, e));
+                    match key.as_str() {
+                        "regex" => options.regex = Some(map.next_value().map_err(with_key)?),
+                        "anchored" => options.anchored = map.next_value().map_err(with_key)?,
+                        "transform" => {
+                            options.transform = Some(map.next_value().map_err(with_key)?)
+                        }
+                        "priority" => options.priority = map.next_value().map_err(with_key)?,
+                        "timestamp_refs" => {
+                            options.timestamp_refs = map.next_value().map_err(with_key)?
+                        }
+                        _ => return Err(A::Error::unknown_field(&key, OPTIONS)),
+                    }
+                }
+                Ok(RawVarSchema::WithOptions(options))
+            }
+        }
+
+        deserializer.deserialize_any(RawVarSchemaVisitor)
+    }
 }
 
 pub struct SchemaConfig {
     ts_schemas: Vec<TimestampSchema>,
     var_schemas: Vec<VarSchema>,
     delimiters: [bool; 128],
+    // Delimiters made of several characters (e.g. `||`), sorted from longest to shortest
+    multibyte_delimiters: Vec<String>,
+    escape_char: Option<char>,
+    line_separator: Option<char>,
+    timestamp_required: bool,
+    crlf_normalized: bool,
 }
 
 impl SchemaConfig {
@@ -70,24 +391,109 @@ impl SchemaConfig {
         &self.var_schemas
     }
 
+    // Returns the variable names in declaration order
+    pub fn variable_names(&self) -> Vec<&str> {
+        self.var_schemas
+            .iter()
+            .map(|schema| schema.get_name())
+            .collect()
+    }
+
+    // Returns the variable schema IDs in order of precedence: highest priority first, then in
+    // declaration order
+    pub fn var_schema_ids_by_priority(&self) -> Vec<usize> {
+        let mut schema_ids: Vec<usize> = (0..self.var_schemas.len()).collect();
+        schema_ids
+            .sort_by_key(|schema_id| std::cmp::Reverse(self.var_schemas[*schema_id].priority));
+        schema_ids
+    }
+
+    // Returns the NFA of the named variable, e.g. to inspect its automaton. The NFA is built on
+    // first use and cached. An unknown name results in `UnresolvedSchemaReference`.
+    pub fn variable_nfa(&self, name: &str) -> Result<&NFA> {
+        self.var_schemas
+            .iter()
+            .find(|schema| schema.get_name() == name)
+            .ok_or_else(|| UnresolvedSchemaReference(name.to_string()))?
+            .get_nfa()
+    }
+
+    // Returns the name of the variable matching the whole token, or `None` if no variable matches.
+    // If multiple variables match, the one with the highest priority (then the first declared)
+    // wins, like in the lexer.
+    pub fn classify(&self, token: &str) -> Option<&str> {
+        self.var_schema_ids_by_priority()
+            .into_iter()
+            .map(|schema_id| &self.var_schemas[schema_id])
+            .find(|schema| schema.matches(token).unwrap_or(false))
+            .map(|schema| schema.get_name())
+    }
+
+    // Returns the timestamp regex patterns in declaration order
+    pub fn timestamp_patterns(&self) -> Vec<&str> {
+        self.ts_schemas
+            .iter()
+            .map(|schema| schema.get_regex())
+            .collect()
+    }
+
     pub fn has_delimiter(&self, delimiter: char) -> bool {
         if false == delimiter.is_ascii() {
             return false;
         }
         self.delimiters[delimiter as usize]
     }
+
+    // Returns the delimiters in ascending order, including the line separator
+    pub fn delimiters(&self) -> Vec<char> {
+        (0..128u8)
+            .filter(|c| self.delimiters[*c as usize])
+            .map(char::from)
+            .collect()
+    }
+
+    // Returns the delimiters made of several characters, longest first. The lexer matches them in
+    // addition to the single-character delimiters, preferring the longest one at a position.
+    pub fn get_multibyte_delimiters(&self) -> &[String] {
+        &self.multibyte_delimiters
+    }
+
+    pub fn get_escape_char(&self) -> Option<char> {
+        self.escape_char
+    }
+
+    // Returns the character terminating lines, `\n` unless the schema sets `line_separator`. It is
+    // always a delimiter.
+    pub fn get_line_separator(&self) -> char {
+        self.line_separator.unwrap_or('\n')
+    }
+
+    pub fn is_timestamp_required(&self) -> bool {
+        self.timestamp_required
+    }
+
+    // Whether `\r\n` line endings are lexed as `\n`, so no token ends with a stray `\r`
+    pub fn is_crlf_normalized(&self) -> bool {
+        self.crlf_normalized
+    }
 }
 
 impl SchemaConfig {
     const TIMESTAMP_KEY: &'static str = "timestamp";
     const VAR_KEY: &'static str = "variables";
     const DELIMITER_EKY: &'static str = "delimiters";
+    const VAR_REGEX_KEY: &'static str = "regex";
+    const TIMESTAMP_REF_PREFIX: &'static str = "${timestamp.";
 
+    // Malformed YAML results in `YamlParsingError`. Well-formed YAML that doesn't follow the schema
+    // layout, e.g. with a misspelled key or a string where a boolean is expected, results in
+    // `InvalidSchema` with the serde message. Errors in the options of a variable name the variable
+    // and the option, e.g. `variables.int: priority: invalid type: string "high", expected i32`.
     pub fn parse_from_str(yaml_content: &str) -> Result<SchemaConfig> {
-        match Self::load_kv_pairs_from_yaml_content(yaml_content) {
-            Ok(kv_pairs) => Self::load_from_kv_pairs(kv_pairs),
-            Err(e) => Err(YamlParsingError(e)),
-        }
+        let value: Value = serde_yaml::from_str(yaml_content).map_err(YamlParsingError)?;
+        let raw_schema: RawSchema =
+            serde_yaml::from_value(value).map_err(|e| InvalidSchema(e.to_string()))?;
+        Self::load_from_raw_schema(raw_schema)
     }
 
     pub fn parse_from_file(yaml_file_path: &str) -> Result<SchemaConfig> {
@@ -103,79 +509,519 @@ impl SchemaConfig {
         }
     }
 
-    fn get_key_value<'a>(
-        kv_map: &'a HashMap<String, Value>,
-        key: &'static str,
-    ) -> Result<&'a Value> {
-        kv_map.get(key).ok_or_else(|| MissingSchemaKey(key))
+    // Parses each schema file and merges them in order (see `merge`)
+    pub fn parse_from_files(yaml_file_paths: &[&str]) -> Result<SchemaConfig> {
+        let Some((first_path, other_paths)) = yaml_file_paths.split_first() else {
+            return Err(InvalidSchema("no schema file given".to_string()));
+        };
+        let mut schema_config = Self::parse_from_file(first_path)?;
+        for path in other_paths {
+            schema_config = schema_config.merge(Self::parse_from_file(path)?)?;
+        }
+        Ok(schema_config)
+    }
+
+    // Merges another schema into this one:
+    // - Timestamp schemas are unioned, skipping the patterns already declared.
+    // - Variable schemas are concatenated, so this schema's variables are declared first. A
+    //   variable name declared in both schemas results in `DuplicateVariableName`.
+    // - Delimiters are unioned, and so are multibyte delimiters.
+    // - The escape characters must agree if both are set, otherwise `InvalidSchema` is returned.
+    //   The same goes for the line separators.
+    // - Timestamps are required and CRLF line endings normalized if either schema says so.
+    pub fn merge(mut self, other: SchemaConfig) -> Result<SchemaConfig> {
+        for ts_schema in other.ts_schemas {
+            if false == self.timestamp_patterns().contains(&ts_schema.get_regex()) {
+                self.ts_schemas.push(ts_schema);
+            }
+        }
+
+        for var_schema in other.var_schemas {
+            if self.variable_names().contains(&var_schema.get_name()) {
+                return Err(DuplicateVariableName(var_schema.name));
+            }
+            self.var_schemas.push(var_schema);
+        }
+
+        for (delimiter, other_delimiter) in self.delimiters.iter_mut().zip(other.delimiters) {
+            *delimiter |= other_delimiter;
+        }
+        for multibyte_delimiter in other.multibyte_delimiters {
+            if false == self.multibyte_delimiters.contains(&multibyte_delimiter) {
+                self.multibyte_delimiters.push(multibyte_delimiter);
+            }
+        }
+        Self::sort_multibyte_delimiters(&mut self.multibyte_delimiters);
+
+        self.escape_char = match (self.escape_char, other.escape_char) {
+            (Some(c), Some(other_c)) if c != other_c => {
+                return Err(InvalidSchema("conflicting escape characters".to_string()))
+            }
+            (escape_char, other_escape_char) => escape_char.or(other_escape_char),
+        };
+        self.line_separator = match (self.line_separator, other.line_separator) {
+            (Some(c), Some(other_c)) if c != other_c => {
+                return Err(InvalidSchema("conflicting line separators".to_string()))
+            }
+            (line_separator, other_line_separator) => line_separator.or(other_line_separator),
+        };
+        self.timestamp_required |= other.timestamp_required;
+        self.crlf_normalized |= other.crlf_normalized;
+
+        Ok(self)
+    }
+
+    // Loads a variable schema written in the mapping form, e.g.:
+    // ```yaml
+    // int:
+    //   regex: '\d+'
+    //   anchored: true
+    //   transform: strip_leading_zeros
+    //   priority: 1
+    // ```
+    fn load_var_schema_with_options(
+        name: String,
+        options: RawVarOptions,
+        definitions: &HashMap<String, String>,
+        classes: &HashMap<String, String>,
+        ts_schemas: &[TimestampSchema],
+    ) -> Result<VarSchema> {
+        let Some(regex) = options.regex else {
+            return Err(MissingSchemaKey(Self::VAR_REGEX_KEY));
+        };
+        let (regex, timestamp_refs) = if options.timestamp_refs {
+            Self::expand_timestamp_refs(&regex, ts_schemas)?
+        } else {
+            (regex, Vec::new())
+        };
+        let regex = Self::expand_references(&regex, definitions, classes)?;
+        let mut var_schema = VarSchema::new(name, regex)?;
+        var_schema.timestamp_refs = timestamp_refs;
+        var_schema.anchored = options.anchored;
+        var_schema.transform = options
+            .transform
+            .map(|transform| Self::parse_transform(&transform))
+            .transpose()?;
+        var_schema.priority = options.priority;
+        Ok(var_schema)
+    }
+
+    // Replaces every `${timestamp.name}` reference in the regex with a group named `name` matching
+    // the pattern of the group of the same name in the timestamp schemas, and returns the
+    // referenced names. A match of the variable must then capture the same text as the timestamp
+    // of its log event did (see `VarSchema::matches_timestamp_refs`), e.g. to only match dates in
+    // the year of the event:
+    // ```yaml
+    // timestamp:
+    //   - '(?P<year>\d{4})-\d{2}-\d{2}'
+    // variables:
+    //   same_year_date:
+    //     regex: '${timestamp.year}/\d{2}/\d{2}'
+    //     timestamp_refs: true
+    // ```
+    // References require this opt-in, as they make lexing depend on the current timestamp. Each
+    // group can be referenced once per regex.
+    fn expand_timestamp_refs(
+        regex: &str,
+        ts_schemas: &[TimestampSchema],
+    ) -> Result<(String, Vec<String>)> {
+        let mut expanded = String::with_capacity(regex.len());
+        let mut timestamp_refs = Vec::new();
+        let mut remaining = regex;
+        while let Some(reference_start) = remaining.find(Self::TIMESTAMP_REF_PREFIX) {
+            let Some(reference_len) = remaining[reference_start..].find('}') else {
+                break;
+            };
+            let name_start = reference_start + Self::TIMESTAMP_REF_PREFIX.len();
+            let name = &remaining[name_start..reference_start + reference_len];
+            // Timestamp schemas may capture the group with different patterns
+            let mut group_regexes: Vec<&str> = Vec::new();
+            for ts_schema in ts_schemas {
+                if let Some(group_regex) = ts_schema.get_group_regex(name) {
+                    if false == group_regexes.contains(&group_regex) {
+                        group_regexes.push(group_regex);
+                    }
+                }
+            }
+            if group_regexes.is_empty() {
+                return Err(UnresolvedSchemaReference(format!("timestamp.{}", name)));
+            }
+
+            expanded += &remaining[..reference_start];
+            expanded += &format!("(?P<{}>{})", name, group_regexes.join("|"));
+            timestamp_refs.push(name.to_string());
+            remaining = &remaining[reference_start + reference_len + 1..];
+        }
+        expanded += remaining;
+        Ok((expanded, timestamp_refs))
+    }
+
+    // Checks that the named character classes declared under `classes` are bracket expressions,
+    // e.g.:
+    // ```yaml
+    // classes:
+    //   hex: '[0-9a-fA-F]'
+    // variables:
+    //   id: '0x\p{hex}+'
+    // ```
+    fn check_classes(classes: &HashMap<String, String>) -> Result<()> {
+        for (name, class) in classes {
+            if false == class.starts_with('[') || false == class.ends_with(']') {
+                return Err(InvalidSchema(format!(
+                    "class `{}` isn't a bracket expression",
+                    name
+                )));
+            }
+        }
+        Ok(())
     }
 
-    fn load_kv_pairs_from_yaml_content(
-        yaml_content: &str,
-    ) -> serde_yaml::Result<HashMap<String, Value>> {
-        let kv_map_result: HashMap<String, Value> = serde_yaml::from_str(&yaml_content)?;
-        Ok(kv_map_result)
+    // Expands the definitions, then the named classes of the regex
+    fn expand_references(
+        regex: &str,
+        definitions: &HashMap<String, String>,
+        classes: &HashMap<String, String>,
+    ) -> Result<String> {
+        Self::expand_classes(&Self::expand_definitions(regex, definitions)?, classes)
+    }
+
+    // Replaces every `\p{name}` reference in the regex with the named class. A reference inside a
+    // bracket expression (e.g. `[\p{hex}_]`) becomes a nested class. Escaped backslashes are left
+    // as is, so `\\p{2}` stays a literal backslash followed by `p{2}`.
+    fn expand_classes(regex: &str, classes: &HashMap<String, String>) -> Result<String> {
+        let mut expanded = String::with_capacity(regex.len());
+        let mut remaining = regex;
+        while let Some(escape_pos) = remaining.find('\\') {
+            expanded += &remaining[..escape_pos];
+            let escaped = &remaining[escape_pos..];
+            let reference_len = escaped.find('}').filter(|_| escaped.starts_with("\\p{"));
+            match reference_len {
+                Some(reference_len) => {
+                    let name = &escaped[3..reference_len];
+                    let class = classes
+                        .get(name)
+                        .ok_or_else(|| UnresolvedSchemaReference(name.to_string()))?;
+                    expanded += class;
+                    remaining = &escaped[reference_len + 1..];
+                }
+                None => {
+                    // Copy the escape sequence so that an escaped backslash isn't taken as the
+                    // start of a reference
+                    let escape_len = 1 + escaped[1..].chars().next().map_or(0, char::len_utf8);
+                    expanded += &escaped[..escape_len];
+                    remaining = &escaped[escape_len..];
+                }
+            }
+        }
+        expanded += remaining;
+        Ok(expanded)
     }
 
-    fn load_from_kv_pairs(kv_pairs: HashMap<String, Value>) -> Result<Self> {
+    // Replaces every `${name}` reference in the regex with the named definition, i.e. the reusable
+    // regex fragments declared under `definitions`, e.g.:
+    // ```yaml
+    // definitions:
+    //   num: '\d+'
+    // variables:
+    //   version: '${num}\.${num}'
+    // ```
+    // Each reference is replaced with a non-capturing group, so `${num}+` repeats the whole
+    // definition. Definitions can reference other definitions, but not recursively. An escaped
+    // `\${name}` isn't a reference.
+    fn expand_definitions(regex: &str, definitions: &HashMap<String, String>) -> Result<String> {
+        Self::expand_definitions_with_stack(regex, definitions, &mut Vec::new())
+    }
+
+    fn expand_definitions_with_stack(
+        regex: &str,
+        definitions: &HashMap<String, String>,
+        expansion_stack: &mut Vec<String>,
+    ) -> Result<String> {
+        let mut expanded = String::with_capacity(regex.len());
+        let mut remaining = regex;
+        while let Some(special_pos) = remaining.find(['\\', '$']) {
+            expanded += &remaining[..special_pos];
+            let special = &remaining[special_pos..];
+            if special.starts_with('\\') {
+                // Copy the escape sequence so that `\${name}` stays a literal `$`, and an escaped
+                // backslash isn't taken as escaping the `$` after it
+                let escape_len = 1 + special[1..].chars().next().map_or(0, char::len_utf8);
+                expanded += &special[..escape_len];
+                remaining = &special[escape_len..];
+                continue;
+            }
+            let Some(reference_len) = special.find('}').filter(|_| special.starts_with("${"))
+            else {
+                expanded.push('$');
+                remaining = &special[1..];
+                continue;
+            };
+            let name = &special[2..reference_len];
+            if expansion_stack.iter().any(|expanding| expanding == name) {
+                return Err(InvalidSchema(format!(
+                    "definition `{}` references itself",
+                    name
+                )));
+            }
+            let fragment = definitions
+                .get(name)
+                .ok_or_else(|| UnresolvedSchemaReference(name.to_string()))?;
+            expansion_stack.push(name.to_string());
+            let expanded_fragment =
+                Self::expand_definitions_with_stack(fragment, definitions, expansion_stack)?;
+            expansion_stack.pop();
+
+            // The definition is grouped so that it keeps its precedence, e.g. `${sign}\d+` with
+            // `sign: '\-|\+'` doesn't become `\-|\+\d+`
+            expanded += "(?:";
+            expanded += &expanded_fragment;
+            expanded += ")";
+            remaining = &special[reference_len + 1..];
+        }
+        expanded += remaining;
+        Ok(expanded)
+    }
+
+    fn parse_transform(name: &str) -> Result<Transform> {
+        match name {
+            "lowercase" => Ok(Transform::Lowercase),
+            "uppercase" => Ok(Transform::Uppercase),
+            "trim" => Ok(Transform::Trim),
+            "strip_leading_zeros" => Ok(Transform::StripLeadingZeros),
+            _ => Err(InvalidSchema(format!("unknown transform `{}`", name))),
+        }
+    }
+
+    // Parses an optional value that must be a single ASCII character
+    fn parse_optional_char(value: Option<String>) -> Result<Option<char>> {
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        let mut chars = value.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii() => Ok(Some(c)),
+            (Some(_), None) => Err(NoneASCIICharacters),
+            _ => Err(InvalidSchema(format!(
+                "{:?} isn't a single character",
+                value
+            ))),
+        }
+    }
+
+    fn set_delimiter(delimiters: &mut [bool; 128], c: char) -> Result<()> {
+        if false == c.is_ascii() {
+            return Err(NoneASCIICharacters);
+        }
+        delimiters[c as usize] = true;
+        Ok(())
+    }
+
+    // Multibyte delimiters are made of at least two ASCII characters, none of which is the line
+    // separator, e.g.:
+    // ```yaml
+    // delimiters: " "
+    // multibyte_delimiters: ["||", " - "]
+    // ```
+    fn check_multibyte_delimiters(
+        multibyte_delimiters: &[String],
+        line_separator: char,
+    ) -> Result<()> {
+        for multibyte_delimiter in multibyte_delimiters {
+            if false == multibyte_delimiter.is_ascii() {
+                return Err(NoneASCIICharacters);
+            }
+            if multibyte_delimiter.len() < 2 || multibyte_delimiter.contains(line_separator) {
+                return Err(InvalidSchema(format!(
+                    "invalid multibyte delimiter {:?}",
+                    multibyte_delimiter
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Sorts the delimiters longest first, then alphabetically so that duplicates end up next to
+    // each other and are removed
+    fn sort_multibyte_delimiters(multibyte_delimiters: &mut Vec<String>) {
+        multibyte_delimiters.sort_by(|lhs, rhs| {
+            std::cmp::Reverse(lhs.len())
+                .cmp(&std::cmp::Reverse(rhs.len()))
+                .then_with(|| lhs.cmp(rhs))
+        });
+        multibyte_delimiters.dedup();
+    }
+
+    // Parses an item of the sequence form of delimiters, which is either a single character or
+    // the name of a whitespace character
+    fn parse_delimiter_item(item: &str) -> Result<char> {
+        let mut chars = item.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(c);
+        }
+        match item {
+            "space" => Ok(' '),
+            "tab" => Ok('\t'),
+            "newline" => Ok('\n'),
+            "cr" => Ok('\r'),
+            _ => Err(InvalidSchema(format!("unknown delimiter {:?}", item))),
+        }
+    }
+
+    fn load_from_raw_schema(raw_schema: RawSchema) -> Result<Self> {
+        // Handle definitions and classes (optional)
+        let definitions = raw_schema.definitions;
+        let classes = raw_schema.classes;
+        Self::check_classes(&classes)?;
+
         // Handle timestamps
         let mut ts_schemas: Vec<TimestampSchema> = Vec::new();
-        let timestamps = Self::get_key_value(&kv_pairs, Self::TIMESTAMP_KEY)?;
-        if let Value::Sequence(sequence) = timestamps {
-            sequence.iter().try_for_each(|val| {
-                if let Value::String(s) = val {
-                    ts_schemas.push(TimestampSchema::new(s.clone())?);
-                    Ok(())
-                } else {
-                    Err(InvalidSchema)
-                }
-            })?;
-        } else {
-            return Err(InvalidSchema);
+        let timestamps = raw_schema
+            .timestamp
+            .ok_or(MissingSchemaKey(Self::TIMESTAMP_KEY))?;
+        for timestamp in timestamps {
+            ts_schemas.push(TimestampSchema::new(Self::expand_references(
+                &timestamp,
+                &definitions,
+                &classes,
+            )?)?);
         }
 
         // Handle variables
         let mut var_schemas: Vec<VarSchema> = Vec::new();
-        let vars = Self::get_key_value(&kv_pairs, Self::VAR_KEY)?;
-        if let Value::Mapping(map) = vars {
-            for (key, value) in map {
-                match (key, value) {
-                    (Value::String(name), Value::String(regex)) => {
-                        var_schemas.push(VarSchema::new(name.clone(), regex.clone())?);
-                    }
-                    _ => return Err(InvalidSchema),
+        let RawVariables(vars) = raw_schema
+            .variables
+            .ok_or(MissingSchemaKey(Self::VAR_KEY))?;
+        for (name, var) in vars {
+            // The YAML parser already rejects duplicate keys, but a builder doesn't
+            if var_schemas.iter().any(|schema| schema.get_name() == name) {
+                return Err(DuplicateVariableName(name));
+            }
+            match var {
+                RawVarSchema::Regex(regex) => {
+                    let regex = Self::expand_references(&regex, &definitions, &classes)?;
+                    var_schemas.push(VarSchema::new(name, regex)?);
+                }
+                RawVarSchema::WithOptions(options) => {
+                    var_schemas.push(Self::load_var_schema_with_options(
+                        name,
+                        options,
+                        &definitions,
+                        &classes,
+                        &ts_schemas,
+                    )?);
                 }
             }
-        } else {
-            return Err(InvalidSchema);
         }
 
         // Handle delimiter
         let mut delimiters = [false; 128];
-        let delimiter = Self::get_key_value(&kv_pairs, Self::DELIMITER_EKY)?;
-        if let Value::String(delimiter_str) = delimiter {
-            for c in delimiter_str.chars() {
-                if false == c.is_ascii() {
-                    return Err(NoneASCIICharacters);
+        let delimiter = raw_schema
+            .delimiters
+            .ok_or(MissingSchemaKey(Self::DELIMITER_EKY))?;
+        match delimiter {
+            RawDelimiters::Chars(delimiter_str) => {
+                for c in delimiter_str.chars() {
+                    Self::set_delimiter(&mut delimiters, c)?;
+                }
+            }
+            RawDelimiters::Items(items) => {
+                for item in items {
+                    Self::set_delimiter(&mut delimiters, Self::parse_delimiter_item(&item)?)?;
                 }
-                delimiters[c as usize] = true;
             }
-        } else {
-            return Err(InvalidSchema);
         }
-        delimiters['\n' as usize] = true;
 
-        Ok((Self {
+        // Handle escape character (optional)
+        let escape_char = Self::parse_optional_char(raw_schema.escape)?;
+
+        // Handle line separator (optional), which is implicitly a delimiter
+        let line_separator = Self::parse_optional_char(raw_schema.line_separator)?;
+        delimiters[line_separator.unwrap_or('\n') as usize] = true;
+
+        // Handle multibyte delimiters (optional)
+        let mut multibyte_delimiters = raw_schema.multibyte_delimiters;
+        Self::check_multibyte_delimiters(&multibyte_delimiters, line_separator.unwrap_or('\n'))?;
+        Self::sort_multibyte_delimiters(&mut multibyte_delimiters);
+
+        Ok(Self {
             ts_schemas,
             var_schemas,
             delimiters,
-        }))
+            multibyte_delimiters,
+            escape_char,
+            line_separator,
+            timestamp_required: raw_schema.require_timestamp,
+            crlf_normalized: raw_schema.normalize_crlf,
+        })
+    }
+}
+
+// Assembles a schema in code rather than from YAML, e.g.:
+// ```
+// let schema_config = SchemaConfig::builder()
+//     .add_timestamp(r"\d{2}:\d{2}:\d{2}")
+//     .add_variable("int", r"\d+")
+//     .add_delimiter(' ')
+//     .build()?;
+// ```
+// `build` validates the schema the same way as a schema file: regexes must compile, variable names
+// must be unique and delimiters must be ASCII. The line separator is a delimiter as usual.
+#[derive(Clone, Default)]
+pub struct SchemaConfigBuilder {
+    timestamps: Vec<String>,
+    variables: Vec<(String, String)>,
+    delimiters: String,
+}
+
+impl SchemaConfig {
+    pub fn builder() -> SchemaConfigBuilder {
+        SchemaConfigBuilder::default()
+    }
+}
+
+impl SchemaConfigBuilder {
+    pub fn add_timestamp(&mut self, regex: &str) -> &mut Self {
+        self.timestamps.push(regex.to_string());
+        self
+    }
+
+    // Variables are declared in the order they're added, which sets their schema IDs
+    pub fn add_variable(&mut self, name: &str, regex: &str) -> &mut Self {
+        self.variables.push((name.to_string(), regex.to_string()));
+        self
+    }
+
+    pub fn add_delimiter(&mut self, delimiter: char) -> &mut Self {
+        self.delimiters.push(delimiter);
+        self
+    }
+
+    pub fn build(&self) -> Result<SchemaConfig> {
+        let variables = self
+            .variables
+            .iter()
+            .map(|(name, regex)| (name.clone(), RawVarSchema::Regex(regex.clone())))
+            .collect();
+        SchemaConfig::load_from_raw_schema(RawSchema {
+            timestamp: Some(self.timestamps.clone()),
+            variables: Some(RawVariables(variables)),
+            delimiters: Some(RawDelimiters::Chars(self.delimiters.clone())),
+            multibyte_delimiters: Vec::new(),
+            escape: None,
+            line_separator: None,
+            require_timestamp: false,
+            normalize_crlf: false,
+            definitions: HashMap::new(),
+            classes: HashMap::new(),
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error_handling::Error::RegexParsingError;
+    use regex_syntax::ast;
 
     #[test]
     fn test_read_example_schema_file() -> Result<()> {
@@ -192,6 +1038,782 @@ mod tests {
         for delimiter in delimiters {
             assert!(parsed_schema.has_delimiter(delimiter));
         }
+        assert_eq!(
+            parsed_schema.delimiters(),
+            vec!['\t', '\n', '\r', ' ', '!', '%', ',', ':', ';']
+        );
+        assert_eq!(parsed_schema.get_escape_char(), None);
+
+        // The line separator is a delimiter even if it isn't listed
+        let minimal_schema = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ' ,'\nvariables:\n  int: '\\d+'\n",
+        )?;
+        assert_eq!(minimal_schema.delimiters(), vec!['\n', ' ', ',']);
+
+        assert_eq!(
+            parsed_schema.variable_names(),
+            vec!["int", "float", "hex", "loglevel"]
+        );
+        let timestamp_patterns = parsed_schema.timestamp_patterns();
+        assert_eq!(timestamp_patterns.len(), 3);
+        assert_eq!(
+            timestamp_patterns[2],
+            r"\d{4}\-\d{2}\-\d{2} \d{2}:\d{2}:\d{2}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_config_builder() -> Result<()> {
+        let schema_config = SchemaConfig::builder()
+            .add_timestamp(r"\d{2}:\d{2}:\d{2}")
+            .add_variable("int", r"\d+")
+            .add_variable("hex", r"0x[0-9a-f]+")
+            .add_delimiter(' ')
+            .add_delimiter(',')
+            .build()?;
+        assert_eq!(
+            schema_config.timestamp_patterns(),
+            vec![r"\d{2}:\d{2}:\d{2}"]
+        );
+        assert_eq!(schema_config.variable_names(), vec!["int", "hex"]);
+        assert_eq!(schema_config.delimiters(), vec!['\n', ' ', ',']);
+
+        // The same schema as YAML is equivalent
+        let yaml_schema = SchemaConfig::parse_from_str(
+            "timestamp: ['\\d{2}:\\d{2}:\\d{2}']\ndelimiters: ' ,'\nvariables:\n  int: '\\d+'\n  hex: '0x[0-9a-f]+'\n",
+        )?;
+        assert_eq!(
+            yaml_schema.timestamp_patterns(),
+            schema_config.timestamp_patterns()
+        );
+        assert_eq!(yaml_schema.delimiters(), schema_config.delimiters());
+
+        let mut builder = SchemaConfig::builder();
+        builder
+            .add_variable("int", r"\d+")
+            .add_variable("int", r"-\d+");
+        assert!(matches!(builder.build(), Err(DuplicateVariableName(name)) if "int" == name));
+
+        let mut builder = SchemaConfig::builder();
+        builder.add_variable("int", r"\d+").add_delimiter('\u{e9}');
+        assert!(matches!(builder.build(), Err(NoneASCIICharacters)));
+
+        let mut builder = SchemaConfig::builder();
+        builder.add_timestamp(r"\d{2}:(\d{2}");
+        assert!(matches!(builder.build(), Err(RegexParsingError(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_regex_position() -> Result<()> {
+        // The error keeps the span of the malformed part of the pattern, e.g. the unclosed bracket
+        match VarSchema::new("id".to_string(), r"id=[a-z".to_string()) {
+            Err(RegexParsingError(e)) => {
+                assert_eq!(e.kind(), &ast::ErrorKind::ClassUnclosed);
+                assert_eq!(e.span().start.line, 1);
+                assert_eq!(e.span().start.column, 4);
+                assert_eq!(e.span().start.offset, 3);
+            }
+            _ => panic!("Expected a regex parsing error"),
+        }
+        match TimestampSchema::new(r"\d{4}-(\d{2}".to_string()) {
+            Err(RegexParsingError(e)) => {
+                assert_eq!(e.kind(), &ast::ErrorKind::GroupUnclosed);
+                assert_eq!(e.span().start.column, 7);
+            }
+            _ => panic!("Expected a regex parsing error"),
+        }
+
+        // Errors in a schema file aren't flattened either
+        let result = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ' '\nvariables:\n  hex: '0x[0-9a-f+'\n",
+        );
+        match result {
+            Err(RegexParsingError(e)) => {
+                assert_eq!(e.kind(), &ast::ErrorKind::ClassUnclosed);
+                assert_eq!(e.span().start.column, 3);
+            }
+            _ => panic!("Expected a regex parsing error"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_layout_validation() -> Result<()> {
+        let schema_prefix = "timestamp: []\nvariables:\n  int: '\\d+'\n";
+        assert!(
+            SchemaConfig::parse_from_str(&(schema_prefix.to_string() + "delimiters: ' '\n"))
+                .is_ok()
+        );
+
+        // A misspelled top-level key is reported, rather than the key it was meant to be missing
+        let result =
+            SchemaConfig::parse_from_str(&(schema_prefix.to_string() + "delimiter: ' '\n"));
+        assert!(
+            matches!(&result, Err(InvalidSchema(e)) if e.contains("unknown field `delimiter`"))
+        );
+        let result = SchemaConfig::parse_from_str(
+            &(schema_prefix.to_string() + "delimiters: ' '\nrequire_timestmap: true\n"),
+        );
+        assert!(matches!(result, Err(InvalidSchema(_))));
+
+        // The same goes for variable options and values of the wrong type, which are reported along
+        // with the variable
+        let result = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ' '\nvariables:\n  int:\n    regex: '\\d+'\n    anchor: true\n",
+        );
+        assert!(matches!(
+            &result,
+            Err(InvalidSchema(e)) if e.starts_with("variables.int: unknown field `anchor`")
+        ));
+        let result = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ' '\nvariables:\n  int:\n    regex: '\\d+'\n    priority: high\n",
+        );
+        assert!(matches!(
+            &result,
+            Err(InvalidSchema(e)) if e.starts_with("variables.int: priority: invalid type")
+        ));
+        let result =
+            SchemaConfig::parse_from_str("timestamp: []\ndelimiters: ' '\nvariables:\n  int: 5\n");
+        assert!(matches!(&result, Err(InvalidSchema(e)) if e.starts_with("variables.int: ")));
+        let result =
+            SchemaConfig::parse_from_str("timestamp: 'TS'\ndelimiters: ' '\nvariables: {}\n");
+        assert!(matches!(result, Err(InvalidSchema(_))));
+        let result =
+            SchemaConfig::parse_from_str("timestamp: []\ndelimiters: ' '\nvariables: []\n");
+        assert!(matches!(result, Err(InvalidSchema(_))));
+
+        // Only malformed YAML is a parsing error
+        let result = SchemaConfig::parse_from_str("timestamp: [\n");
+        assert!(matches!(result, Err(YamlParsingError(_))));
+        let result = SchemaConfig::parse_from_str("timestamp: []\nvariables: {}\n");
+        assert!(matches!(result, Err(MissingSchemaKey("delimiters"))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_var_schema_with_options() -> Result<()> {
+        let parsed_schema = SchemaConfig::parse_from_str(
+            r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  int: '\d+'
+  anchored_int:
+    regex: '\d+'
+    anchored: true
+  hex:
+    regex: '[0-9a-f]+'
+"#,
+        )?;
+        let var_schemas = parsed_schema.get_var_schemas();
+        assert_eq!(var_schemas.len(), 3);
+        assert_eq!(var_schemas[0].is_anchored(), false);
+        assert_eq!(var_schemas[1].get_name(), "anchored_int");
+        assert_eq!(var_schemas[1].get_regex(), r"\d+");
+        assert_eq!(var_schemas[1].is_anchored(), true);
+        assert_eq!(var_schemas[2].is_anchored(), false);
+
+        let result = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ' '\nvariables:\n  int:\n    anchored: true\n",
+        );
+        assert!(matches!(result, Err(MissingSchemaKey("regex"))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp_capture_fields() -> Result<()> {
+        let ts_schema = TimestampSchema::new(
+            r"(?P<year>\d{4})\-(?P<month>\d{2})\-(?P<day>\d{2})( (?P<time>\d{2}:\d{2}))?"
+                .to_string(),
+        )?;
+        let fields = ts_schema.capture_fields("2015-01-31 15:50")?.unwrap();
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields["year"], "2015");
+        assert_eq!(fields["month"], "01");
+        assert_eq!(fields["day"], "31");
+        assert_eq!(fields["time"], "15:50");
+
+        // The optional group didn't take part in the match
+        let fields = ts_schema.capture_fields("2015-01-31")?.unwrap();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields.get("time"), None);
+
+        assert_eq!(ts_schema.capture_fields("2015-01-3")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_separator() -> Result<()> {
+        let schema_prefix = "timestamp: []\ndelimiters: ' '\nvariables:\n  int: '\\d+'\n";
+        let parsed_schema = SchemaConfig::parse_from_str(schema_prefix)?;
+        assert_eq!(parsed_schema.get_line_separator(), '\n');
+        assert!(parsed_schema.has_delimiter('\n'));
+
+        let schema = schema_prefix.to_string() + "line_separator: \"\\x1e\"\n";
+        let parsed_schema = SchemaConfig::parse_from_str(&schema)?;
+        assert_eq!(parsed_schema.get_line_separator(), '\x1e');
+        assert!(parsed_schema.has_delimiter('\x1e'));
+        assert_eq!(parsed_schema.has_delimiter('\n'), false);
+
+        // Merging keeps the separator, unless the other schema sets a different one
+        let other_schema = "timestamp: []\ndelimiters: ','\nvariables:\n  word: '\\w+'\n";
+        let merged_schema = parsed_schema.merge(SchemaConfig::parse_from_str(other_schema)?)?;
+        assert_eq!(merged_schema.get_line_separator(), '\x1e');
+        let other_schema = "timestamp: []\ndelimiters: ','\nvariables:\n  hex: '[a-f]+'\n\
+                            line_separator: \"\\0\"\n";
+        let result = merged_schema.merge(SchemaConfig::parse_from_str(&other_schema)?);
+        assert!(matches!(
+            result,
+            Err(crate::error_handling::Error::InvalidSchema(_))
+        ));
+
+        let schema = schema_prefix.to_string() + "line_separator: \"\\r\\n\"\n";
+        let result = SchemaConfig::parse_from_str(&schema);
+        assert!(matches!(
+            result,
+            Err(crate::error_handling::Error::InvalidSchema(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_var_schema_transform() -> Result<()> {
+        let parsed_schema = SchemaConfig::parse_from_str(
+            r#"
+timestamp: []
+delimiters: " "
+variables:
+  int: '\d+'
+  padded_int:
+    regex: '\d+'
+    transform: strip_leading_zeros
+"#,
+        )?;
+        let var_schemas = parsed_schema.get_var_schemas();
+        assert_eq!(var_schemas[0].get_transform(), None);
+        assert_eq!(
+            var_schemas[1].get_transform(),
+            Some(Transform::StripLeadingZeros)
+        );
+
+        assert_eq!(Transform::Lowercase.apply("ERROR"), "error");
+        assert_eq!(Transform::Uppercase.apply("warn"), "WARN");
+        assert_eq!(Transform::Trim.apply(" a b "), "a b");
+        assert_eq!(Transform::StripLeadingZeros.apply("0070"), "70");
+        assert_eq!(Transform::StripLeadingZeros.apply("000"), "0");
+
+        let result = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ' '\nvariables:\n  int:\n    regex: '\\d+'\n    transform: reverse\n",
+        );
+        assert!(matches!(
+            result,
+            Err(crate::error_handling::Error::InvalidSchema(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_var_schema_priority() -> Result<()> {
+        let parsed_schema = SchemaConfig::parse_from_str(
+            r#"
+timestamp: []
+delimiters: " "
+variables:
+  hex: '[0-9a-f]+'
+  int:
+    regex: '\d+'
+    priority: 2
+  word:
+    regex: '[a-z]+'
+    priority: -1
+"#,
+        )?;
+        let var_schemas = parsed_schema.get_var_schemas();
+        assert_eq!(var_schemas[0].get_priority(), 0);
+        assert_eq!(var_schemas[1].get_priority(), 2);
+        assert_eq!(var_schemas[2].get_priority(), -1);
+        assert_eq!(parsed_schema.var_schema_ids_by_priority(), vec![1, 0, 2]);
+        assert_eq!(parsed_schema.classify("42"), Some("int"));
+        assert_eq!(parsed_schema.classify("abc"), Some("hex"));
+        assert_eq!(parsed_schema.classify("xyz"), Some("word"));
+
+        let result = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ' '\nvariables:\n  int:\n    regex: '\\d+'\n    priority: high\n",
+        );
+        assert!(matches!(
+            result,
+            Err(crate::error_handling::Error::InvalidSchema(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_var_schema_as_literal() -> Result<()> {
+        let literal_regexes = ["ERROR", "WARN", "key=value"];
+        for regex in literal_regexes {
+            let var_schema = VarSchema::new("var".to_string(), regex.to_string())?;
+            assert_eq!(var_schema.as_literal(), Some(regex));
+        }
+
+        let non_literal_regexes = [r"\d+", "(ERROR)", "ERROR|WARN", "a.b", r"a\-b", "ab?"];
+        for regex in non_literal_regexes {
+            let var_schema = VarSchema::new("var".to_string(), regex.to_string())?;
+            assert_eq!(var_schema.as_literal(), None);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_regex_feature() {
+        let result = VarSchema::new("var".to_string(), r"id=(?=\d)\d+".to_string());
+        assert!(matches!(
+            result,
+            Err(crate::error_handling::Error::UnsupportedRegexFeature {
+                feature: "look-around",
+                position: 3
+            })
+        ));
+        let result = TimestampSchema::new(r"\d+?".to_string());
+        assert!(matches!(
+            result,
+            Err(crate::error_handling::Error::UnsupportedRegexFeature {
+                feature: "non-greedy repetition",
+                position: 0
+            })
+        ));
+
+        // The lexer's DFA can't enforce word boundaries, so they're rejected rather than ignored
+        let result = VarSchema::new("var".to_string(), r"\Bab".to_string());
+        assert!(matches!(
+            result,
+            Err(crate::error_handling::Error::UnsupportedRegexFeature {
+                feature: "assertion",
+                position: 0
+            })
+        ));
+        let result = TimestampSchema::new(r"\d{2}:\d{2}\b".to_string());
+        assert!(matches!(
+            result,
+            Err(crate::error_handling::Error::UnsupportedRegexFeature {
+                feature: "assertion",
+                position: 11
+            })
+        ));
+    }
+
+    #[test]
+    fn test_classify() -> Result<()> {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let schema_path = std::path::Path::new(project_root)
+            .join("examples")
+            .join("schema.yaml");
+        let parsed_schema = SchemaConfig::parse_from_file(schema_path.to_str().unwrap())?;
+
+        let var_schemas = parsed_schema.get_var_schemas();
+        assert!(var_schemas[0].matches("-42")?);
+        assert_eq!(var_schemas[0].matches("4.2")?, false);
+        assert!(var_schemas[1].matches("4.2")?);
+
+        let test_cases = [
+            ("42", Some("int")),
+            ("-7", Some("int")),
+            ("3.14", Some("float")),
+            ("-0.5", Some("float")),
+            ("0x1f", Some("hex")),
+            ("deadbeef", Some("hex")),
+            ("DEADBEEF", Some("hex")),
+            ("ERROR", Some("loglevel")),
+            ("TRACE", Some("loglevel")),
+            ("error", None),
+            ("10.0.0.1", None),
+            ("", None),
+        ];
+        for (token, expected) in test_cases {
+            assert_eq!(parsed_schema.classify(token), expected, "token: {}", token);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_variable_nfa() -> Result<()> {
+        let parsed_schema = SchemaConfig::parse_from_str(
+            r#"
+timestamp: []
+delimiters: " "
+variables:
+  int: '\d+'
+  ipv4: '\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3}'
+"#,
+        )?;
+        let nfa = parsed_schema.variable_nfa("ipv4")?;
+        assert_eq!(nfa.get_start(), NFA::START_STATE);
+        assert_eq!(nfa.get_accept(), NFA::ACCEPT_STATE);
+        assert!(nfa.accepts("192.168.0.1"));
+        assert_eq!(nfa.accepts("192.168.0"), false);
+
+        // The NFA is cached
+        assert!(std::ptr::eq(nfa, parsed_schema.variable_nfa("ipv4")?));
+
+        let result = parsed_schema.variable_nfa("ipv6");
+        assert!(matches!(result, Err(UnresolvedSchemaReference(name)) if "ipv6" == name));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_classes() -> Result<()> {
+        let parsed_schema = SchemaConfig::parse_from_str(
+            r#"
+classes:
+  hex: '[0-9a-fA-F]'
+definitions:
+  byte: '\p{hex}{2}'
+timestamp: []
+delimiters: " "
+variables:
+  id: '0x\p{hex}+'
+  mac: '${byte}(:${byte}){5}'
+  token: '[\p{hex}_]+'
+  path: 'C:\\p{2}'
+"#,
+        )?;
+        let var_schemas = parsed_schema.get_var_schemas();
+        assert_eq!(var_schemas[0].get_regex(), "0x[0-9a-fA-F]+");
+        assert!(var_schemas[0].matches("0xdeadBEEF")?);
+        assert_eq!(var_schemas[0].matches("0xg")?, false);
+        assert!(var_schemas[1].matches("00:1a:2B:3c:4d:5e")?);
+        assert_eq!(var_schemas[2].get_regex(), "[[0-9a-fA-F]_]+");
+        assert!(var_schemas[2].matches("ab_12")?);
+        // An escaped backslash isn't a reference
+        assert_eq!(var_schemas[3].get_regex(), r"C:\\p{2}");
+        assert!(var_schemas[3].matches(r"C:\pp")?);
+
+        let result = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ' '\nvariables:\n  id: '\\p{base64}+'\n\
+             classes:\n  hex: '[0-9a-f]'\n",
+        );
+        assert!(matches!(result, Err(UnresolvedSchemaReference(name)) if "base64" == name));
+
+        // Classes must be bracket expressions
+        let result = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ' '\nvariables:\n  id: '\\p{hex}+'\n\
+             classes:\n  hex: '\\d'\n",
+        );
+        assert!(matches!(result, Err(InvalidSchema(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_definitions() -> Result<()> {
+        let parsed_schema = SchemaConfig::parse_from_str(
+            r#"
+definitions:
+  num: '\d+'
+  date: '${num}\-${num}\-${num}'
+timestamp:
+  - '${date} ${num}:${num}'
+delimiters: " "
+variables:
+  version: '${num}\.${num}'
+  port:
+    regex: 'port=${num}'
+    anchored: true
+"#,
+        )?;
+        assert_eq!(
+            parsed_schema.timestamp_patterns(),
+            vec![r"(?:(?:\d+)\-(?:\d+)\-(?:\d+)) (?:\d+):(?:\d+)"]
+        );
+        let var_schemas = parsed_schema.get_var_schemas();
+        assert_eq!(var_schemas[0].get_regex(), r"(?:\d+)\.(?:\d+)");
+        assert_eq!(var_schemas[1].get_regex(), r"port=(?:\d+)");
+        assert!(var_schemas[0].matches("1.42")?);
+        assert!(var_schemas[1].matches("port=8080")?);
+
+        // A definition keeps its precedence where it's referenced, even if it's an alternation or
+        // is quantified
+        let parsed_schema = SchemaConfig::parse_from_str(
+            r#"
+definitions:
+  sign: '\-|\+'
+  hexdigit: '\d|[a-f]'
+timestamp: []
+delimiters: " "
+variables:
+  int: '${sign}\d+'
+  hex: '0x${hexdigit}+'
+  price: '\$${sign}\d+'
+"#,
+        )?;
+        let var_schemas = parsed_schema.get_var_schemas();
+        assert!(var_schemas[0].matches("-5")?);
+        assert_eq!(var_schemas[0].matches("-")?, false);
+        assert!(var_schemas[1].matches("0x1f")?);
+        assert_eq!(var_schemas[1].matches("0x1")?, true);
+        assert_eq!(var_schemas[1].matches("1f")?, false);
+        // An escaped `$` isn't a reference, but can be followed by one
+        assert_eq!(var_schemas[2].get_regex(), r"\$(?:\-|\+)\d+");
+        assert!(var_schemas[2].matches("$+5")?);
+        let definitions = HashMap::from([("sign".to_string(), r"\-|\+".to_string())]);
+        assert_eq!(
+            SchemaConfig::expand_definitions(r"\${sign}", &definitions)?,
+            r"\${sign}"
+        );
+        assert_eq!(
+            SchemaConfig::expand_definitions(r"\\${sign}", &definitions)?,
+            r"\\(?:\-|\+)"
+        );
+
+        let result = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ' '\nvariables:\n  version: '${num}\\.${minor}'\n\
+             definitions:\n  num: '\\d+'\n",
+        );
+        assert!(matches!(result, Err(UnresolvedSchemaReference(name)) if "minor" == name));
+
+        // Recursive definitions are rejected
+        let result = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ' '\nvariables:\n  a: '${x}'\n\
+             definitions:\n  x: 'a${y}'\n  y: 'b${x}'\n",
+        );
+        assert!(matches!(result, Err(InvalidSchema(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge() -> Result<()> {
+        let schema = SchemaConfig::parse_from_str(
+            r#"
+timestamp:
+  - '\d{4}\-\d{2}\-\d{2}'
+delimiters: " :"
+variables:
+  int: '\d+'
+  hex: '0x(\d|[a-f])+'
+"#,
+        )?;
+        let other_schema = SchemaConfig::parse_from_str(
+            r#"
+timestamp:
+  - '\d{4}\-\d{2}\-\d{2}'
+  - '\d{2}:\d{2}:\d{2}'
+delimiters: " ,="
+escape: '\'
+variables:
+  ip: '\d+\.\d+\.\d+\.\d+'
+"#,
+        )?;
+        let merged_schema = schema.merge(other_schema)?;
+
+        assert_eq!(
+            merged_schema.timestamp_patterns(),
+            vec![r"\d{4}\-\d{2}\-\d{2}", r"\d{2}:\d{2}:\d{2}"]
+        );
+        assert_eq!(merged_schema.variable_names(), vec!["int", "hex", "ip"]);
+        for c in 0..128u8 {
+            let is_delimiter = matches!(c as char, ' ' | ':' | ',' | '=' | '\n');
+            assert_eq!(merged_schema.has_delimiter(c as char), is_delimiter);
+        }
+        assert_eq!(merged_schema.get_escape_char(), Some('\\'));
+
+        // Variable names must stay unique
+        let duplicate_schema = "timestamp: []\ndelimiters: ' '\nvariables:\n  hex: '[a-f]+'\n";
+        let result = merged_schema.merge(SchemaConfig::parse_from_str(duplicate_schema)?);
+        assert!(matches!(result, Err(DuplicateVariableName(name)) if "hex" == name));
+
+        // Schema files are merged in order
+        let mut paths = Vec::new();
+        for (idx, content) in [
+            "timestamp: []\ndelimiters: ' '\nvariables:\n  int: '\\d+'\n",
+            "timestamp: []\ndelimiters: ','\nvariables:\n  word: '\\w+'\n",
+        ]
+        .iter()
+        .enumerate()
+        {
+            let path = std::env::temp_dir().join(format!("log_surgeon_test_schema_{}.yaml", idx));
+            std::fs::write(&path, content).map_err(IOError)?;
+            paths.push(path.to_str().unwrap().to_string());
+        }
+        let path_refs: Vec<&str> = paths.iter().map(|path| path.as_str()).collect();
+        let merged_schema = SchemaConfig::parse_from_files(&path_refs)?;
+        assert_eq!(merged_schema.variable_names(), vec!["int", "word"]);
+        assert!(merged_schema.has_delimiter(' ') && merged_schema.has_delimiter(','));
+        for path in &paths {
+            std::fs::remove_file(path).map_err(IOError)?;
+        }
+        assert!(SchemaConfig::parse_from_files(&[]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delimiter_sequence() -> Result<()> {
+        let schema_prefix = "timestamp:\n  - 'TIMESTAMP'\nvariables:\n  int: '\\d+'\n";
+
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "delimiters: [\" \", \"tab\", \":\"]\n").as_str(),
+        )?;
+        for c in 0..128u8 {
+            let is_delimiter = matches!(c as char, ' ' | '\t' | ':' | '\n');
+            assert_eq!(parsed_schema.has_delimiter(c as char), is_delimiter);
+        }
+
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "delimiters:\n  - space\n  - cr\n  - ','\n").as_str(),
+        )?;
+        for delimiter in [' ', '\r', ',', '\n'] {
+            assert!(parsed_schema.has_delimiter(delimiter));
+        }
+        assert_eq!(parsed_schema.has_delimiter('\t'), false);
+
+        for invalid_delimiters in ["[\"spaces\"]", "[1]", "[\"é\"]"] {
+            let result = SchemaConfig::parse_from_str(
+                (schema_prefix.to_string() + "delimiters: " + invalid_delimiters + "\n").as_str(),
+            );
+            assert!(result.is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multibyte_delimiters() -> Result<()> {
+        let schema_prefix =
+            "timestamp:\n  - 'TIMESTAMP'\ndelimiters: ' '\nvariables:\n  int: '\\d+'\n";
+
+        let parsed_schema = SchemaConfig::parse_from_str(schema_prefix)?;
+        assert!(parsed_schema.get_multibyte_delimiters().is_empty());
+
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "multibyte_delimiters: ['||', ' - ', '||']\n").as_str(),
+        )?;
+        assert_eq!(parsed_schema.get_multibyte_delimiters(), [" - ", "||"]);
+        assert_eq!(parsed_schema.has_delimiter('|'), false);
+
+        // Duplicates are removed even if other delimiters of the same length are declared between
+        // them
+        let other_parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "multibyte_delimiters: ['ab', 'cd', 'ab']\n").as_str(),
+        )?;
+        assert_eq!(other_parsed_schema.get_multibyte_delimiters(), ["ab", "cd"]);
+
+        // Merging keeps the longest delimiters first, and removes the delimiters both schemas have
+        let other_schema = SchemaConfig::parse_from_str(
+            "timestamp: []\ndelimiters: ','\nvariables:\n  word: '\\w+'\n\
+             multibyte_delimiters: ['::', '||', ' -- ']\n",
+        )?;
+        let merged_schema = parsed_schema.merge(other_schema)?;
+        assert_eq!(
+            merged_schema.get_multibyte_delimiters(),
+            [" -- ", " - ", "::", "||"]
+        );
+
+        for (invalid_delimiters, expected_err) in [
+            ("['|']", "InvalidSchema"),
+            ("[\"a\\nb\"]", "InvalidSchema"),
+            ("['éé']", "NoneASCIICharacters"),
+        ] {
+            let result = SchemaConfig::parse_from_str(
+                (schema_prefix.to_string() + "multibyte_delimiters: " + invalid_delimiters + "\n")
+                    .as_str(),
+            );
+            assert!(
+                matches!(&result, Err(e) if format!("{:?}", e).starts_with(expected_err)),
+                "{}",
+                invalid_delimiters
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_char() -> Result<()> {
+        let schema_prefix =
+            "timestamp:\n  - 'TIMESTAMP'\ndelimiters: ' ,'\nvariables:\n  int: '\\d+'\n";
+
+        let parsed_schema =
+            SchemaConfig::parse_from_str((schema_prefix.to_string() + "escape: '\\'\n").as_str())?;
+        assert_eq!(parsed_schema.get_escape_char(), Some('\\'));
+
+        let result =
+            SchemaConfig::parse_from_str((schema_prefix.to_string() + "escape: '\\\\'\n").as_str());
+        assert!(matches!(result, Err(InvalidSchema(_))));
+
+        Ok(())
+    }
+    #[test]
+    fn test_require_timestamp() -> Result<()> {
+        let schema_prefix =
+            "timestamp:\n  - 'TIMESTAMP'\ndelimiters: ' ,'\nvariables:\n  int: '\\d+'\n";
+
+        let parsed_schema = SchemaConfig::parse_from_str(schema_prefix)?;
+        assert_eq!(parsed_schema.is_timestamp_required(), false);
+
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "require_timestamp: true\n").as_str(),
+        )?;
+        assert_eq!(parsed_schema.is_timestamp_required(), true);
+
+        let result = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "require_timestamp: 'yes'\n").as_str(),
+        );
+        assert!(matches!(result, Err(InvalidSchema(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp_refs() -> Result<()> {
+        let schema_prefix =
+            "timestamp:\n  - '(?P<year>\\d{4})-\\d{2}'\n  - '(?P<year>\\d{2})/\\d{2}'\n\
+                             delimiters: ' '\nvariables:\n  date:\n";
+        let parsed_schema = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string()
+                + "    regex: 'y${timestamp.year}'\n    timestamp_refs: true\n")
+                .as_str(),
+        )?;
+        let ts_schema = &parsed_schema.get_ts_schemas()[0];
+        assert_eq!(ts_schema.get_group_regex("year"), Some(r"\d{4}"));
+        assert_eq!(ts_schema.get_group_regex("month"), None);
+
+        // The reference matches the group of any timestamp schema
+        let var_schema = &parsed_schema.get_var_schemas()[0];
+        assert_eq!(var_schema.get_regex(), r"y(?P<year>\d{4}|\d{2})");
+        assert_eq!(var_schema.get_timestamp_refs(), ["year"]);
+        let timestamp_fields = HashMap::from([("year".to_string(), "2024".to_string())]);
+        assert!(var_schema.matches_timestamp_refs("y2024", &timestamp_fields)?);
+        assert_eq!(
+            var_schema.matches_timestamp_refs("y2023", &timestamp_fields)?,
+            false
+        );
+
+        // References require the opt-in, and the group must exist
+        let result = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string() + "    regex: 'y${timestamp.year}'\n").as_str(),
+        );
+        assert!(matches!(result, Err(UnresolvedSchemaReference(name)) if "timestamp.year" == name));
+        let result = SchemaConfig::parse_from_str(
+            (schema_prefix.to_string()
+                + "    regex: 'y${timestamp.day}'\n    timestamp_refs: true\n")
+                .as_str(),
+        );
+        assert!(matches!(result, Err(UnresolvedSchemaReference(name)) if "timestamp.day" == name));
 
         Ok(())
     }
@@ -1,11 +1,15 @@
 use log_surgeon::error_handling::Result;
 use log_surgeon::lexer::BufferedFileStream;
+use log_surgeon::lexer::LexStep;
 use log_surgeon::lexer::Lexer;
+use log_surgeon::lexer::MatchPolicy;
+use log_surgeon::lexer::StringStream;
+use log_surgeon::lexer::{Token, TokenType};
 use log_surgeon::parser::SchemaConfig;
 
 use std::fs::File;
 use std::io::{self, BufRead};
-use std::rc::Rc;
+use std::sync::Arc;
 
 #[test]
 fn test_lexer_simple() -> Result<()> {
@@ -18,7 +22,7 @@ fn test_lexer_simple() -> Result<()> {
         .join("logs")
         .join("simple.log");
 
-    let parsed_schema = Rc::new(SchemaConfig::parse_from_file(
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
         schema_path.to_str().unwrap(),
     )?);
     let mut lexer = Lexer::new(parsed_schema)?;
@@ -33,7 +37,7 @@ fn test_lexer_simple() -> Result<()> {
 
     let mut parsed_lines = Vec::new();
     let mut parsed_line = String::new();
-    let mut curr_line_num = 0usize;
+    let mut curr_line_num = 1usize;
     for token in &tokens {
         if curr_line_num != token.get_line_num() {
             parsed_lines.push(parsed_line.clone());
@@ -59,3 +63,946 @@ fn test_lexer_simple() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_lexer_stream_initial_position() -> Result<()> {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let schema_path = std::path::Path::new(project_root)
+        .join("examples")
+        .join("schema_simple.yaml");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+    let mut lexer = Lexer::new(parsed_schema)?;
+    let mut input_stream = StringStream::new("TIMESTAMP Id: 3190\nnext line\n");
+    input_stream.set_initial_position(1000, 4096);
+    lexer.set_input_stream(Box::new(input_stream));
+
+    let mut tokens = Vec::new();
+    while let Some(token) = lexer.get_next_token()? {
+        tokens.push(token);
+    }
+    assert_eq!(tokens.len(), 5);
+    assert_eq!(tokens[0].get_val(), "TIMESTAMP");
+    assert_eq!(tokens[0].get_line_num(), 1000);
+    assert_eq!(tokens[0].get_byte_offset(), 4096);
+    assert_eq!(tokens[2].get_val(), "3190");
+    assert_eq!(tokens[2].get_line_num(), 1000);
+    assert_eq!(tokens[2].get_byte_offset(), 4096 + "TIMESTAMP Id: ".len());
+    assert_eq!(tokens[3].get_val(), "\n");
+    assert_eq!(tokens[3].get_line_num(), 1000);
+    assert_eq!(
+        tokens[3].get_byte_offset(),
+        4096 + "TIMESTAMP Id: 3190".len()
+    );
+
+    // The next line continues counting from the given position
+    assert_eq!(tokens[4].get_val(), "next line\n");
+    assert_eq!(tokens[4].get_line_num(), 1001);
+    assert_eq!(
+        tokens[4].get_byte_offset(),
+        4096 + "TIMESTAMP Id: 3190\n".len()
+    );
+    Ok(())
+}
+
+fn lex_all(lexer: &mut Lexer, input: &str) -> Result<Vec<Token>> {
+    lexer.set_input_stream(Box::new(StringStream::new(input)));
+    let mut tokens = Vec::new();
+    while let Some(token) = lexer.get_next_token()? {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+#[test]
+fn test_lexer_match_policy() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  int: '\d+'
+  float: '\d+\.\d+'
+"#,
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+    assert_eq!(lexer.get_match_policy(), MatchPolicy::LongestMatch);
+
+    // Longest match: "3.14" is a single float
+    let tokens = lex_all(&mut lexer, "value 3.14 42\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["value ", "3.14", " ", "42", "\n"]);
+    assert!(matches!(tokens[1].get_token_type(), TokenType::Variable(1)));
+    assert!(matches!(tokens[3].get_token_type(), TokenType::Variable(0)));
+
+    // First declared: `int` is declared first, so "3.14" splits into "3" and ".14"
+    lexer.set_match_policy(MatchPolicy::FirstDeclared);
+    let tokens = lex_all(&mut lexer, "value 3.14 42\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["value ", "3", ".14 ", "42", "\n"]);
+    assert!(matches!(tokens[1].get_token_type(), TokenType::Variable(0)));
+    assert!(matches!(tokens[2].get_token_type(), TokenType::StaticText));
+    assert!(matches!(tokens[3].get_token_type(), TokenType::Variable(0)));
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_escape_char() -> Result<()> {
+    let schema_prefix = r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " ,"
+variables:
+  word: '[a-z]+'
+"#;
+
+    // Without an escape character, `,` splits `a\,b`
+    let mut lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(schema_prefix)?))?;
+    let tokens = lex_all(&mut lexer, "x a\\,b\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["x", " a\\,", "b", "\n"]);
+
+    // With `\` as the escape character, `a\,b` stays in one token
+    let schema = schema_prefix.to_string() + "escape: '\\'\n";
+    let mut lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(&schema)?))?;
+    let tokens = lex_all(&mut lexer, "x a\\,b y\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["x", " a\\,b ", "y", "\n"]);
+
+    // An escaped escape character doesn't escape the delimiter
+    let tokens = lex_all(&mut lexer, "x a\\\\,b\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["x", " a\\\\,", "b", "\n"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_anchored_variable() -> Result<()> {
+    let schema_template = r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  int:
+    regex: '\d+'
+    anchored: ANCHORED
+"#;
+
+    // Not anchored: the prefix "123" of "123abc" is a variable under the first-declared policy
+    let schema = schema_template.replace("ANCHORED", "false");
+    let mut lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(&schema)?))?;
+    lexer.set_match_policy(MatchPolicy::FirstDeclared);
+    let tokens = lex_all(&mut lexer, "id 123abc 45\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["id ", "123", "abc ", "45", "\n"]);
+
+    // Anchored: "123abc" is static text since the match doesn't extend to a delimiter
+    let schema = schema_template.replace("ANCHORED", "true");
+    let mut lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(&schema)?))?;
+    lexer.set_match_policy(MatchPolicy::FirstDeclared);
+    let tokens = lex_all(&mut lexer, "id 123abc 45\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["id 123abc ", "45", "\n"]);
+    assert!(matches!(tokens[0].get_token_type(), TokenType::StaticText));
+    assert!(matches!(tokens[1].get_token_type(), TokenType::Variable(0)));
+
+    // The longest-match policy always requires a delimiter after the match
+    lexer.set_match_policy(MatchPolicy::LongestMatch);
+    let tokens = lex_all(&mut lexer, "id 123abc 45\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["id 123abc ", "45", "\n"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_variable_transform() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  loglevel:
+    regex: '(INFO)|(ERROR)'
+    transform: lowercase
+  int:
+    regex: '\d+'
+    transform: strip_leading_zeros
+"#,
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+    let tokens = lex_all(&mut lexer, "ERROR code 0042 000\n")?;
+    let raws: Vec<&str> = tokens.iter().map(|token| token.raw()).collect();
+    assert_eq!(raws, vec!["ERROR", " code ", "0042", " ", "000", "\n"]);
+    let values: Vec<&str> = tokens.iter().map(|token| token.value()).collect();
+    assert_eq!(values, vec!["error", " code ", "42", " ", "0", "\n"]);
+
+    // The byte offsets still refer to the raw text
+    assert_eq!(tokens[2].get_byte_offset(), 11);
+    assert_eq!(tokens[2].get_val(), "0042");
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_dfa_csv() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TS'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let lexer = Lexer::new(schema_config)?;
+    assert_eq!(
+        lexer.timestamp_dfa_to_csv(),
+        "from_state,symbol_range,to_state,accept_tag\n0,T,1,\n1,S,2,0\n"
+    );
+    assert_eq!(
+        lexer.var_dfa_to_csv(),
+        "from_state,symbol_range,to_state,accept_tag\n0,0-9,1,0\n1,0-9,1,0\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_lexer_crlf() -> Result<()> {
+    let schema_prefix = r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " \r"
+variables:
+  int: '\d+'
+"#;
+    let input = "TIMESTAMP a 1\r\nline 2\r\n\r\nx\ry 3\r\n";
+
+    // By default the `\r` stays in the tokens
+    let mut lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(schema_prefix)?))?;
+    let tokens = lex_all(&mut lexer, input)?;
+    assert!(tokens.iter().any(|token| token.get_val().contains("\r\n")));
+
+    let schema = schema_prefix.to_string() + "normalize_crlf: true\n";
+    let mut lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(&schema)?))?;
+    let tokens = lex_all(&mut lexer, input)?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(
+        vals,
+        vec![
+            "TIMESTAMP",
+            " a ",
+            "1",
+            "\n",
+            "line ",
+            "2",
+            "\n",
+            "\n",
+            "x\ry ",
+            "3",
+            "\n"
+        ]
+    );
+    let line_nums: Vec<usize> = tokens.iter().map(|token| token.get_line_num()).collect();
+    assert_eq!(line_nums, vec![1, 1, 1, 1, 2, 2, 2, 3, 4, 4, 4]);
+
+    // A lone `\r` is kept, and byte offsets still point into the original input
+    for token in &tokens {
+        let byte_offset = token.get_byte_offset();
+        assert!(input[byte_offset..]
+            .replace("\r\n", "\n")
+            .starts_with(token.get_val()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_literal_variables() -> Result<()> {
+    // Wrapping a literal in a group makes it a regex, so the two schemas describe the same
+    // variables while only the first one goes through the literal fast path
+    let schema_template = r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " \t:"
+variables:
+  int: '\d+'
+  error: 'LEFTERRORRIGHT'
+  upper: '[A-Z]+'
+  warn: 'LEFTWARNRIGHT'
+  code: 'E\d+'
+  info: 'LEFTINFORIGHT'
+"#;
+    let literal_schema = schema_template.replace("LEFT", "").replace("RIGHT", "");
+    let regex_schema = schema_template.replace("LEFT", "(").replace("RIGHT", ")");
+    let input = "TIMESTAMP ERROR WARN INFO ERRORS xERROR ERROR: E12 INFO1 123 in\n\
+                 ERROR\tINFO WARN 2\n\
+                 INFO";
+
+    let mut literal_lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(&literal_schema)?))?;
+    let mut regex_lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(&regex_schema)?))?;
+    for match_policy in [MatchPolicy::LongestMatch, MatchPolicy::FirstDeclared] {
+        literal_lexer.set_match_policy(match_policy);
+        regex_lexer.set_match_policy(match_policy);
+        let literal_tokens = lex_all(&mut literal_lexer, input)?;
+        let regex_tokens = lex_all(&mut regex_lexer, input)?;
+        assert_eq!(
+            format!("{:?}", literal_tokens),
+            format!("{:?}", regex_tokens)
+        );
+    }
+
+    literal_lexer.set_match_policy(MatchPolicy::LongestMatch);
+    let tokens = lex_all(&mut literal_lexer, input)?;
+    let vars: Vec<(&str, usize)> = tokens
+        .iter()
+        .filter_map(|token| match token.get_token_type() {
+            TokenType::Variable(schema_id) => Some((token.get_val(), schema_id)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        vars,
+        vec![
+            // `ERROR` is declared before `upper`, while `WARN` is declared after it
+            ("ERROR", 1),
+            ("WARN", 2),
+            ("INFO", 2),
+            ("ERRORS", 2),
+            ("ERROR", 1),
+            ("E12", 4),
+            ("123", 0),
+            ("ERROR", 1),
+            ("INFO", 2),
+            ("WARN", 2),
+            ("2", 0),
+            ("INFO", 2),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_multiword_variable() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  int: '\d+'
+  reason: '"(\w| )*"'
+  range: '\d+-( \d+)+'
+"#,
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+    let lex_vals = |lexer: &mut Lexer, input: &str| -> Result<Vec<(String, TokenType)>> {
+        Ok(lex_all(lexer, input)?
+            .iter()
+            .map(|token| (token.get_val().to_string(), token.get_token_type()))
+            .collect())
+    };
+
+    // The quoted string is captured as a single variable despite the spaces
+    let tokens = lex_vals(&mut lexer, "failed \"a b c\" 42\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|(val, _)| val.as_str()).collect();
+    assert_eq!(vals, vec!["failed ", "\"a b c\"", " ", "42", "\n"]);
+    assert!(matches!(tokens[1].1, TokenType::Variable(1)));
+    assert!(matches!(tokens[3].1, TokenType::Variable(0)));
+
+    // An unterminated quote doesn't hide the variables after it
+    let tokens = lex_vals(&mut lexer, "say \"hello 123 more\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|(val, _)| val.as_str()).collect();
+    assert_eq!(vals, vec!["say \"hello ", "123", " more\n"]);
+    assert!(matches!(tokens[1].1, TokenType::Variable(0)));
+
+    // A match not followed by a delimiter falls back to lexing after its first delimiter
+    let tokens = lex_vals(&mut lexer, "x \"a b\"c 7")?;
+    let vals: Vec<&str> = tokens.iter().map(|(val, _)| val.as_str()).collect();
+    assert_eq!(vals, vec!["x \"a b\"c ", "7"]);
+
+    // When the match fails after consuming more delimiters, the longest match followed by a
+    // delimiter is extracted
+    let tokens = lex_vals(&mut lexer, "pages 1- 2 3 4- x\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|(val, _)| val.as_str()).collect();
+    assert_eq!(vals, vec!["pages ", "1- 2 3", " 4- x\n"]);
+    assert!(matches!(tokens[1].1, TokenType::Variable(2)));
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_unknown_tokens() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let input = "id 42 caf\u{e9} \u{fffd}\u{fffd} 7\n";
+    let mut lexer = Lexer::new(schema_config)?;
+
+    // By default, non-ASCII characters are folded into static text
+    let tokens = lex_all(&mut lexer, input)?;
+    assert_eq!(tokens.iter().any(|token| token.is_unknown()), false);
+
+    lexer.set_emit_unknown_tokens(true);
+    let tokens = lex_all(&mut lexer, input)?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(
+        vals,
+        vec![
+            "id ",
+            "42",
+            " caf",
+            "\u{e9}",
+            " ",
+            "\u{fffd}\u{fffd}",
+            " ",
+            "7",
+            "\n"
+        ]
+    );
+    let unknown_vals: Vec<&str> = tokens
+        .iter()
+        .filter(|token| token.is_unknown())
+        .map(|token| token.get_val())
+        .collect();
+    assert_eq!(unknown_vals, vec!["\u{e9}", "\u{fffd}\u{fffd}"]);
+    assert!(matches!(tokens[5].get_token_type(), TokenType::Unknown));
+    assert_eq!(tokens[5].get_byte_offset(), input.find('\u{fffd}').unwrap());
+    assert!(matches!(tokens[7].get_token_type(), TokenType::Variable(0)));
+
+    // The line can still be reconstructed from its tokens
+    assert_eq!(vals.concat(), input);
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_standalone() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  word: 'a|b'
+"#,
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+    assert!(matches!(
+        lexer.get_next_token(),
+        Err(log_surgeon::error_handling::Error::LexerInputStreamNotSet)
+    ));
+
+    lexer.set_input_stream(Box::new(StringStream::new("a b")));
+    let first_token = lexer.get_next_token()?.unwrap();
+    assert_eq!(first_token.get_val(), "a");
+    assert!(matches!(
+        first_token.get_token_type(),
+        TokenType::Variable(0)
+    ));
+    let second_token = lexer.get_next_token()?.unwrap();
+    assert_eq!(second_token.get_val(), " ");
+    assert!(matches!(
+        second_token.get_token_type(),
+        TokenType::StaticText
+    ));
+    let third_token = lexer.get_next_token()?.unwrap();
+    assert_eq!(third_token.get_val(), "b");
+    assert!(matches!(
+        third_token.get_token_type(),
+        TokenType::Variable(0)
+    ));
+    assert_eq!(third_token.get_byte_offset(), 2);
+
+    // `None` marks the end of the stream, and keeps being returned
+    assert!(lexer.get_next_token()?.is_none());
+    assert!(lexer.get_next_token()?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_tokenize_borrowed() -> Result<()> {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    let schema_path = std::path::Path::new(project_root)
+        .join("examples")
+        .join("schema_simple.yaml");
+    let log_path = std::path::Path::new(project_root)
+        .join("examples")
+        .join("logs")
+        .join("simple.log");
+    let schema_config = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+    let mut input = std::fs::read_to_string(log_path).unwrap();
+    input += "  naïve café id=42 ü\n";
+    let mut lexer = Lexer::new(schema_config)?;
+
+    lexer.set_input_stream(Box::new(StringStream::new(input.as_str())));
+    let mut owned_tokens = Vec::new();
+    while let Some(token) = lexer.get_next_token()? {
+        owned_tokens.push(token);
+    }
+
+    let borrowed_tokens = lexer
+        .tokenize_borrowed(input.as_str())
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(owned_tokens.len(), borrowed_tokens.len());
+    for (owned, borrowed) in owned_tokens.iter().zip(borrowed_tokens.iter()) {
+        assert_eq!(owned.get_val().as_bytes(), borrowed.get_val().as_bytes());
+        assert_eq!(
+            format!("{:?}", owned.get_token_type()),
+            format!("{:?}", borrowed.get_token_type())
+        );
+        assert_eq!(owned.get_line_num(), borrowed.get_line_num());
+        assert_eq!(owned.get_byte_offset(), borrowed.get_byte_offset());
+        // The borrowed text is a slice of the input, not a copy
+        let input_range = input.as_bytes().as_ptr_range();
+        assert!(input_range.contains(&borrowed.get_val().as_ptr()));
+    }
+
+    // Borrowed tokens keep the `\r` of CRLF line endings, so they cover the input exactly
+    let crlf_input = "TIMESTAMP a\r\nb\r\n";
+    let borrowed_tokens = lexer
+        .tokenize_borrowed(crlf_input)
+        .collect::<Result<Vec<_>>>()?;
+    let concatenated: String = borrowed_tokens.iter().map(|t| t.get_val()).collect();
+    assert_eq!(concatenated, crlf_input);
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_delimiter_in_variable() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " -"
+variables:
+  int: '\-{0,1}\d+'
+"#,
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+    lexer.set_input_stream(Box::new(StringStream::new("-42 x -7 a-3 - 5-\n")));
+    let mut tokens = Vec::new();
+    while let Some(token) = lexer.get_next_token()? {
+        tokens.push((token.get_val().to_string(), token.get_token_type()));
+    }
+    let vars: Vec<&str> = tokens
+        .iter()
+        .filter(|(_, token_type)| matches!(token_type, TokenType::Variable(0)))
+        .map(|(val, _)| val.as_str())
+        .collect();
+    // A `-` that starts a variable match is part of the variable. A `-` following other text
+    // delimits it, and a `-` the variable can't extend into a match stays a delimiter.
+    assert_eq!(vars, vec!["-42", "-7", "3", "5"]);
+    let vals: Vec<&str> = tokens.iter().map(|(val, _)| val.as_str()).collect();
+    assert_eq!(
+        vals,
+        vec!["-42", " x ", "-7", " a-", "3", " - ", "5", "-\n"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_variable_stops_at_line_separator() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TS'
+delimiters: " "
+variables:
+  msg: 'a.*b'
+"#,
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+    let tokens = lex_all(&mut lexer, "TS a x\nyb z\nline3 ab\n")?;
+    let summary: Vec<(&str, usize, bool)> = tokens
+        .iter()
+        .map(|token| {
+            (
+                token.get_val(),
+                token.get_line_num(),
+                matches!(token.get_token_type(), TokenType::Variable(_)),
+            )
+        })
+        .collect();
+    // The match starting at `a` can't reach the `b` of the next line
+    assert_eq!(
+        summary,
+        vec![
+            ("TS", 1, false),
+            (" a x\n", 1, false),
+            ("yb z\n", 2, false),
+            ("line3 ", 3, false),
+            ("ab", 3, true),
+            ("\n", 3, false),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_variable_priority() -> Result<()> {
+    let schema_template = r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  hex: '[0-9a-f]+'
+  int:
+    regex: '\d+'
+    priority: INT_PRIORITY
+  level: 'LEFTINFORIGHT'
+  word:
+    regex: '[A-Z]+'
+    priority: 1
+"#;
+    let input = "TIMESTAMP 42 beef INFO\n";
+    for (int_priority, literal_prefix, literal_suffix) in [("0", "", ""), ("1", "(", ")")] {
+        // Literal variables go through the literal fast path, which must honor priorities too
+        let schema = schema_template
+            .replace("INT_PRIORITY", int_priority)
+            .replace("LEFT", literal_prefix)
+            .replace("RIGHT", literal_suffix);
+        let mut lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(&schema)?))?;
+        let tokens = lex_all(&mut lexer, input)?;
+        let vars: Vec<(&str, usize)> = tokens
+            .iter()
+            .filter_map(|token| match token.get_token_type() {
+                TokenType::Variable(schema_id) => Some((token.get_val(), schema_id)),
+                _ => None,
+            })
+            .collect();
+        // `42` ties between `hex` and `int`: declaration order decides when priorities are equal
+        let expected_int_var = if "0" == int_priority { 0 } else { 1 };
+        assert_eq!(
+            vars,
+            vec![("42", expected_int_var), ("beef", 0), ("INFO", 3)]
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_next_step() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " \t:\n"
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+
+    // Input made only of delimiters still produces tokens, then ends with `Eof`
+    for input in [" \t: ", "  \n\n:", "\n"] {
+        lexer.set_input_stream(Box::new(StringStream::new(input)));
+        let mut lexed = String::new();
+        loop {
+            match lexer.next_step()? {
+                LexStep::Token(token) => lexed += token.get_val(),
+                LexStep::Skipped => panic!("Nothing is skipped without a line limit"),
+                LexStep::Eof => break,
+            }
+        }
+        assert_eq!(lexed, input);
+        assert!(matches!(lexer.next_step()?, LexStep::Eof));
+    }
+    lexer.set_input_stream(Box::new(StringStream::new("")));
+    assert!(matches!(lexer.next_step()?, LexStep::Eof));
+
+    // A line over the limit is skipped from its last token on (including the space after `1`), and
+    // lexing carries on with the next line
+    lexer.set_max_line_bytes(Some(8));
+    lexer.set_input_stream(Box::new(StringStream::new("1 23456789012\n4\n")));
+    let mut steps = Vec::new();
+    loop {
+        match lexer.next_step()? {
+            LexStep::Token(token) => steps.push(token.get_val().to_string()),
+            LexStep::Skipped => steps.push("<skipped>".to_string()),
+            LexStep::Eof => break,
+        }
+    }
+    assert_eq!(steps, vec!["1", "<skipped>", "4", "\n"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_token_columns() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " \t\n"
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+    lexer.set_input_stream(Box::new(StringStream::new("\t42 x\n1\t2 \u{e9}\t3\n")));
+    let mut columns = Vec::new();
+    while let Some(token) = lexer.get_next_token()? {
+        columns.push((
+            token.get_val().to_string(),
+            token.get_column(),
+            token.display_column(Token::DEFAULT_TAB_WIDTH),
+            token.display_column(4),
+        ));
+    }
+    let expected = [
+        ("\t", 0, 0, 0),
+        ("42", 1, 8, 4),
+        (" x\n", 3, 10, 6),
+        ("1", 0, 0, 0),
+        ("\t", 1, 1, 1),
+        ("2", 2, 8, 4),
+        // `é` takes 2 bytes but a single column
+        (" \u{e9}\t", 3, 9, 5),
+        ("3", 7, 16, 8),
+        ("\n", 8, 17, 9),
+    ];
+    let expected: Vec<(String, usize, usize, usize)> = expected
+        .iter()
+        .map(|(val, column, display_column, narrow_display_column)| {
+            (
+                val.to_string(),
+                *column,
+                *display_column,
+                *narrow_display_column,
+            )
+        })
+        .collect();
+    assert_eq!(columns, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_timestamp_refs() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '(?P<year>\d{4})-\d{2}-\d{2}'
+delimiters: " \n"
+variables:
+  same_year_date:
+    regex: '${timestamp.year}/\d{2}/\d{2}'
+    timestamp_refs: true
+"#,
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+    lexer.set_input_stream(Box::new(StringStream::new(
+        "2024-01-02 backup of 2024/01/01 and 2023/12/31\n\
+         restored 2024/01/02\n\
+         2025-01-01 backup of 2024/12/31 and 2025/01/01\n",
+    )));
+    let mut vars = Vec::new();
+    while let Some(token) = lexer.get_next_token()? {
+        if let TokenType::Variable(_) = token.get_token_type() {
+            vars.push(token.get_val().to_string());
+        }
+    }
+    // Only the dates in the year of the last timestamp are matched
+    assert_eq!(vars, vec!["2024/01/01", "2024/01/02", "2025/01/01"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_highlight_line() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '\d{4}-\d{2}-\d{2}'
+delimiters: " "
+variables:
+  level: 'ERROR|WARN|INFO'
+  ip: '\d+\.\d+\.\d+\.\d+'
+"#,
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+    let spans: Vec<(std::ops::Range<usize>, String)> = lexer
+        .highlight_line("2024-01-01 ERROR 10.0.0.1")?
+        .into_iter()
+        .map(|(range, token_type)| (range, format!("{:?}", token_type)))
+        .collect();
+    let expected = [
+        (0..10, "Timestamp(0)"),
+        (10..11, "StaticText"),
+        (11..16, "Variable(0)"),
+        (16..17, "StaticText"),
+        (17..25, "Variable(1)"),
+    ];
+    let expected: Vec<(std::ops::Range<usize>, String)> = expected
+        .into_iter()
+        .map(|(range, token_type)| (range, token_type.to_string()))
+        .collect();
+    assert_eq!(spans, expected);
+
+    // Each line is highlighted on its own
+    assert!(lexer.highlight_line("")?.is_empty());
+    assert_eq!(lexer.highlight_line("WARN")?.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_multibyte_delimiters() -> Result<()> {
+    let schema_prefix = r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  word: '[a-z]+'
+"#;
+    let schema = schema_prefix.to_string() + "multibyte_delimiters: ['||', ' - ']\n";
+    let mut lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(&schema)?))?;
+
+    let tokens = lex_all(&mut lexer, "a||b\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["a", "||", "b", "\n"]);
+    let content_tokens: Vec<&Token> = tokens
+        .iter()
+        .filter(|token| matches!(token.get_token_type(), TokenType::Variable(_)))
+        .collect();
+    assert_eq!(content_tokens.len(), 2);
+
+    // A single `|` isn't a delimiter, while the longest delimiter wins over a single space
+    let tokens = lex_all(&mut lexer, "a|b c - d\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["a|b ", "c", " - ", "d", "\n"]);
+
+    // Delimiters spanning the end of the input or a line aren't matched
+    let tokens = lex_all(&mut lexer, "a|\nb|")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["a|\n", "b|"]);
+
+    // Without multibyte delimiters, `a||b` is a single token
+    let mut lexer = Lexer::new(Arc::new(SchemaConfig::parse_from_str(schema_prefix)?))?;
+    let tokens = lex_all(&mut lexer, "a||b\n")?;
+    let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+    assert_eq!(vals, vec!["a||b\n"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_relex_line() -> Result<()> {
+    let schema_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("examples")
+        .join("schema.yaml");
+    let schema_config = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+
+    let mut lines: Vec<String> = (1..=50)
+        .map(|line_num| format!("2015-01-31T15:50:45.392 INFO line {}\n", line_num))
+        .collect();
+    let edited_line = "2015-01-31T15:50:46.001 ERROR edited 0x1f -3.5\n";
+    let tokens = lexer.relex_line(42, edited_line)?;
+    assert!(tokens.len() > 1);
+    assert!(tokens.iter().all(|token| 42 == token.get_line_num()));
+    assert_eq!(tokens[0].get_byte_offset(), 0);
+    assert!(matches!(
+        tokens[0].get_token_type(),
+        TokenType::Timestamp(_)
+    ));
+
+    // The tokens match the ones of the line lexed within the whole input
+    lines[41] = edited_line.to_string();
+    let all_tokens = lex_all(&mut lexer, lines.concat().as_str())?;
+    let line_tokens: Vec<String> = all_tokens
+        .iter()
+        .filter(|token| 42 == token.get_line_num())
+        .map(|token| format!("{:?}", token))
+        .collect();
+    let relexed_tokens: Vec<String> = tokens.iter().map(|token| format!("{:?}", token)).collect();
+    assert_eq!(relexed_tokens, line_tokens);
+
+    Ok(())
+}
+
+#[test]
+fn test_lexer_self_describing_tokens() -> Result<()> {
+    let schema_config = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " =\t"
+variables:
+  int: '\d+'
+  user: 'user_[a-z]+'
+"#,
+    )?);
+    let mut lexer = Lexer::new(schema_config)?;
+    let tokens = lex_all(&mut lexer, "TIMESTAMP user_bob took  42\tms\n")?;
+    let summary: Vec<(&str, String, Option<&str>)> = tokens
+        .iter()
+        .map(|token| {
+            (
+                token.get_val(),
+                format!("{:?}", token.get_token_type()),
+                token.variable_name(),
+            )
+        })
+        .collect();
+    // Variable tokens report the name of their variable directly
+    assert_eq!(
+        summary,
+        vec![
+            ("TIMESTAMP", "Timestamp(0)".to_string(), None),
+            (" ", "StaticText".to_string(), None),
+            ("user_bob", "Variable(1)".to_string(), Some("user")),
+            (" took  ", "StaticText".to_string(), None),
+            ("42", "Variable(0)".to_string(), Some("int")),
+            ("\tms\n", "StaticTextWithEndLine".to_string(), None),
+        ]
+    );
+
+    // Whitespace in static text can be told apart from words, while the text ending the line
+    // keeps its type
+    lexer.set_classify_whitespace(true);
+    let tokens = lex_all(&mut lexer, "TIMESTAMP user_bob took  42\tms \n")?;
+    let summary: Vec<(&str, String)> = tokens
+        .iter()
+        .map(|token| (token.get_val(), format!("{:?}", token.get_token_type())))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("TIMESTAMP", "Timestamp(0)".to_string()),
+            (" ", "Whitespace".to_string()),
+            ("user_bob", "Variable(1)".to_string()),
+            (" ", "Whitespace".to_string()),
+            ("took", "StaticText".to_string()),
+            ("  ", "Whitespace".to_string()),
+            ("42", "Variable(0)".to_string()),
+            ("\t", "Whitespace".to_string()),
+            ("ms", "StaticText".to_string()),
+            (" \n", "StaticTextWithEndLine".to_string()),
+        ]
+    );
+
+    Ok(())
+}
@@ -4,3 +4,15 @@ extern crate log_surgeon;
 fn test_log_surgeon_version() {
     assert_eq!(log_surgeon::version(), "0.0.1");
 }
+
+#[test]
+fn test_determinize_from_outside_the_crate() -> log_surgeon::error_handling::Result<()> {
+    let nfa = log_surgeon::nfa::NFA::from_regex(r"a+")?;
+    let (dfa, state_mapping) = nfa.determinize();
+    let root: log_surgeon::dfa::State = dfa.get_root();
+    let next_state = dfa.get_next_state(root.clone(), b'a').unwrap();
+    assert_eq!(dfa.is_accept_state(next_state.clone()), Some(0));
+    assert!(state_mapping[&next_state].contains(&nfa.get_accept()));
+    assert_eq!(dfa.is_accept_state(root), None);
+    Ok(())
+}
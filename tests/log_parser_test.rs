@@ -0,0 +1,1637 @@
+use log_surgeon::error_handling::{Error, LimitKind, Result};
+use log_surgeon::lexer::{BufferedFileStream, BytePolicy, Lexer, StringStream, TokenType};
+use log_surgeon::log_parser::{LogEventDto, LogParser, ParseProgress, ParseStats, TokenAnnotation};
+use log_surgeon::parser::SchemaConfig;
+
+use std::sync::Arc;
+
+fn get_example_path(file_name: &str) -> std::path::PathBuf {
+    let project_root = env!("CARGO_MANIFEST_DIR");
+    std::path::Path::new(project_root)
+        .join("examples")
+        .join(file_name)
+}
+
+fn write_temp_log(file_name: &str, content: &str) -> Result<std::path::PathBuf> {
+    let log_path = std::env::temp_dir().join(format!("log_surgeon_test_{}", file_name));
+    std::fs::write(&log_path, content).map_err(Error::IOError)?;
+    Ok(log_path)
+}
+
+#[test]
+fn test_log_event_to_json() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let log_path = get_example_path("logs").join("simple.log");
+
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+
+    // The first event has no timestamp and no variables
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    let json: serde_json::Value = serde_json::from_str(&log_event.to_json()).unwrap();
+    assert!(json["timestamp"].is_null());
+    assert!(json["variables"].as_object().unwrap().is_empty());
+    assert_eq!(json["message"], "This log event doesn't have a timestamp\n");
+
+    // The second event spans two lines with a single `int` variable
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    let json: serde_json::Value = serde_json::from_str(&log_event.to_json()).unwrap();
+    assert_eq!(json["timestamp"], "TIMESTAMP");
+    assert_eq!(json["line_range"], serde_json::json!([2, 3]));
+    let variables = json["variables"].as_object().unwrap();
+    assert_eq!(variables.len(), 1);
+    assert_eq!(variables["int"], serde_json::json!(["3190"]));
+    assert!(json["message"]
+        .as_str()
+        .unwrap()
+        .starts_with(" Id: 3190; This is a\n"));
+
+    // Static text should never show up as a variable
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    let json: serde_json::Value = serde_json::from_str(&log_event.to_json()).unwrap();
+    let variables = json["variables"].as_object().unwrap();
+    assert_eq!(variables.keys().collect::<Vec<_>>(), vec!["int"]);
+    assert_eq!(variables["int"], serde_json::json!(["0", "1000"]));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_all() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let log_path = get_example_path("logs").join("simple.log");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+
+    let mut log_parser = LogParser::new(parsed_schema.clone())?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let mut expected_events = Vec::new();
+    while let Some(log_event) = log_parser.parse_next_log_event()? {
+        expected_events.push((log_event.get_line_range(), log_event.raw_text()));
+    }
+
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let events: Vec<((usize, usize), String)> = log_parser
+        .parse_all()?
+        .iter()
+        .map(|log_event| (log_event.get_line_range(), log_event.raw_text()))
+        .collect();
+    assert!(events.len() > 1);
+    assert_eq!(events, expected_events);
+
+    // The input is exhausted afterwards
+    assert!(log_parser.parse_all()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_with_built_schema() -> Result<()> {
+    let schema_config = SchemaConfig::builder()
+        .add_timestamp(r"\d{2}:\d{2}:\d{2}")
+        .add_variable("int", r"\d+")
+        .add_variable("user", r"user_[a-z]+")
+        .add_delimiter(' ')
+        .build()?;
+    let mut log_parser = LogParser::new(Arc::new(schema_config))?;
+    log_parser.set_input_stream(Box::new(StringStream::new(
+        "10:00:01 user_alice logged in 3 times\n",
+    )))?;
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.timestamp_raw(), Some("10:00:01"));
+    let variables: Vec<(&str, usize)> = log_event
+        .get_log_message_tokens()
+        .iter()
+        .filter_map(|token| match token.get_token_type() {
+            TokenType::Variable(schema_id) => Some((token.get_val(), schema_id)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(variables, vec![("user_alice", 1), ("3", 0)]);
+    // The debug output names the matched variables
+    let debug_output = format!("{:?}", log_event);
+    assert!(debug_output.contains("\"user_alice\" (user)"));
+    assert!(debug_output.contains("\"3\" (int)"));
+    assert!(log_parser.parse_next_log_event()?.is_none());
+
+    Ok(())
+}
+
+// Reader yielding one line per read, which keeps track of the lines not read yet
+struct LineReader {
+    lines: std::rc::Rc<std::cell::RefCell<std::collections::VecDeque<String>>>,
+}
+
+impl std::io::Read for LineReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some(line) = self.lines.borrow_mut().pop_front() else {
+            return Ok(0);
+        };
+        buf[..line.len()].copy_from_slice(line.as_bytes());
+        Ok(line.len())
+    }
+}
+
+#[test]
+fn test_parse_from_reader() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '\d{2}:\d{2}:\d{2}'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let lines = std::rc::Rc::new(std::cell::RefCell::new(
+        [
+            "10:00:01 start 1\n",
+            "\tcontinued\n",
+            "10:00:02 next 2\n",
+            "10:00:03 more 3\n",
+            "10:00:04 last 4\n",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect(),
+    ));
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_reader(std::io::BufReader::new(LineReader {
+        lines: lines.clone(),
+    }))?;
+
+    // The input is streamed: the first event is returned before the whole input is read
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.raw_text(), "10:00:01 start 1\n\tcontinued\n");
+    assert!(false == lines.borrow().is_empty());
+
+    let events: Vec<String> = log_parser
+        .parse_all()?
+        .iter()
+        .map(|log_event| log_event.raw_text())
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            "10:00:02 next 2\n",
+            "10:00:03 more 3\n",
+            "10:00:04 last 4\n"
+        ]
+    );
+    assert!(lines.borrow().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_in_parallel_with_shared_schema() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+
+    let mut handles = Vec::new();
+    for _ in 0..2 {
+        let schema_config = parsed_schema.clone();
+        handles.push(std::thread::spawn(move || -> Result<usize> {
+            let log_path = get_example_path("logs").join("simple.log");
+            let mut log_parser = LogParser::new(schema_config)?;
+            log_parser.set_input_file(log_path.to_str().unwrap())?;
+            let mut num_events = 0usize;
+            while log_parser.parse_next_log_event()?.is_some() {
+                num_events += 1;
+            }
+            Ok(num_events)
+        }));
+    }
+
+    for handle in handles {
+        assert_eq!(handle.join().unwrap()?, 6);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_with_line_bytes_limit() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+
+    let long_line = format!("TIMESTAMP {} 2\n", "x".repeat(1000));
+    let log_path = write_temp_log(
+        "line_bytes_limit.log",
+        format!("TIMESTAMP short 1\n{}TIMESTAMP ok 3\n", long_line).as_str(),
+    )?;
+
+    let mut log_parser = LogParser::with_limits(parsed_schema, 64, 32)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.get_line_range(), (1, 1));
+
+    // The long line trips the limit, the caller can skip it and keep parsing
+    let result = log_parser.parse_next_log_event();
+    assert!(matches!(
+        result,
+        Err(Error::LimitExceeded {
+            kind: LimitKind::BytesPerLine,
+            limit: 32
+        })
+    ));
+
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.get_line_range(), (3, 3));
+    assert_eq!(
+        log_event.get_timestamp_token().unwrap().get_byte_offset(),
+        18 + long_line.len()
+    );
+    assert!(log_parser.parse_next_log_event()?.is_none());
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_keep_delimiters() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+    let log_path = write_temp_log("keep_delimiters.log", "a, b\n")?;
+
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    for (keep_delimiters, expected_vals) in
+        [(false, vec!["a, b\n"]), (true, vec!["a", ", ", "b", "\n"])]
+    {
+        log_parser.keep_delimiters(keep_delimiters);
+        log_parser.set_input_file(log_path.to_str().unwrap())?;
+        let log_event = log_parser.parse_next_log_event()?.unwrap();
+        let tokens = log_event.get_log_message_tokens();
+        let vals: Vec<&str> = tokens.iter().map(|token| token.get_val()).collect();
+        assert_eq!(vals, expected_vals);
+        assert_eq!(log_event.raw_text(), "a, b\n");
+
+        // Every token keeps its own position
+        let mut byte_offset = 0;
+        for token in tokens {
+            assert_eq!(token.get_line_num(), 1);
+            assert_eq!(token.get_byte_offset(), byte_offset);
+            byte_offset += token.get_val().len();
+        }
+        assert!(matches!(
+            tokens.last().unwrap().get_token_type(),
+            TokenType::StaticTextWithEndLine
+        ));
+        assert!(log_parser.parse_next_log_event()?.is_none());
+    }
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_collapse_delimiters() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.keep_delimiters(true);
+
+    for num_spaces in [1, 4, 12] {
+        let log = format!("a{}b\n", " ".repeat(num_spaces));
+        let log_path = write_temp_log("collapse_delimiters.log", log.as_str())?;
+        for (collapse_delimiters, expected_num_delimiter_tokens) in [(true, 1), (false, num_spaces)]
+        {
+            log_parser.collapse_delimiters(collapse_delimiters);
+            log_parser.set_input_file(log_path.to_str().unwrap())?;
+            let log_event = log_parser.parse_next_log_event()?.unwrap();
+            let vals: Vec<&str> = log_event
+                .get_log_message_tokens()
+                .iter()
+                .map(|token| token.get_val())
+                .collect();
+            let content_vals: Vec<&str> = vals
+                .iter()
+                .copied()
+                .filter(|val| false == val.trim().is_empty())
+                .collect();
+            assert_eq!(content_vals, vec!["a", "b"]);
+            assert_eq!(
+                vals.iter().filter(|val| val.starts_with(' ')).count(),
+                expected_num_delimiter_tokens
+            );
+            assert_eq!(log_event.raw_text(), log);
+        }
+        std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    }
+
+    // Without collapsing, the blank field between adjacent delimiters is kept
+    let log_path = write_temp_log("collapse_delimiters_blank.log", "a, ,b\n")?;
+    for (collapse_delimiters, expected_vals) in [
+        (true, vec!["a", ", ,", "b", "\n"]),
+        (false, vec!["a", ",", " ", ",", "b", "\n"]),
+    ] {
+        log_parser.collapse_delimiters(collapse_delimiters);
+        log_parser.set_input_file(log_path.to_str().unwrap())?;
+        let log_event = log_parser.parse_next_log_event()?.unwrap();
+        let vals: Vec<&str> = log_event
+            .get_log_message_tokens()
+            .iter()
+            .map(|token| token.get_val())
+            .collect();
+        assert_eq!(vals, expected_vals);
+    }
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_require_timestamp() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let schema_content = std::fs::read_to_string(schema_path).map_err(Error::IOError)?;
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        (schema_content + "\nrequire_timestamp: true\n").as_str(),
+    )?);
+    let log_path = write_temp_log(
+        "require_timestamp.log",
+        "no timestamp here\n\
+         TIMESTAMP a 1\n\
+         \tcontinued\n\
+         TIMESTAMP b 2\n\
+         broken line\n\
+         TIMESTAMP c 3\n",
+    )?;
+
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+
+    // The first line lacks a timestamp and isn't a continuation
+    let result = log_parser.parse_next_log_event();
+    assert!(matches!(result, Err(Error::MissingTimestamp { line: 1 })));
+
+    // Indented lines are continuations of the previous event
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.get_line_range(), (2, 3));
+
+    // The offending line is dropped while the previous event is kept
+    let result = log_parser.parse_next_log_event();
+    assert!(matches!(result, Err(Error::MissingTimestamp { line: 5 })));
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.raw_text(), "TIMESTAMP b 2\n");
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.get_line_range(), (6, 6));
+    assert!(log_parser.parse_next_log_event()?.is_none());
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_max_event_bytes() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+
+    // A giant single line of variables, followed by a regular event
+    let giant_line = format!(
+        "TIMESTAMP {}\n",
+        (0..2000)
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>()
+            .join(" ")
+    );
+    let log_path = write_temp_log(
+        "max_event_bytes.log",
+        format!("{}TIMESTAMP next 1\n", giant_line).as_str(),
+    )?;
+
+    let max_event_bytes = 64;
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_max_event_bytes(max_event_bytes);
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+
+    let mut events = Vec::new();
+    while let Some(log_event) = log_parser.parse_next_log_event()? {
+        events.push(log_event);
+    }
+    let last_event = events.pop().unwrap();
+    assert_eq!(last_event.raw_text(), "TIMESTAMP next 1\n");
+
+    // The giant line is split into multiple events at token boundaries
+    assert!(events.len() > giant_line.len() / max_event_bytes);
+    assert!(events[0].get_timestamp_token().is_some());
+    for log_event in &events {
+        assert!(log_event.raw_text().len() <= max_event_bytes);
+        assert_eq!(log_event.get_line_range(), (1, 1));
+    }
+    for log_event in &events[1..] {
+        assert!(log_event.get_timestamp_token().is_none());
+    }
+    // Every part but the last one was cut by the limit
+    let (last_part, cut_parts) = events.split_last().unwrap();
+    assert!(cut_parts.iter().all(|log_event| log_event.is_truncated()));
+    assert_eq!(last_part.is_truncated(), false);
+    assert_eq!(last_event.is_truncated(), false);
+    let raw_text: String = events
+        .iter()
+        .map(|log_event| log_event.raw_text())
+        .collect();
+    assert_eq!(raw_text, giant_line);
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_annotated_tokens() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  ip: '\d+\.\d+\.\d+\.\d+'
+"#,
+    )?);
+    let log_path = write_temp_log(
+        "annotated_tokens.log",
+        "TIMESTAMP connection from 10.0.0.1 closed\n",
+    )?;
+
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+
+    let annotations: Vec<(&str, Option<&str>, std::ops::Range<usize>)> = log_event
+        .annotated_tokens()
+        .iter()
+        .map(|annotation: &TokenAnnotation| {
+            (
+                annotation.get_text(),
+                annotation.get_variable_name(),
+                annotation.get_byte_range(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        annotations,
+        vec![
+            ("TIMESTAMP", None, 0..9),
+            (" connection from ", None, 9..26),
+            ("10.0.0.1", Some("ip"), 26..34),
+            (" closed\n", None, 34..42),
+        ]
+    );
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_redact() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  int: '\d+'
+  ipv4: '\d+\.\d+\.\d+\.\d+'
+"#,
+    )?);
+    let content = "TIMESTAMP 3 connections from 10.0.0.1 and 192.168.1.20\n";
+    let log_path = write_temp_log("redact.log", content)?;
+
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+
+    let edits = log_event.redact("ipv4", "<ip>");
+    assert_eq!(
+        edits,
+        vec![(29..37, "<ip>".to_string()), (42..54, "<ip>".to_string())]
+    );
+
+    // Splicing the edits back to front leaves the earlier ranges valid
+    let mut redacted = content.to_string();
+    for (range, replacement) in edits.iter().rev() {
+        redacted.replace_range(range.clone(), replacement);
+    }
+    assert_eq!(redacted, "TIMESTAMP 3 connections from <ip> and <ip>\n");
+    assert!(log_event.redact("missing", "<x>").is_empty());
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_parse_without_trailing_newline() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '\d{4}\-\d{2}\-\d{2}'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let mut log_parser = LogParser::new(parsed_schema)?;
+
+    let log_path = write_temp_log("no_trailing_newline.log", "2024-01-01 hello")?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.get_line_range(), (1, 1));
+    assert_eq!(log_event.raw_text(), "2024-01-01 hello");
+    assert_eq!(
+        log_event
+            .annotated_tokens()
+            .last()
+            .unwrap()
+            .get_byte_range(),
+        10..16
+    );
+    assert!(log_parser.parse_next_log_event()?.is_none());
+
+    // A multi-line event ending with a variable right at the end of the file
+    let content = "2024-01-01 a\n2024-01-02 b\nc 42";
+    std::fs::write(&log_path, content).map_err(Error::IOError)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.raw_text(), "2024-01-01 a\n");
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.get_line_range(), (2, 3));
+    assert_eq!(log_event.raw_text(), "2024-01-02 b\nc 42");
+    let last_annotation = log_event.annotated_tokens().pop().unwrap();
+    assert_eq!(last_annotation.get_variable_name(), Some("int"));
+    assert_eq!(last_annotation.get_byte_range(), 28..30);
+    assert_eq!(last_annotation.get_byte_range().end, content.len());
+    assert!(log_parser.parse_next_log_event()?.is_none());
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_timestamp_fields() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+  - '(?P<year>\d{4})\-(?P<month>\d{2})\-(?P<day>\d{2})T\d{2}:\d{2}:\d{2}'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let log_path = write_temp_log(
+        "timestamp_fields.log",
+        "no timestamp\n2015-01-31T15:50:45 started\nTIMESTAMP stopped\n",
+    )?;
+
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.timestamp_fields(), None);
+
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    let fields = log_event.timestamp_fields().unwrap();
+    assert_eq!(fields.len(), 3);
+    assert_eq!(fields["year"], "2015");
+    assert_eq!(fields["month"], "01");
+    assert_eq!(fields["day"], "31");
+
+    // A timestamp schema without named groups has no fields
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert!(log_event.timestamp_fields().unwrap().is_empty());
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_timestamp_raw() -> Result<()> {
+    let schema_path = get_example_path("schema.yaml");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+    let log = "no timestamp\n2015-01-31T15:50:45,392 INFO started\n";
+    let log_path = write_temp_log("timestamp_raw.log", log)?;
+
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.timestamp_raw(), None);
+
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    let timestamp_raw = log_event.timestamp_raw().unwrap();
+    assert_eq!(timestamp_raw, "2015-01-31T15:50:45,392");
+    let byte_offset = log_event.get_timestamp_token().unwrap().get_byte_offset();
+    assert_eq!(
+        &log[byte_offset..byte_offset + timestamp_raw.len()],
+        timestamp_raw
+    );
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_to_clp_ir() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  ip: '\d+\.\d+\.\d+\.\d+'
+  int: '\d+'
+"#,
+    )?);
+    let log_path = write_temp_log(
+        "to_clp_ir.log",
+        "TIMESTAMP user 10.0.0.1 failed\nTIMESTAMP 3 tries \\ \x11 left\n",
+    )?;
+
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+
+    let clp_ir = log_parser.parse_next_log_event()?.unwrap().to_clp_ir();
+    assert_eq!(clp_ir.get_logtype(), " user \x11 failed\n");
+    assert_eq!(clp_ir.get_variables(), ["10.0.0.1"]);
+
+    // Placeholders and escape characters in static text are escaped
+    let clp_ir = log_parser.parse_next_log_event()?.unwrap().to_clp_ir();
+    assert_eq!(clp_ir.get_logtype(), " \x11 tries \\\\ \\\x11 left\n");
+    assert_eq!(clp_ir.get_variables(), ["3"]);
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_log_event_dto_round_trip() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  ip: '\d+\.\d+\.\d+\.\d+'
+  int: '\d+'
+"#,
+    )?);
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_stream(Box::new(StringStream::new(
+        "TIMESTAMP user 10.0.0.1 failed 3 times\n",
+    )))?;
+    let dto = log_parser.parse_next_log_event()?.unwrap().to_dto();
+    assert_eq!(dto.line_range, (1, 1));
+    assert_eq!(dto.get_timestamp_token().unwrap().text, "TIMESTAMP");
+    let variables: Vec<(&str, Option<&str>)> = dto
+        .tokens
+        .iter()
+        .map(|token| (token.text.as_str(), token.variable_name.as_deref()))
+        .collect();
+    assert_eq!(
+        variables,
+        vec![
+            ("TIMESTAMP", None),
+            (" user ", None),
+            ("10.0.0.1", Some("ip")),
+            (" failed ", None),
+            ("3", Some("int")),
+            (" times\n", None),
+        ]
+    );
+
+    let json = serde_json::to_string(&dto).unwrap();
+    let mut deserialized: LogEventDto = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, dto);
+
+    // A DTO from an untrusted source may point past its tokens
+    deserialized.timestamp_idx = Some(deserialized.tokens.len());
+    assert!(deserialized.get_timestamp_token().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_line_separator() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+line_separator: "\0"
+variables:
+  int: '\d+'
+"#,
+    )?);
+    assert!(parsed_schema.has_delimiter('\0'));
+    assert_eq!(parsed_schema.has_delimiter('\n'), false);
+
+    let log_path = write_temp_log(
+        "line_separator.log",
+        "TIMESTAMP a 1\0 continued 2\0TIMESTAMP multi\nline 3\0",
+    )?;
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.raw_text(), "TIMESTAMP a 1\0 continued 2\0");
+    assert_eq!(log_event.get_line_range(), (1, 2));
+    assert!(matches!(
+        log_event
+            .get_log_message_tokens()
+            .last()
+            .unwrap()
+            .get_token_type(),
+        TokenType::StaticTextWithEndLine
+    ));
+
+    // Newlines are plain static text
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(log_event.raw_text(), "TIMESTAMP multi\nline 3\0");
+    assert_eq!(log_event.get_line_range(), (3, 3));
+    let annotations = log_event.annotated_tokens();
+    assert_eq!(annotations[2].get_text(), "3");
+    assert_eq!(annotations[2].get_variable_name(), Some("int"));
+    assert!(log_parser.parse_next_log_event()?.is_none());
+
+    // Lines are counted by separator when parsing a line range as well
+    let log_events = log_parser.parse_line_range(3, 3)?;
+    assert_eq!(log_events.len(), 1);
+    assert_eq!(log_events[0].raw_text(), "TIMESTAMP multi\nline 3\0");
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_parse_crlf_file() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TS'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    let content = "TS 42\r\nTS 7\r\n";
+    let log_path = write_temp_log("crlf.log", content)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let log_events = log_parser.parse_all()?;
+    assert_eq!(log_events.len(), 2);
+    // The `\r` doesn't stick to the variable ending the line, but still takes space in the file
+    let annotations: Vec<(&str, Option<&str>, std::ops::Range<usize>)> = log_events
+        .iter()
+        .flat_map(|log_event| log_event.annotated_tokens())
+        .map(|annotation| {
+            (
+                annotation.get_text(),
+                annotation.get_variable_name(),
+                annotation.get_byte_range(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        annotations,
+        vec![
+            ("TS", None, 0..2),
+            (" ", None, 2..3),
+            ("42", Some("int"), 3..5),
+            ("\n", None, 5..6),
+            ("TS", None, 7..9),
+            (" ", None, 9..10),
+            ("7", Some("int"), 10..11),
+            ("\n", None, 11..12),
+        ]
+    );
+    assert_eq!(
+        log_events[1]
+            .get_timestamp_token()
+            .unwrap()
+            .get_byte_offset(),
+        7
+    );
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_progress_callback() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let mut content = String::new();
+    for i in 0..20 {
+        content += format!("TIMESTAMP event {}\n  continuation line\n", i).as_str();
+    }
+    let log_path = write_temp_log("progress_callback.log", content.as_str())?;
+
+    let progress_reports = std::rc::Rc::new(std::cell::RefCell::new(Vec::<ParseProgress>::new()));
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_progress_interval_lines(3);
+    let reports = progress_reports.clone();
+    log_parser.set_progress_callback(Box::new(move |progress| {
+        reports.borrow_mut().push(progress)
+    }));
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let mut num_events = 0;
+    while log_parser.parse_next_log_event()?.is_some() {
+        num_events += 1;
+    }
+    assert_eq!(num_events, 20);
+    // Reaching the end of the input doesn't invoke the callback
+    assert_eq!(log_parser.parse_next_log_event()?.is_none(), true);
+
+    let reports = progress_reports.borrow().clone();
+    assert_eq!(reports.len(), 40 / 3);
+    for (i, progress) in reports.iter().enumerate() {
+        assert_eq!(progress.line_num, (i + 1) * 3);
+        let expected_bytes_read: usize = content
+            .split_inclusive('\n')
+            .take(progress.line_num)
+            .map(|line| line.len())
+            .sum();
+        assert_eq!(progress.bytes_read, expected_bytes_read);
+    }
+    for window in reports.windows(2) {
+        assert!(window[0].line_num < window[1].line_num);
+        assert!(window[0].num_events_emitted <= window[1].num_events_emitted);
+    }
+
+    // Once cleared, the callback is no longer invoked
+    log_parser.clear_progress_callback();
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    while log_parser.parse_next_log_event()?.is_some() {}
+    assert_eq!(progress_reports.borrow().len(), 40 / 3);
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_parse_line_range() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '\d{4}-\d{2}-\d{2}'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    // Every third event has two continuation lines
+    let mut content = String::new();
+    let mut event_line_ranges = Vec::new();
+    let mut line_num = 1;
+    for i in 0..30 {
+        content += format!("2024-01-01 event {}\n", i).as_str();
+        let mut last_line = line_num;
+        if 0 == i % 3 {
+            content += "  continuation\n  continuation\n";
+            last_line += 2;
+        }
+        event_line_ranges.push((line_num, last_line));
+        line_num = last_line + 1;
+    }
+    let log_path = write_temp_log("parse_line_range.log", content.as_str())?;
+
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+
+    // Line 12 is in the middle of an event, which is parsed from its start
+    let (start, end) = (12, 20);
+    assert_eq!(event_line_ranges[6], (11, 13));
+    let line_ranges: Vec<(usize, usize)> = log_parser
+        .parse_line_range(start, end)?
+        .iter()
+        .map(|log_event| log_event.get_line_range())
+        .collect();
+    let expected_line_ranges: Vec<(usize, usize)> = event_line_ranges
+        .iter()
+        .filter(|(first, last)| *last >= start && *first <= end)
+        .copied()
+        .collect();
+    assert_eq!(line_ranges, expected_line_ranges);
+    assert_eq!(line_ranges.first(), Some(&(11, 13)));
+
+    let log_events = log_parser.parse_line_range(start, end)?;
+    let first_event = log_events.first().unwrap();
+    assert_eq!(
+        first_event.raw_text(),
+        "2024-01-01 event 6\n  continuation\n  continuation\n"
+    );
+    assert_eq!(
+        first_event.get_timestamp_token().unwrap().get_byte_offset(),
+        content.find("2024-01-01 event 6").unwrap()
+    );
+
+    // The input is reset to the start of the file afterwards
+    assert_eq!(
+        log_parser.parse_next_log_event()?.unwrap().get_line_range(),
+        (1, 3)
+    );
+
+    // Ranges past the end of the file are empty
+    assert_eq!(log_parser.parse_line_range(1000, 1010)?.len(), 0);
+
+    // Streams not backed by a file set with `set_input_file` can't seek
+    log_parser.set_input_stream(Box::new(BufferedFileStream::new(
+        log_path.to_str().unwrap(),
+    )?))?;
+    assert!(matches!(
+        log_parser.parse_line_range(start, end),
+        Err(Error::UnseekableInputStream)
+    ));
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_for_each_token_of_event() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+    let log_path = get_example_path("logs").join("simple.log");
+
+    let describe = |token: &log_surgeon::lexer::Token| {
+        format!(
+            "{:?} {} {}",
+            token.get_token_type(),
+            token.get_line_num(),
+            token.get_val()
+        )
+    };
+
+    // Runs both modes with and without an event size cap; the streamed tokens must match the
+    // buffered log events exactly, event by event
+    for max_event_bytes in [None, Some(16)] {
+        let mut buffered_parser = LogParser::new(parsed_schema.clone())?;
+        let mut streaming_parser = LogParser::new(parsed_schema.clone())?;
+        if let Some(max_event_bytes) = max_event_bytes {
+            buffered_parser.set_max_event_bytes(max_event_bytes);
+            streaming_parser.set_max_event_bytes(max_event_bytes);
+        }
+        buffered_parser.set_input_file(log_path.to_str().unwrap())?;
+        streaming_parser.set_input_file(log_path.to_str().unwrap())?;
+
+        let mut buffered_events = Vec::new();
+        while let Some(log_event) = buffered_parser.parse_next_log_event()? {
+            let mut tokens = Vec::new();
+            tokens.extend(log_event.get_timestamp_token().map(describe));
+            tokens.extend(log_event.get_log_message_tokens().iter().map(describe));
+            buffered_events.push(tokens);
+        }
+
+        let mut streamed_events = Vec::new();
+        loop {
+            let mut tokens = Vec::new();
+            if false
+                == streaming_parser
+                    .for_each_token_of_event(|token| tokens.push(describe(&token)))?
+            {
+                assert!(tokens.is_empty());
+                break;
+            }
+            streamed_events.push(tokens);
+        }
+
+        assert!(buffered_events.len() > 1);
+        assert_eq!(streamed_events, buffered_events);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_skip_blank_lines() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " \t\n"
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let input = "\nTIMESTAMP first 1\n continued\n\n  more 2\n\n \t\nTIMESTAMP second 3\n\n";
+    let log_path = write_temp_log("skip_blank_lines.log", input)?;
+    let mut log_parser = LogParser::new(parsed_schema)?;
+
+    // By default, blank lines are part of the events
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let log_events = log_parser.parse_all()?;
+    assert_eq!(log_events.len(), 3);
+    assert_eq!(log_events[0].raw_text(), "\n");
+    assert_eq!(log_events[1].get_line_range(), (2, 7));
+    assert_eq!(log_events[2].get_line_range(), (8, 9));
+
+    log_parser.skip_blank_lines(true);
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let log_events = log_parser.parse_all()?;
+    assert_eq!(log_events.len(), 2);
+    // The blank line followed by a continuation line is kept
+    assert_eq!(
+        log_events[0].raw_text(),
+        "TIMESTAMP first 1\n continued\n\n  more 2\n"
+    );
+    assert_eq!(log_events[0].get_line_range(), (2, 5));
+    assert_eq!(log_events[1].raw_text(), "TIMESTAMP second 3\n");
+    assert_eq!(log_events[1].get_line_range(), (8, 8));
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[test]
+fn test_indented_timestamp() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '\d{4}\-\d{2}\-\d{2}'
+delimiters: " \t\n"
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let input = "2024-01-01 first 1\n  continued 2\n  \t2024-01-02 second 3\n    at 4\n";
+    let log_path = write_temp_log("indented_timestamp.log", input)?;
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+
+    let log_events = log_parser.parse_all()?;
+    assert_eq!(log_events.len(), 2);
+    assert_eq!(
+        log_events[0].raw_text(),
+        "2024-01-01 first 1\n  continued 2\n"
+    );
+    assert_eq!(log_events[0].get_line_range(), (1, 2));
+
+    // The indented timestamp starts a new event, which keeps the indentation
+    assert_eq!(
+        log_events[1].raw_text(),
+        "  \t2024-01-02 second 3\n    at 4\n"
+    );
+    assert_eq!(log_events[1].get_line_range(), (3, 4));
+    assert_eq!(
+        log_events[1].get_timestamp_token().unwrap().get_val(),
+        "2024-01-02"
+    );
+    assert_eq!(
+        log_events[1].get_log_message_tokens()[0].get_val(),
+        " second "
+    );
+
+    // Parsing a line range finds the indented event start as well
+    let log_events = log_parser.parse_line_range(4, 4)?;
+    assert_eq!(log_events.len(), 1);
+    assert_eq!(log_events[0].get_line_range(), (3, 4));
+
+    std::fs::remove_file(&log_path).map_err(Error::IOError)?;
+    Ok(())
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_gzip_input() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+    let log_path = get_example_path("logs/simple.log");
+    let gzip_log_path = get_example_path("logs/simple.log.gz");
+
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let log_events = log_parser.parse_all()?;
+    log_parser.set_input_file(gzip_log_path.to_str().unwrap())?;
+    let gzip_log_events = log_parser.parse_all()?;
+
+    assert!(false == log_events.is_empty());
+    assert_eq!(log_events.len(), gzip_log_events.len());
+    for (log_event, gzip_log_event) in log_events.iter().zip(gzip_log_events.iter()) {
+        assert_eq!(log_event.raw_text(), gzip_log_event.raw_text());
+        assert_eq!(log_event.get_line_range(), gzip_log_event.get_line_range());
+        assert_eq!(
+            log_event.annotated_tokens(),
+            gzip_log_event.annotated_tokens()
+        );
+    }
+
+    // Compressed input can't be seeked by line
+    assert!(matches!(
+        log_parser.parse_line_range(1, 2),
+        Err(Error::UnseekableInputStream)
+    ));
+    Ok(())
+}
+
+#[cfg(not(feature = "gzip"))]
+#[test]
+fn test_gzip_input_without_feature() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+    let gzip_log_path = get_example_path("logs/simple.log.gz");
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    assert!(matches!(
+        log_parser.set_input_file(gzip_log_path.to_str().unwrap()),
+        Err(Error::IOError(e)) if std::io::ErrorKind::Unsupported == e.kind()
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_parse_stats() -> Result<()> {
+    let schema_path = get_example_path("schema_simple.yaml");
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_file(
+        schema_path.to_str().unwrap(),
+    )?);
+    let log_path = get_example_path("logs/simple.log");
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    assert_eq!(log_parser.stats().var_matches, vec![0, 0]);
+
+    let log_events = log_parser.parse_all()?;
+    let stats = log_parser.stats().clone();
+    // `int` matches `3190`, `1000` and four `0`s, while `with_delimiter` matches `a a` twice
+    assert_eq!(stats.var_matches, vec![6, 2]);
+    assert_eq!(stats.timestamp_matches, 5);
+
+    // Every byte of the input is either matched or counted as static text
+    let input = std::fs::read_to_string(&log_path).map_err(Error::IOError)?;
+    let matched_bytes: usize = log_events
+        .iter()
+        .flat_map(|log_event| log_event.annotated_tokens())
+        .filter(|annotation| annotation.get_variable_name().is_some())
+        .map(|annotation| annotation.get_text().len())
+        .sum();
+    assert_eq!(
+        matched_bytes + stats.timestamp_matches * "TIMESTAMP".len() + stats.static_text_bytes,
+        input.len()
+    );
+
+    // Streaming the events gathers the same statistics, which are reset with the input
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    assert_eq!(
+        log_parser.stats(),
+        &ParseStats {
+            var_matches: vec![0, 0],
+            ..Default::default()
+        }
+    );
+    while log_parser.for_each_token_of_event(|_| {})? {}
+    assert_eq!(log_parser.stats(), &stats);
+
+    Ok(())
+}
+
+#[test]
+fn test_from_streams() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '(?P<hour>\d{2}):(?P<minute>\d{2}):(?P<second>\d{2})'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let api_log = "starting api\n\
+                   10:00:01 api a\n\
+                   10:00:05 api b\n\
+                   \tcontinued b\n\
+                   10:00:09 api c\n";
+    let db_log = "09:59:59 db a\n\
+                  10:00:03 db b\n\
+                  10:00:05 db c\n";
+    let mut merged_parser = LogParser::from_streams(
+        vec![
+            Box::new(StringStream::new(api_log)),
+            Box::new(StringStream::new(db_log)),
+        ],
+        parsed_schema,
+    )?;
+    let merged: Vec<String> = merged_parser
+        .parse_all()?
+        .iter()
+        .map(|log_event| log_event.raw_text())
+        .collect();
+    // The event without a timestamp at the start of a stream comes first, continuation lines stay
+    // in their event, and events with equal timestamps are in stream order
+    assert_eq!(
+        merged,
+        vec![
+            "starting api\n",
+            "09:59:59 db a\n",
+            "10:00:01 api a\n",
+            "10:00:03 db b\n",
+            "10:00:05 api b\n\tcontinued b\n",
+            "10:00:05 db c\n",
+            "10:00:09 api c\n",
+        ]
+    );
+    assert!(merged_parser.parse_next_log_event()?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_from_streams_with_different_timestamp_fields() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '(?P<month>\d{2})/(?P<day>\d{2}) (?P<hour>\d{2}):(?P<minute>\d{2})'
+  - '(?P<month>[A-Z][a-z]{2}) (?P<day>\d{2}) (?P<hour>\d{2}):(?P<minute>\d{2})'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let numeric_month_log = "01/15 10:00 api a\n";
+    let named_month_log = "Jan 02 09:00 db a\n";
+    let mut merged_parser = LogParser::from_streams(
+        vec![
+            Box::new(StringStream::new(numeric_month_log)),
+            Box::new(StringStream::new(named_month_log)),
+        ],
+        parsed_schema,
+    )?;
+    let merged: Vec<String> = merged_parser
+        .parse_all()?
+        .iter()
+        .map(|log_event| log_event.raw_text())
+        .collect();
+    // The month of the second stream isn't numeric, so it orders before the numeric month of the
+    // first stream, rather than its day being compared against that month
+    assert_eq!(merged, vec!["Jan 02 09:00 db a\n", "01/15 10:00 api a\n"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_token_source_ids() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '(?P<hour>\d{2}):(?P<minute>\d{2}):(?P<second>\d{2})'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let mut api_stream = StringStream::new("10:00:01 api 1\n10:00:05 api 2\n\tcontinued\n");
+    api_stream.set_source_id("api");
+    let mut db_stream = StringStream::new("10:00:03 db 3\n");
+    db_stream.set_source_id("db");
+    let mut merged_parser = LogParser::from_streams(
+        vec![Box::new(api_stream), Box::new(db_stream)],
+        parsed_schema.clone(),
+    )?;
+    let log_events = merged_parser.parse_all()?;
+    assert_eq!(log_events.len(), 3);
+    for (log_event, expected_source_id) in log_events.iter().zip(["api", "db", "api"]) {
+        let timestamp_token = log_event.get_timestamp_token().unwrap();
+        assert_eq!(timestamp_token.source_id(), Some(expected_source_id));
+        for token in log_event.get_log_message_tokens() {
+            assert_eq!(token.source_id(), Some(expected_source_id));
+        }
+    }
+
+    // Streams without a label don't set one on their tokens
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_stream(Box::new(StringStream::new("10:00:01 api 1\n")))?;
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert!(log_event
+        .get_log_message_tokens()
+        .iter()
+        .all(|token| token.source_id().is_none()));
+
+    Ok(())
+}
+
+#[test]
+fn test_byte_policy() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let log_path = std::env::temp_dir().join("log_surgeon_test_byte_policy.log");
+    std::fs::write(&log_path, b"TIMESTAMP a\x80b 42\nTIMESTAMP c 7\n").map_err(Error::IOError)?;
+    let log_path = log_path.to_str().unwrap();
+
+    let mut log_parser = LogParser::new(parsed_schema.clone())?;
+    log_parser.set_byte_policy(Some(BytePolicy::Skip));
+    log_parser.set_input_file(log_path)?;
+    let log_events = log_parser.parse_all()?;
+    assert_eq!(log_events.len(), 2);
+    assert_eq!(log_events[0].raw_text(), "TIMESTAMP ab 42\n");
+    // The dropped byte still counts towards the byte offsets
+    assert_eq!(
+        log_events[1]
+            .get_timestamp_token()
+            .unwrap()
+            .get_byte_offset(),
+        19
+    );
+
+    log_parser.set_byte_policy(Some(BytePolicy::Replace));
+    log_parser.set_input_file(log_path)?;
+    let log_events = log_parser.parse_all()?;
+    assert_eq!(log_events[0].raw_text(), "TIMESTAMP a?b 42\n");
+
+    // Tokens following a dropped or replaced character on the same line report their position in
+    // the input as well
+    let utf8_log_path = write_temp_log("byte_policy_utf8.log", "TIMESTAMP \u{e9} 42 x\n")?;
+    for byte_policy in [BytePolicy::Skip, BytePolicy::Replace] {
+        log_parser.set_byte_policy(Some(byte_policy));
+        log_parser.set_input_file(utf8_log_path.to_str().unwrap())?;
+        let log_events = log_parser.parse_all()?;
+        let byte_ranges: Vec<(&str, std::ops::Range<usize>)> = log_events[0]
+            .annotated_tokens()
+            .into_iter()
+            .map(|annotation| (annotation.get_text(), annotation.get_byte_range()))
+            .collect();
+        let replacement = match byte_policy {
+            BytePolicy::Replace => "?",
+            _ => "",
+        };
+        assert_eq!(byte_ranges[1].0, format!(" {} ", replacement));
+        assert_eq!(byte_ranges[2], ("42", 13..15));
+        assert_eq!(byte_ranges[3], (" x\n", 15..18));
+    }
+    std::fs::remove_file(&utf8_log_path).map_err(Error::IOError)?;
+
+    log_parser.set_byte_policy(Some(BytePolicy::Strict));
+    log_parser.set_input_file(log_path)?;
+    assert!(matches!(
+        log_parser.parse_all(),
+        Err(Error::NoneASCIICharacters)
+    ));
+
+    // Without a policy, the invalid UTF-8 fails reading the file
+    log_parser.set_byte_policy(None);
+    log_parser.set_input_file(log_path)?;
+    assert!(matches!(log_parser.parse_all(), Err(Error::IOError(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_max_continuation_lines() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - 'TIMESTAMP'
+delimiters: " ():"
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let log_path = write_temp_log(
+        "max_continuation_lines.log",
+        "TIMESTAMP java.lang.NullPointerException\n\
+         \tat a.B.c(B.java:1)\n\
+         \tat a.B.d(B.java:2)\n\
+         \tat a.B.e(B.java:3)\n\
+         \tat a.B.f(B.java:4)\n\
+         \tat a.B.g(B.java:5)\n\
+         TIMESTAMP done\n",
+    )?;
+
+    // Without a cap, the whole stack trace belongs to the event
+    let mut log_parser = LogParser::new(parsed_schema.clone())?;
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let log_events = log_parser.parse_all()?;
+    assert_eq!(log_events.len(), 2);
+    assert_eq!(log_events[0].body_line_count(), 6);
+    assert_eq!(log_events[0].is_truncated(), false);
+    assert_eq!(log_events[1].body_line_count(), 1);
+
+    log_parser.set_max_continuation_lines(3);
+    log_parser.set_input_file(log_path.to_str().unwrap())?;
+    let log_events = log_parser.parse_all()?;
+    let summary: Vec<((usize, usize), bool, bool)> = log_events
+        .iter()
+        .map(|log_event| {
+            (
+                log_event.get_line_range(),
+                log_event.get_timestamp_token().is_some(),
+                log_event.is_truncated(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ((1, 4), true, true),
+            ((5, 6), false, false),
+            ((7, 7), true, false),
+        ]
+    );
+    assert_eq!(log_events[0].body_line_count(), 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_inherit_timestamp() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '\d{2}:\d{2}:\d{2}'
+delimiters: " ():"
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let input = "starting\n\
+                 10:00:01 java.lang.NullPointerException\n\
+                 \tat a.B.c(B.java:1)\n\
+                 \tat a.B.d(B.java:2)\n\
+                 \tat a.B.e(B.java:3)\n\
+                 10:00:02 done\n";
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_max_continuation_lines(1);
+    log_parser.inherit_timestamp(true);
+    log_parser.set_input_stream(Box::new(StringStream::new(input)))?;
+    let log_events = log_parser.parse_all()?;
+    let summary: Vec<((usize, usize), Option<&str>, Option<(&str, usize)>)> = log_events
+        .iter()
+        .map(|log_event| {
+            (
+                log_event.get_line_range(),
+                log_event.timestamp_raw(),
+                log_event
+                    .effective_timestamp()
+                    .map(|ts_token| (ts_token.get_val(), ts_token.get_line_num())),
+            )
+        })
+        .collect();
+    // The trace lines split off the first event inherit its timestamp, while the line before any
+    // timestamp has nothing to inherit
+    assert_eq!(
+        summary,
+        vec![
+            ((1, 1), None, None),
+            ((2, 3), Some("10:00:01"), Some(("10:00:01", 2))),
+            ((4, 5), None, Some(("10:00:01", 2))),
+            ((6, 6), Some("10:00:02"), Some(("10:00:02", 6))),
+        ]
+    );
+
+    // Without the option, events without a timestamp have no effective timestamp either
+    log_parser.inherit_timestamp(false);
+    log_parser.set_input_stream(Box::new(StringStream::new(input)))?;
+    let log_events = log_parser.parse_all()?;
+    assert!(log_events[2].effective_timestamp().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_disable_variable_mid_stream() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '\d{2}:\d{2}:\d{2}'
+delimiters: " ="
+variables:
+  hex: '0x[0-9a-f]+'
+  int: '\d+'
+  level: 'DEBUG'
+"#,
+    )?);
+    let input = "10:00:01 DEBUG ptr=0x1f size=42\n\
+                 10:00:02 DEBUG ptr=0x2e size=43\n\
+                 10:00:03 DEBUG ptr=0x3d size=44\n";
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_stream(Box::new(StringStream::new(input)))?;
+    let variable_names = |log_event: &log_surgeon::log_parser::LogEvent| -> Vec<String> {
+        log_event
+            .get_log_message_tokens()
+            .iter()
+            .filter_map(|token| token.variable_name())
+            .map(|name| name.to_string())
+            .collect()
+    };
+
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(variable_names(&log_event), vec!["level", "hex", "int"]);
+
+    log_parser.set_variable_enabled("hex", false)?;
+    log_parser.set_variable_enabled("level", false)?;
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(variable_names(&log_event), vec!["int"]);
+    // The text of disabled variables is static text
+    assert!(log_event
+        .get_log_message_tokens()
+        .iter()
+        .any(|token| token.get_val().contains("0x2e")
+            && matches!(token.get_token_type(), TokenType::StaticText)));
+
+    log_parser.set_variable_enabled("hex", true)?;
+    let log_event = log_parser.parse_next_log_event()?.unwrap();
+    assert_eq!(variable_names(&log_event), vec!["hex", "int"]);
+
+    assert!(matches!(
+        log_parser.set_variable_enabled("missing", false),
+        Err(Error::UnresolvedSchemaReference(name)) if "missing" == name
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_timestamp_lookahead_tokens() -> Result<()> {
+    let parsed_schema = Arc::new(SchemaConfig::parse_from_str(
+        r#"
+timestamp:
+  - '\d{4}\-\d{2}\-\d{2}'
+delimiters: " "
+variables:
+  int: '\d+'
+"#,
+    )?);
+    let input = "host1 2024-01-01 msg 1\n\
+                 continued\n\
+                 host2  2024-01-02 msg 2\n\
+                 host3 rack4 2024-01-03 msg 3\n";
+    let mut log_parser = LogParser::new(parsed_schema.clone())?;
+    log_parser.set_timestamp_lookahead_tokens(1);
+    log_parser.set_input_stream(Box::new(StringStream::new(input)))?;
+    let log_events = log_parser.parse_all()?;
+    let summary: Vec<((usize, usize), Option<&str>, String)> = log_events
+        .iter()
+        .map(|log_event| {
+            (
+                log_event.get_line_range(),
+                log_event.timestamp_raw(),
+                log_event.raw_text(),
+            )
+        })
+        .collect();
+    // The line with two tokens before its timestamp continues the previous event
+    assert_eq!(
+        summary,
+        vec![
+            (
+                (1, 2),
+                Some("2024-01-01"),
+                "host1 2024-01-01 msg 1\ncontinued\n".to_string()
+            ),
+            (
+                (3, 4),
+                Some("2024-01-02"),
+                "host2  2024-01-02 msg 2\nhost3 rack4 2024-01-03 msg 3\n".to_string()
+            ),
+        ]
+    );
+    // Like indentation, the text before the timestamp isn't part of the message
+    assert_eq!(log_events[0].get_log_message_tokens()[0].get_val(), " msg ");
+
+    let mut lexer = Lexer::new(parsed_schema.clone())?;
+    lexer.set_timestamp_lookahead_tokens(2);
+    assert!(lexer.starts_with_timestamp("host3 rack4 2024-01-03 msg 3"));
+    assert_eq!(false, lexer.starts_with_timestamp("host3 rack4 msg 3"));
+
+    // Without lookahead, no line starts with a timestamp
+    let mut log_parser = LogParser::new(parsed_schema)?;
+    log_parser.set_input_stream(Box::new(StringStream::new(input)))?;
+    let log_events = log_parser.parse_all()?;
+    assert_eq!(log_events.len(), 1);
+    assert!(log_events[0].get_timestamp_token().is_none());
+
+    Ok(())
+}